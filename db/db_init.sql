@@ -29,10 +29,23 @@ create table item
     created_at INTEGER,
     updated_at integer,
     model_name TEXT    default ''   not null,
+    size       integer default 0    not null,
+    mtime      integer default 0    not null,
+    inode      integer default 0    not null,
+    json_mtime integer default 0    not null,
+    seen_at    integer default 0    not null,
+    network_dim   integer,
+    network_alpha real,
+    recommended_weight real,
+    civitai_model_id   integer,
+    civitai_version_id integer,
     constraint item_pk_2
         unique (path, base_label)
 );
 
+create index item_civitai_version_id_index
+    on item (civitai_version_id);
+
 create table tag
 (
     name        TEXT    not null
@@ -76,3 +89,217 @@ create table tag_tag
 );
 
 
+
+create table file_hash
+(
+    path       TEXT    not null,
+    base_label TEXT    not null,
+    size       integer not null,
+    mtime      integer not null,
+    blake3     TEXT    not null,
+    sha256     TEXT    not null,
+    autov2     TEXT    not null,
+    updated_at integer,
+    constraint file_hash_pk
+        primary key (path, base_label)
+);
+
+create index file_hash_blake3_index
+    on file_hash (blake3);
+
+create index file_hash_sha256_index
+    on file_hash (sha256);
+
+create table scan_error
+(
+    id         integer not null
+        constraint scan_error_pk
+            primary key autoincrement,
+    path       TEXT    not null,
+    base_label TEXT    default '' not null,
+    stage      TEXT    not null,
+    message    TEXT    not null,
+    created_at integer
+);
+
+create index scan_error_path_index
+    on scan_error (path, base_label);
+
+create table model_header
+(
+    item         integer not null
+        constraint model_header_pk
+            primary key
+        constraint model_header_item_id_fk
+            references item
+            on update cascade on delete cascade,
+    format       TEXT    not null,
+    tensor_count integer not null,
+    param_count  integer not null,
+    dtypes       TEXT    default '{}' not null,
+    metadata     TEXT    default '{}' not null
+);
+
+create table trigger_word
+(
+    item   integer not null
+        constraint trigger_word_item_id_fk
+            references item
+            on update cascade on delete cascade,
+    word   TEXT    not null,
+    source TEXT    not null,
+    count  integer default 0 not null,
+    constraint trigger_word_pk
+        primary key (item, word, source)
+);
+
+create index trigger_word_word_index
+    on trigger_word (word);
+
+create table pickle_scan
+(
+    item           integer not null
+        constraint pickle_scan_pk
+            primary key
+        constraint pickle_scan_item_id_fk
+            references item
+            on update cascade on delete cascade,
+    safe           boolean not null,
+    globals        TEXT    default '[]' not null,
+    unsafe_globals TEXT    default '[]' not null,
+    scanned_at     integer,
+    error          TEXT
+);
+
+create table civitai_lookup
+(
+    item        integer not null
+        constraint civitai_lookup_pk
+            primary key
+        constraint civitai_lookup_item_id_fk
+            references item
+            on update cascade on delete cascade,
+    status      TEXT    not null,
+    http_status integer,
+    message     TEXT    default '' not null,
+    checked_at  integer not null
+);
+
+create index civitai_lookup_status_index
+    on civitai_lookup (status);
+
+create table civitai_info
+(
+    item       integer not null
+        constraint civitai_info_pk
+            primary key
+        constraint civitai_info_item_id_fk
+            references item
+            on update cascade on delete cascade,
+    info       TEXT    not null,
+    updated_at integer not null
+);
+
+create table gallery_image
+(
+    id              integer not null
+        constraint gallery_image_pk
+            primary key autoincrement,
+    item            integer not null
+        constraint gallery_image_item_id_fk
+            references item
+            on update cascade on delete cascade,
+    file            TEXT    not null,
+    url             TEXT    not null,
+    nsfw_level      integer default 0 not null,
+    width           integer,
+    height          integer,
+    prompt          TEXT,
+    negative_prompt TEXT,
+    sampler         TEXT,
+    cfg_scale       real,
+    steps           integer,
+    seed            integer,
+    resources       TEXT    default '[]' not null,
+    meta            TEXT    default '{}' not null
+);
+
+create index gallery_image_item_index
+    on gallery_image (item);
+
+create table model_update
+(
+    item                integer not null
+        constraint model_update_pk
+            primary key
+        constraint model_update_item_id_fk
+            references item
+            on update cascade on delete cascade,
+    model_id            integer not null,
+    version_id          integer not null,
+    version_name        TEXT    default '' not null,
+    latest_version_id   integer not null,
+    latest_version_name TEXT    default '' not null,
+    latest_published_at TEXT    default '' not null,
+    latest_base_model   TEXT    default '' not null,
+    has_update          integer default false not null,
+    checked_at          integer not null
+);
+
+create table download
+(
+    id               integer not null
+        constraint download_pk
+            primary key autoincrement,
+    source           TEXT    not null,
+    base_label       TEXT    not null,
+    subfolder        TEXT    default '' not null,
+    priority         integer default 0 not null,
+    status           TEXT    not null,
+    path             TEXT    default '' not null,
+    total_bytes      integer default 0 not null,
+    downloaded_bytes integer default 0 not null,
+    error            TEXT    default '' not null,
+    created_at       integer not null,
+    updated_at       integer not null,
+    kind             TEXT    default 'civitai' not null,
+    revision         TEXT    default '' not null,
+    file             TEXT    default '' not null
+);
+
+create index download_status_index
+    on download (status);
+
+create table hf_source
+(
+    item        integer not null
+        constraint hf_source_pk
+            primary key
+        constraint hf_source_item_id_fk
+            references item
+            on update cascade on delete cascade,
+    repo_id     TEXT    not null,
+    revision    TEXT    not null,
+    file        TEXT    not null,
+    license     TEXT    default '' not null,
+    model_card  TEXT    default '' not null,
+    updated_at  integer not null,
+    card_data   TEXT    default '' not null
+);
+
+create table model_record
+(
+    item       integer not null
+        constraint model_record_pk
+            primary key
+        constraint model_record_item_id_fk
+            references item
+            on update cascade on delete cascade,
+    provider   TEXT    not null,
+    record     TEXT    not null,
+    updated_at integer not null
+);
+
+-- Version of the last migration in db/migrations this schema matches
+insert into app_info (label, value)
+values ('schema_version', 19);
@@ -0,0 +1,4 @@
+alter table item add column size integer default 0 not null;
+alter table item add column mtime integer default 0 not null;
+alter table item add column inode integer default 0 not null;
+alter table item add column json_mtime integer default 0 not null;
@@ -0,0 +1 @@
+alter table item add column seen_at integer default 0 not null;
@@ -0,0 +1,19 @@
+create table file_hash
+(
+    path       TEXT    not null,
+    base_label TEXT    not null,
+    size       integer not null,
+    mtime      integer not null,
+    blake3     TEXT    not null,
+    sha256     TEXT    not null,
+    autov2     TEXT    not null,
+    updated_at integer,
+    constraint file_hash_pk
+        primary key (path, base_label)
+);
+
+create index file_hash_blake3_index
+    on file_hash (blake3);
+
+create index file_hash_sha256_index
+    on file_hash (sha256);
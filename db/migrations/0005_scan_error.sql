@@ -0,0 +1,14 @@
+create table scan_error
+(
+    id         integer not null
+        constraint scan_error_pk
+            primary key autoincrement,
+    path       TEXT    not null,
+    base_label TEXT    default '' not null,
+    stage      TEXT    not null,
+    message    TEXT    not null,
+    created_at integer
+);
+
+create index scan_error_path_index
+    on scan_error (path, base_label);
@@ -0,0 +1,14 @@
+create table model_header
+(
+    item         integer not null
+        constraint model_header_pk
+            primary key
+        constraint model_header_item_id_fk
+            references item
+            on update cascade on delete cascade,
+    format       TEXT    not null,
+    tensor_count integer not null,
+    param_count  integer not null,
+    dtypes       TEXT    default '{}' not null,
+    metadata     TEXT    default '{}' not null
+);
@@ -0,0 +1,18 @@
+alter table item add column network_dim integer;
+alter table item add column network_alpha real;
+
+create table trigger_word
+(
+    item   integer not null
+        constraint trigger_word_item_id_fk
+            references item
+            on update cascade on delete cascade,
+    word   TEXT    not null,
+    source TEXT    not null,
+    count  integer default 0 not null,
+    constraint trigger_word_pk
+        primary key (item, word, source)
+);
+
+create index trigger_word_word_index
+    on trigger_word (word);
@@ -0,0 +1,13 @@
+create table pickle_scan
+(
+    item           integer not null
+        constraint pickle_scan_pk
+            primary key
+        constraint pickle_scan_item_id_fk
+            references item
+            on update cascade on delete cascade,
+    safe           boolean not null,
+    globals        TEXT    default '[]' not null,
+    unsafe_globals TEXT    default '[]' not null,
+    scanned_at     integer
+);
@@ -0,0 +1,16 @@
+create table civitai_lookup
+(
+    item        integer not null
+        constraint civitai_lookup_pk
+            primary key
+        constraint civitai_lookup_item_id_fk
+            references item
+            on update cascade on delete cascade,
+    status      TEXT    not null,
+    http_status integer,
+    message     TEXT    default '' not null,
+    checked_at  integer not null
+);
+
+create index civitai_lookup_status_index
+    on civitai_lookup (status);
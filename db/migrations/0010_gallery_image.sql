@@ -0,0 +1,26 @@
+create table gallery_image
+(
+    id              integer not null
+        constraint gallery_image_pk
+            primary key autoincrement,
+    item            integer not null
+        constraint gallery_image_item_id_fk
+            references item
+            on update cascade on delete cascade,
+    file            TEXT    not null,
+    url             TEXT    not null,
+    nsfw_level      integer default 0 not null,
+    width           integer,
+    height          integer,
+    prompt          TEXT,
+    negative_prompt TEXT,
+    sampler         TEXT,
+    cfg_scale       real,
+    steps           integer,
+    seed            integer,
+    resources       TEXT    default '[]' not null,
+    meta            TEXT    default '{}' not null
+);
+
+create index gallery_image_item_index
+    on gallery_image (item);
@@ -0,0 +1 @@
+alter table item add column recommended_weight real;
@@ -0,0 +1,18 @@
+create table model_update
+(
+    item                integer not null
+        constraint model_update_pk
+            primary key
+        constraint model_update_item_id_fk
+            references item
+            on update cascade on delete cascade,
+    model_id            integer not null,
+    version_id          integer not null,
+    version_name        TEXT    default '' not null,
+    latest_version_id   integer not null,
+    latest_version_name TEXT    default '' not null,
+    latest_published_at TEXT    default '' not null,
+    latest_base_model   TEXT    default '' not null,
+    has_update          integer default false not null,
+    checked_at          integer not null
+);
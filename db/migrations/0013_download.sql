@@ -0,0 +1,20 @@
+create table download
+(
+    id               integer not null
+        constraint download_pk
+            primary key autoincrement,
+    source           TEXT    not null,
+    base_label       TEXT    not null,
+    subfolder        TEXT    default '' not null,
+    priority         integer default 0 not null,
+    status           TEXT    not null,
+    path             TEXT    default '' not null,
+    total_bytes      integer default 0 not null,
+    downloaded_bytes integer default 0 not null,
+    error            TEXT    default '' not null,
+    created_at       integer not null,
+    updated_at       integer not null
+);
+
+create index download_status_index
+    on download (status);
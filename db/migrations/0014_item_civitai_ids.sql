@@ -0,0 +1,5 @@
+alter table item add column civitai_model_id integer;
+alter table item add column civitai_version_id integer;
+
+create index item_civitai_version_id_index
+    on item (civitai_version_id);
@@ -0,0 +1,11 @@
+create table civitai_info
+(
+    item       integer not null
+        constraint civitai_info_pk
+            primary key
+        constraint civitai_info_item_id_fk
+            references item
+            on update cascade on delete cascade,
+    info       TEXT    not null,
+    updated_at integer not null
+);
@@ -0,0 +1,15 @@
+create table hf_source
+(
+    item        integer not null
+        constraint hf_source_pk
+            primary key
+        constraint hf_source_item_id_fk
+            references item
+            on update cascade on delete cascade,
+    repo_id     TEXT    not null,
+    revision    TEXT    not null,
+    file        TEXT    not null,
+    license     TEXT    default '' not null,
+    model_card  TEXT    default '' not null,
+    updated_at  integer not null
+);
@@ -0,0 +1,14 @@
+alter table hf_source add column card_data TEXT default '' not null;
+
+create table model_record
+(
+    item       integer not null
+        constraint model_record_pk
+            primary key
+        constraint model_record_item_id_fk
+            references item
+            on update cascade on delete cascade,
+    provider   TEXT    not null,
+    record     TEXT    not null,
+    updated_at integer not null
+);
@@ -0,0 +1 @@
+alter table pickle_scan add column error TEXT;
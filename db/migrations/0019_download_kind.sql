@@ -0,0 +1,3 @@
+alter table download add column kind TEXT default 'civitai' not null;
+alter table download add column revision TEXT default '' not null;
+alter table download add column file TEXT default '' not null;
@@ -1,36 +1,71 @@
 //! Copyright (c) 2025 Trung Do <dothanhtrung@pm.me>.
 
-use crate::civitai::{update_model_info, CivitaiFileMetadata, CivitaiModel, PREVIEW_EXT};
+use crate::civitai::browse::{self, SearchQuery};
+use crate::civitai::client::CivitaiClient;
+use crate::civitai::gallery::gallery_dir;
+use crate::civitai::queue::DownloadQueue;
+use crate::civitai::update::check_updates;
+use crate::civitai::{read_info, update_model_info, PREVIEW_EXT};
 use crate::config::Config;
-use crate::db::item::{insert_or_update, Item};
-use crate::db::tag::add_tag_from_model_info;
-use crate::db::{base, item, DBPool};
-use crate::BASE_PATH_PREFIX;
+use crate::db::civitai_lookup::{self, CivitaiLookup};
+use crate::db::download::Download;
+use crate::db::gallery::{self, GalleryImage};
+use crate::db::hf_source::{self, HfSource};
+use crate::db::model_header::{self, ModelHeader};
+use crate::db::model_update::{self, OutdatedItem};
+use crate::db::pickle_scan::{self, PickleVerdict};
+use crate::db::scan_error::{self, ScanError};
+use crate::db::trigger_word::{self, TriggerWord};
+use crate::db::{item, DBPool};
+use crate::duplicate::{self, DuplicateGroup, ResolveAction};
+use crate::huggingface::{self, HuggingFaceClient, DEFAULT_REVISION};
+use crate::job::{Job, JobInfo, JobKind, JobManager};
+use crate::prompt::{self, Prompt};
+use crate::provider::{self, Identified, Providers};
+use crate::{scanner, trash, BASE_PATH_PREFIX};
 use actix_web::web::{Data, Query};
 use actix_web::{get, rt, web, Responder};
-use jwalk::{Parallelism, WalkDir};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use sqlx::Error;
 use std::cmp::max;
-use std::collections::HashSet;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tokio::fs;
 use tracing::error;
 
-const TRASH_DIR: &str = ".trash";
-
 pub fn scope_config(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/api")
             .service(get)
             .service(get_item)
+            .service(get_item_info)
+            .service(get_gallery)
+            .service(prompt_snippets)
             .service(reload_from_disk)
             .service(clean)
             .service(delete)
             .service(empty_trash)
             .service(search)
-            .service(sync_civitai),
+            .service(sync_civitai)
+            .service(updates)
+            .service(check_model_updates)
+            .service(search_civitai)
+            .service(download_model)
+            .service(resolve_huggingface)
+            .service(sync_huggingface)
+            .service(download_huggingface)
+            .service(list_downloads)
+            .service(clear_downloads)
+            .service(pause_download)
+            .service(resume_download)
+            .service(cancel_download)
+            .service(set_download_priority)
+            .service(list_jobs)
+            .service(get_job)
+            .service(cancel_job)
+            .service(scan_errors)
+            .service(clear_scan_errors)
+            .service(duplicates)
+            .service(resolve_duplicates),
     );
 }
 
@@ -46,6 +81,7 @@ struct GetRequest {
     pub page: Option<i64>,
     pub count: Option<i64>,
     pub search: Option<String>,
+    #[allow(dead_code)]
     pub tags: Option<String>,
 }
 
@@ -55,8 +91,77 @@ struct ModelInfo {
     name: String,
     path: String,
     preview: String,
-    info: Option<String>,
     tags: Vec<String>,
+    header: Option<ModelHeader>,
+    trigger_words: Vec<TriggerWord>,
+    network_dim: Option<i64>,
+    network_alpha: Option<f64>,
+    pickle_scan: Option<PickleVerdict>,
+    civitai_lookup: Option<CivitaiLookup>,
+    huggingface: Option<HfSource>,
+    /// Record of the provider which identified item
+    record: Option<Identified>,
+}
+
+/// Raw Civitai info of an item. Not part of item, since it can be big.
+#[derive(Serialize)]
+struct InfoResponse {
+    info: String,
+    err: Option<String>,
+}
+
+#[derive(Serialize)]
+struct GalleryResponse {
+    images: Vec<GalleryEntry>,
+    err: Option<String>,
+}
+
+#[derive(Serialize)]
+struct GalleryEntry {
+    /// Http path of downloaded image
+    src: String,
+    #[serde(flatten)]
+    image: GalleryImage,
+}
+
+#[derive(Deserialize)]
+struct PromptRequest {
+    /// Comma separated item ids
+    ids: String,
+}
+
+#[derive(Serialize)]
+struct PromptResponse {
+    #[serde(flatten)]
+    prompt: Prompt,
+    errors: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct JobResponse {
+    job: Option<JobInfo>,
+    err: Option<String>,
+}
+
+impl JobResponse {
+    fn ok(job: &Job) -> Self {
+        Self {
+            job: Some(job.info()),
+            err: None,
+        }
+    }
+
+    fn err(e: impl ToString) -> Self {
+        Self {
+            job: None,
+            err: Some(e.to_string()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JobsResponse {
+    jobs: Vec<JobInfo>,
 }
 
 #[derive(Deserialize)]
@@ -64,6 +169,141 @@ struct DeleteRequest {
     id: Vec<i64>,
 }
 
+#[derive(Deserialize)]
+struct ReloadRequest {
+    /// Index all files, even unchanged ones, e.g. after editing local overrides
+    force: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct SyncRequest {
+    /// Sync all items, even those with an up to date json sidecar
+    force: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct CivitaiSearchRequest {
+    query: Option<String>,
+    /// Model type, e.g. `Checkpoint`, `LORA`
+    #[serde(rename = "type")]
+    model_type: Option<String>,
+    base_model: Option<String>,
+    /// `Highest Rated`, `Most Downloaded` or `Newest`
+    sort: Option<String>,
+    nsfw: Option<bool>,
+    cursor: Option<String>,
+    limit: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct CivitaiSearchResponse {
+    items: Vec<Value>,
+    next_cursor: Option<String>,
+    err: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DownloadRequest {
+    /// Civitai model URL, version ID or AIR URN
+    source: String,
+    /// Collection to download into
+    label: String,
+    subfolder: Option<String>,
+    /// Higher is downloaded first, default 0
+    priority: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct HfResolveRequest {
+    /// e.g. `black-forest-labs/FLUX.1-dev`
+    repo: String,
+    revision: Option<String>,
+}
+
+#[derive(Serialize)]
+struct HfResolveResponse {
+    source: Option<HfSource>,
+    err: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct HfDownloadRequest {
+    repo: String,
+    revision: Option<String>,
+    /// Comma separated paths of files in repo
+    files: String,
+    /// Collection to download into
+    label: String,
+    subfolder: Option<String>,
+    /// Higher is downloaded first, default 0
+    priority: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct PriorityRequest {
+    priority: i64,
+}
+
+#[derive(Serialize)]
+struct DownloadResponse {
+    download: Option<Download>,
+    err: Option<String>,
+}
+
+impl From<anyhow::Result<Download>> for DownloadResponse {
+    fn from(result: anyhow::Result<Download>) -> Self {
+        match result {
+            Ok(download) => Self {
+                download: Some(download),
+                err: None,
+            },
+            Err(e) => Self {
+                download: None,
+                err: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DownloadsResponse {
+    downloads: Vec<Download>,
+    err: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ScanErrorsRequest {
+    page: Option<i64>,
+    count: Option<i64>,
+    stage: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ScanErrorsResponse {
+    errors: Vec<ScanError>,
+    total: i64,
+    err: Option<String>,
+}
+
+#[derive(Serialize)]
+struct UpdatesResponse {
+    items: Vec<OutdatedItem>,
+    err: Option<String>,
+}
+
+#[derive(Serialize)]
+struct DuplicatesResponse {
+    groups: Vec<DuplicateGroup>,
+    err: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ResolveDuplicatesRequest {
+    /// Item to keep
+    keep: i64,
+    action: ResolveAction,
+}
+
 #[get("")]
 async fn get(config: Data<Config>, db_pool: Data<DBPool>, query_params: Query<GetRequest>) -> impl Responder {
     let page = max(1, query_params.page.unwrap_or(1)) - 1;
@@ -71,9 +311,7 @@ async fn get(config: Data<Config>, db_pool: Data<DBPool>, query_params: Query<Ge
     let offset = page * limit;
     let mut ret = Vec::new();
     let mut err = None;
-    let mut total = 0;
-    let mut items = Vec::new();
-    (items, total) = if let Some(search_string) = &query_params.search {
+    let (items, total) = if let Some(search_string) = &query_params.search {
         match item::search(&db_pool.sqlite_pool, search_string, limit, offset).await {
             Ok((i, t)) => (i, t),
             Err(e) => {
@@ -102,7 +340,14 @@ async fn get(config: Data<Config>, db_pool: Data<DBPool>, query_params: Query<Ge
             path: model_url,
             preview: preview_url,
             tags,
-            info: None,
+            header: None,
+            trigger_words: Vec::new(),
+            network_dim: None,
+            network_alpha: None,
+            pickle_scan: None,
+            civitai_lookup: None,
+            huggingface: None,
+            record: None,
         })
     }
 
@@ -114,16 +359,39 @@ async fn get_item(config: Data<Config>, db_pool: Data<DBPool>, url_param: web::P
     let item_id = url_param.into_inner().0;
     match item::get_by_id(&db_pool.sqlite_pool, item_id).await {
         Ok(_item) => {
-            let (model_url, json_url, preview_url) = get_abs_path(&config, &_item.base_label, &_item.path);
-            let info = fs::read_to_string(&json_url).await.unwrap_or_default();
+            let (model_url, _, preview_url) = get_abs_path(&config, &_item.base_label, &_item.path);
             let tags = item::get_tags(&db_pool.sqlite_pool, item_id).await.unwrap_or_default();
+            let header = model_header::get(&db_pool.sqlite_pool, item_id)
+                .await
+                .unwrap_or_default();
+            let trigger_words = trigger_word::get(&db_pool.sqlite_pool, item_id)
+                .await
+                .unwrap_or_default();
+            let (network_dim, network_alpha) = item::get_network(&db_pool.sqlite_pool, item_id)
+                .await
+                .unwrap_or_default();
+            let pickle_scan = pickle_scan::get(&db_pool.sqlite_pool, item_id)
+                .await
+                .unwrap_or_default();
+            let civitai_lookup = civitai_lookup::get(&db_pool.sqlite_pool, item_id)
+                .await
+                .unwrap_or_default();
+            let huggingface = hf_source::get(&db_pool.sqlite_pool, item_id).await.unwrap_or_default();
+            let record = provider::get(&db_pool.sqlite_pool, item_id).await.unwrap_or_default();
             let item = ModelInfo {
                 id: item_id,
                 name: _item.name.unwrap_or_default(),
                 path: model_url,
                 preview: preview_url,
                 tags,
-                info: Some(info),
+                header,
+                trigger_words,
+                network_dim,
+                network_alpha,
+                pickle_scan,
+                civitai_lookup,
+                huggingface,
+                record,
             };
             web::Json(GetResponse {
                 items: vec![item],
@@ -139,84 +407,105 @@ async fn get_item(config: Data<Config>, db_pool: Data<DBPool>, url_param: web::P
     }
 }
 
-#[get("reload_from_disk")]
-async fn reload_from_disk(config: Data<Config>, db_pool: Data<DBPool>) -> impl Responder {
-    rt::spawn(async move {
-        let valid_ext = config.extensions.iter().collect::<HashSet<_>>();
+/// Civitai info of item, from DB or its json sidecar
+#[get("item/{id}/info")]
+async fn get_item_info(config: Data<Config>, db_pool: Data<DBPool>, url_param: web::Path<(i64,)>) -> impl Responder {
+    let item_id = url_param.into_inner().0;
+    let item = match item::get_by_id(&db_pool.sqlite_pool, item_id).await {
+        Ok(item) => item,
+        Err(e) => {
+            return web::Json(InfoResponse {
+                info: String::new(),
+                err: Some(e.to_string()),
+            })
+        }
+    };
+    let (model_url, _, _) = get_abs_path(&config, &item.base_label, &item.path);
+    let info = read_info(&db_pool.sqlite_pool, Some(item_id), Path::new(&model_url))
+        .await
+        .unwrap_or_default();
+    web::Json(InfoResponse { info, err: None })
+}
 
-        if let Err(e) = item::mark_obsolete_all(&db_pool.sqlite_pool).await {
-            error!("Failed to mark all item for reload: {}", e);
-            return;
+/// Downloaded gallery images of item with their generation parameters
+#[get("item/{id}/gallery")]
+async fn get_gallery(db_pool: Data<DBPool>, url_param: web::Path<(i64,)>) -> impl Responder {
+    let item_id = url_param.into_inner().0;
+    let item = match item::get_by_id(&db_pool.sqlite_pool, item_id).await {
+        Ok(item) => item,
+        Err(e) => {
+            return web::Json(GalleryResponse {
+                images: Vec::new(),
+                err: Some(e.to_string()),
+            })
         }
+    };
 
-        for (label, base_path) in config.model_paths.iter() {
-            let parallelism = Parallelism::RayonNewPool(config.walkdir_parallel);
-            for entry in WalkDir::new(base_path)
-                .skip_hidden(true)
-                .parallelism(parallelism.clone())
-                .follow_links(true)
+    let http_dir = gallery_dir(&PathBuf::from(format!("/{}{}", BASE_PATH_PREFIX, item.base_label)).join(&item.path));
+    match gallery::get(&db_pool.sqlite_pool, item_id).await {
+        Ok(images) => {
+            let images = images
                 .into_iter()
-                .flatten()
-            {
-                let path = entry.path();
-
-                let name = path
-                    .file_name()
-                    .unwrap_or_default()
-                    .to_str()
-                    .unwrap_or_default()
-                    .to_string();
-
-                let Ok(relative_path) = get_relative_path(base_path, &path) else {
-                    continue;
-                };
-
-                if entry.file_type().is_file() || entry.file_type().is_symlink() {
-                    let file_ext = path.extension().unwrap_or_default().to_str().unwrap_or_default();
-                    if valid_ext.contains(&file_ext.to_string()) {
-                        let mut json_file = PathBuf::from(path);
-                        json_file.set_extension("json");
-                        let info = fs::read_to_string(&json_file).await.unwrap_or_default();
-                        let v: Value = serde_json::from_str(&info).unwrap();
-                        let base_model = v["baseModel"].as_str().unwrap_or_default();
-                        let blake3 = v["files"][0]["hashes"]["BLAKE3"].as_str().unwrap_or_default();
-                        let file_metadata =
-                            serde_json::from_value::<CivitaiFileMetadata>(v["files"][0]["metadata"].clone())
-                                .unwrap_or_default();
-                        let model_info = serde_json::from_value::<CivitaiModel>(v["model"].clone()).unwrap_or_default();
-
-                        match insert_or_update(
-                            &db_pool.sqlite_pool,
-                            Some(name.as_str()),
-                            &relative_path,
-                            label,
-                            blake3,
-                            &model_info.name,
-                        )
-                        .await
-                        {
-                            Ok(id) => {
-                                let tags = vec![base_model.to_string()];
-                                if let Err(e) = add_tag_from_model_info(
-                                    &db_pool.sqlite_pool,
-                                    id,
-                                    &tags,
-                                    &model_info,
-                                    &file_metadata,
-                                )
-                                .await
-                                {
-                                    error!("Failed to insert tag: {}", e);
-                                }
-                            }
-                            Err(e) => error!("Failed to insert item: {}", e),
-                        }
-                    }
-                }
-            }
+                .map(|image| GalleryEntry {
+                    src: http_dir.join(&image.file).to_str().unwrap_or_default().to_string(),
+                    image,
+                })
+                .collect();
+            web::Json(GalleryResponse { images, err: None })
+        }
+        Err(e) => web::Json(GalleryResponse {
+            images: Vec::new(),
+            err: Some(e.to_string()),
+        }),
+    }
+}
+
+/// Prompt snippets of LoRAs and embeddings, using their recommended weight and trigger words
+#[get("prompt_snippets")]
+async fn prompt_snippets(db_pool: Data<DBPool>, params: Query<PromptRequest>) -> impl Responder {
+    let mut snippets = Vec::new();
+    let mut errors = Vec::new();
+    for id in params.ids.split(',').map(|id| id.trim()).filter(|id| !id.is_empty()) {
+        let snippet = match id.parse() {
+            Ok(id) => prompt::get_snippet(&db_pool.sqlite_pool, id).await,
+            Err(e) => Err(anyhow::anyhow!("Invalid id {}: {}", id, e)),
+        };
+        match snippet {
+            Ok(snippet) => snippets.push(snippet),
+            Err(e) => errors.push(e.to_string()),
         }
+    }
+
+    web::Json(PromptResponse {
+        prompt: prompt::build(snippets),
+        errors,
+    })
+}
+
+#[get("reload_from_disk")]
+async fn reload_from_disk(
+    config: Data<Config>,
+    db_pool: Data<DBPool>,
+    providers: Data<Providers>,
+    jobs: Data<JobManager>,
+    query: Query<ReloadRequest>,
+) -> impl Responder {
+    let job = match jobs.start(JobKind::Scan) {
+        Ok(job) => job,
+        Err(e) => return web::Json(JobResponse::err(e)),
+    };
+
+    let ret = JobResponse::ok(&job);
+    rt::spawn(async move {
+        let _guard = job.guard();
+        let force = query.force.unwrap_or(false);
+        let result = scanner::reload_from_disk(&config, &db_pool.sqlite_pool, &providers, &job, force).await;
+        if let Err(e) = &result {
+            error!("Failed to reload from disk: {}", e);
+        }
+        job.finish(&result);
     });
-    web::Json("")
+    web::Json(ret)
 }
 
 #[get("clean")]
@@ -232,45 +521,342 @@ async fn clean(db_pool: Data<DBPool>) -> impl Responder {
 }
 
 #[get("sync_civitai")]
-async fn sync_civitai(config: Data<Config>) -> impl Responder {
+async fn sync_civitai(
+    config: Data<Config>,
+    db_pool: Data<DBPool>,
+    client: Data<CivitaiClient>,
+    providers: Data<Providers>,
+    jobs: Data<JobManager>,
+    query: Query<SyncRequest>,
+) -> impl Responder {
+    let job = match jobs.start(JobKind::CivitaiSync) {
+        Ok(job) => job,
+        Err(e) => return web::Json(JobResponse::err(e)),
+    };
+
+    let ret = JobResponse::ok(&job);
     let config = (**config).clone();
-    rt::spawn(async { update_model_info(config).await });
-    web::Json("")
+    rt::spawn(async move {
+        let _guard = job.guard();
+        let force = query.force.unwrap_or(false);
+        let result = update_model_info(config, &db_pool.sqlite_pool, &client, &providers, &job, force).await;
+        if let Err(e) = &result {
+            error!("Failed to sync Civitai: {}", e);
+        }
+        job.finish(&result);
+    });
+    web::Json(ret)
+}
+
+/// Items with a newer version on Civitai, found by the last update check
+#[get("updates")]
+async fn updates(db_pool: Data<DBPool>) -> impl Responder {
+    match model_update::get_outdated(&db_pool.sqlite_pool).await {
+        Ok(items) => web::Json(UpdatesResponse { items, err: None }),
+        Err(e) => web::Json(UpdatesResponse {
+            items: Vec::new(),
+            err: Some(e.to_string()),
+        }),
+    }
+}
+
+#[get("updates/check")]
+async fn check_model_updates(
+    config: Data<Config>,
+    db_pool: Data<DBPool>,
+    client: Data<CivitaiClient>,
+    jobs: Data<JobManager>,
+) -> impl Responder {
+    let job = match jobs.start(JobKind::UpdateCheck) {
+        Ok(job) => job,
+        Err(e) => return web::Json(JobResponse::err(e)),
+    };
+
+    let ret = JobResponse::ok(&job);
+    rt::spawn(async move {
+        let _guard = job.guard();
+        let result = check_updates(&config, &db_pool.sqlite_pool, &client, &job).await;
+        if let Err(e) = &result {
+            error!("Failed to check model updates: {}", e);
+        }
+        job.finish(&result);
+    });
+    web::Json(ret)
+}
+
+/// Search models on Civitai. Versions already in library list their item ids in `library_items`.
+#[get("civitai/search")]
+async fn search_civitai(
+    db_pool: Data<DBPool>,
+    client: Data<CivitaiClient>,
+    params: Query<CivitaiSearchRequest>,
+) -> impl Responder {
+    let params = params.into_inner();
+    let query = SearchQuery {
+        query: params.query.unwrap_or_default(),
+        model_type: params.model_type.unwrap_or_default(),
+        base_model: params.base_model.unwrap_or_default(),
+        sort: params.sort.unwrap_or_default(),
+        nsfw: params.nsfw.unwrap_or(false),
+        cursor: params.cursor.unwrap_or_default(),
+        limit: params.limit.unwrap_or_default(),
+    };
+    match browse::search(&db_pool.sqlite_pool, &client, &query).await {
+        Ok(result) => web::Json(CivitaiSearchResponse {
+            items: result.items,
+            next_cursor: result.next_cursor,
+            err: None,
+        }),
+        Err(e) => web::Json(CivitaiSearchResponse {
+            items: Vec::new(),
+            next_cursor: None,
+            err: Some(e.to_string()),
+        }),
+    }
+}
+
+/// Add a Civitai model to the download queue
+#[get("download")]
+async fn download_model(downloads: Data<DownloadQueue>, params: Query<DownloadRequest>) -> impl Responder {
+    let subfolder = params.subfolder.as_deref().unwrap_or_default();
+    let priority = params.priority.unwrap_or_default();
+    let result = downloads
+        .enqueue(&params.source, &params.label, subfolder, priority)
+        .await;
+    web::Json(DownloadResponse::from(result))
+}
+
+/// Match item with a file of a Hugging Face repo by SHA256
+#[get("item/{id}/huggingface")]
+async fn resolve_huggingface(
+    config: Data<Config>,
+    db_pool: Data<DBPool>,
+    client: Data<HuggingFaceClient>,
+    providers: Data<Providers>,
+    url_param: web::Path<(i64,)>,
+    query: Query<HfResolveRequest>,
+) -> impl Responder {
+    let id = url_param.into_inner().0;
+    let revision = query.revision.as_deref().unwrap_or(DEFAULT_REVISION);
+    match huggingface::resolve(
+        &config,
+        &db_pool.sqlite_pool,
+        &client,
+        &providers,
+        id,
+        &query.repo,
+        revision,
+    )
+    .await
+    {
+        Ok(source) => web::Json(HfResolveResponse {
+            source: Some(source),
+            err: None,
+        }),
+        Err(e) => web::Json(HfResolveResponse {
+            source: None,
+            err: Some(e.to_string()),
+        }),
+    }
+}
+
+/// Match unmatched items with files of Hugging Face repos in config
+#[get("huggingface/sync")]
+async fn sync_huggingface(
+    config: Data<Config>,
+    db_pool: Data<DBPool>,
+    jobs: Data<JobManager>,
+    client: Data<HuggingFaceClient>,
+    providers: Data<Providers>,
+) -> impl Responder {
+    let job = match jobs.start(JobKind::HuggingfaceSync) {
+        Ok(job) => job,
+        Err(e) => return web::Json(JobResponse::err(e)),
+    };
+
+    let ret = JobResponse::ok(&job);
+    rt::spawn(async move {
+        let _guard = job.guard();
+        let result = huggingface::sync(&config, &db_pool.sqlite_pool, &client, &providers, &job).await;
+        if let Err(e) = &result {
+            error!("Failed to sync Hugging Face: {}", e);
+        }
+        job.finish(&result);
+    });
+    web::Json(ret)
+}
+
+/// Add files of a Hugging Face repo to the download queue
+#[get("huggingface/download")]
+async fn download_huggingface(downloads: Data<DownloadQueue>, params: Query<HfDownloadRequest>) -> impl Responder {
+    let files = params
+        .files
+        .split(',')
+        .map(|f| f.trim().to_string())
+        .filter(|f| !f.is_empty())
+        .collect::<Vec<_>>();
+    let result = downloads
+        .enqueue_huggingface(
+            &params.repo,
+            params.revision.as_deref().unwrap_or(DEFAULT_REVISION),
+            &files,
+            &params.label,
+            params.subfolder.as_deref().unwrap_or_default(),
+            params.priority.unwrap_or_default(),
+        )
+        .await;
+    match result {
+        Ok(downloads) => web::Json(DownloadsResponse { downloads, err: None }),
+        Err(e) => web::Json(DownloadsResponse {
+            downloads: Vec::new(),
+            err: Some(e.to_string()),
+        }),
+    }
+}
+
+#[get("downloads")]
+async fn list_downloads(downloads: Data<DownloadQueue>) -> impl Responder {
+    match downloads.list().await {
+        Ok(downloads) => web::Json(DownloadsResponse { downloads, err: None }),
+        Err(e) => web::Json(DownloadsResponse {
+            downloads: Vec::new(),
+            err: Some(e.to_string()),
+        }),
+    }
+}
+
+/// Remove done and cancelled downloads from the list
+#[get("downloads/clear")]
+async fn clear_downloads(downloads: Data<DownloadQueue>) -> impl Responder {
+    let err = downloads.clear().await.err().map(|e| e.to_string());
+    web::Json(DownloadsResponse {
+        downloads: Vec::new(),
+        err,
+    })
+}
+
+#[get("downloads/{id}/pause")]
+async fn pause_download(downloads: Data<DownloadQueue>, url_param: web::Path<(i64,)>) -> impl Responder {
+    let id = url_param.into_inner().0;
+    web::Json(DownloadResponse::from(downloads.pause(id).await))
+}
+
+#[get("downloads/{id}/resume")]
+async fn resume_download(downloads: Data<DownloadQueue>, url_param: web::Path<(i64,)>) -> impl Responder {
+    let id = url_param.into_inner().0;
+    web::Json(DownloadResponse::from(downloads.resume(id).await))
+}
+
+#[get("downloads/{id}/cancel")]
+async fn cancel_download(downloads: Data<DownloadQueue>, url_param: web::Path<(i64,)>) -> impl Responder {
+    let id = url_param.into_inner().0;
+    web::Json(DownloadResponse::from(downloads.cancel(id).await))
+}
+
+#[get("downloads/{id}/priority")]
+async fn set_download_priority(
+    downloads: Data<DownloadQueue>,
+    url_param: web::Path<(i64,)>,
+    query: Query<PriorityRequest>,
+) -> impl Responder {
+    let id = url_param.into_inner().0;
+    web::Json(DownloadResponse::from(downloads.set_priority(id, query.priority).await))
+}
+
+#[get("jobs")]
+async fn list_jobs(jobs: Data<JobManager>) -> impl Responder {
+    let jobs = jobs.list().iter().map(|j| j.info()).collect();
+    web::Json(JobsResponse { jobs })
+}
+
+#[get("jobs/{id}")]
+async fn get_job(jobs: Data<JobManager>, url_param: web::Path<(u64,)>) -> impl Responder {
+    let id = url_param.into_inner().0;
+    match jobs.get(id) {
+        Some(job) => web::Json(JobResponse::ok(&job)),
+        None => web::Json(JobResponse::err(format!("Job {} not found", id))),
+    }
+}
+
+#[get("jobs/{id}/cancel")]
+async fn cancel_job(jobs: Data<JobManager>, url_param: web::Path<(u64,)>) -> impl Responder {
+    let id = url_param.into_inner().0;
+    match jobs.get(id) {
+        Some(job) => {
+            job.cancel();
+            web::Json(JobResponse::ok(&job))
+        }
+        None => web::Json(JobResponse::err(format!("Job {} not found", id))),
+    }
 }
 
 #[get("delete")]
 async fn delete(config: Data<Config>, db_pool: Data<DBPool>, params: Query<DeleteRequest>) -> impl Responder {
     for id in params.id.iter() {
-        let Ok((rel_path, label)) = item::mark_obsolete(&db_pool.sqlite_pool, *id).await else {
-            continue;
-        };
-        let Some(base_path) = config.model_paths.get(&label) else {
-            continue;
-        };
-        let base_path = PathBuf::from(base_path);
-        let model_file = base_path.join(rel_path);
-        let trash_dir = base_path.join(TRASH_DIR);
-
-        let mut json_file = model_file.clone();
-        json_file.set_extension("json");
-        let mut preview_file = model_file.clone();
-        preview_file.set_extension(PREVIEW_EXT);
-        // TODO: Removed downloaded video
-
-        for file in [model_file, json_file, preview_file].iter() {
-            if let Err(e) = move_to_dir(file, &trash_dir).await {
-                error!("Failed to move file to trash directory: {}", e);
-            }
+        if let Err(e) = trash::trash_item(&config, &db_pool.sqlite_pool, *id).await {
+            error!("Failed to move item {} to trash: {}", id, e);
         }
     }
 
     web::Json("")
 }
 
+#[get("scan_errors")]
+async fn scan_errors(
+    config: Data<Config>,
+    db_pool: Data<DBPool>,
+    query_params: Query<ScanErrorsRequest>,
+) -> impl Responder {
+    let page = max(1, query_params.page.unwrap_or(1)) - 1;
+    let limit = max(0, query_params.count.unwrap_or(config.api.per_page as i64));
+    let offset = page * limit;
+    match scan_error::get(&db_pool.sqlite_pool, query_params.stage.as_deref(), limit, offset).await {
+        Ok((errors, total)) => web::Json(ScanErrorsResponse {
+            errors,
+            total,
+            err: None,
+        }),
+        Err(e) => web::Json(ScanErrorsResponse {
+            errors: Vec::new(),
+            total: 0,
+            err: Some(e.to_string()),
+        }),
+    }
+}
+
+#[get("scan_errors/clear")]
+async fn clear_scan_errors(db_pool: Data<DBPool>) -> impl Responder {
+    if let Err(e) = scan_error::clear(&db_pool.sqlite_pool).await {
+        error!("Failed to clear scan errors: {}", e);
+    }
+    web::Json("")
+}
+
+#[get("duplicates")]
+async fn duplicates(config: Data<Config>, db_pool: Data<DBPool>) -> impl Responder {
+    match duplicate::find(&config, &db_pool.sqlite_pool).await {
+        Ok(groups) => web::Json(DuplicatesResponse { groups, err: None }),
+        Err(e) => web::Json(DuplicatesResponse {
+            groups: Vec::new(),
+            err: Some(e.to_string()),
+        }),
+    }
+}
+
+/// Keep one item of a duplicate group and trash or link the others to it
+#[get("duplicates/resolve")]
+async fn resolve_duplicates(
+    config: Data<Config>,
+    db_pool: Data<DBPool>,
+    params: Query<ResolveDuplicatesRequest>,
+) -> impl Responder {
+    web::Json(duplicate::resolve(&config, &db_pool.sqlite_pool, params.keep, params.action).await)
+}
+
 #[get("empty_trash")]
 async fn empty_trash(config: Data<Config>) -> impl Responder {
     for (_, base_path) in config.model_paths.iter() {
-        let trash_dir = PathBuf::from(base_path).join(TRASH_DIR);
+        let trash_dir = PathBuf::from(base_path).join(trash::TRASH_DIR);
         if let Err(e) = fs::remove_dir_all(&trash_dir).await {
             error!("Failed to remove trash directory: {}", e);
         }
@@ -283,22 +869,6 @@ async fn search() -> impl Responder {
     web::Json("")
 }
 
-async fn move_to_dir(file: &PathBuf, dir: &PathBuf) -> anyhow::Result<()> {
-    let file_name = file.file_name().unwrap_or_default();
-    if !file_name.is_empty() {
-        let dest = dir.join(file_name);
-        fs::rename(file, dest).await?;
-    }
-
-    Ok(())
-}
-
-fn get_relative_path(base_path: &str, path: &PathBuf) -> Result<String, anyhow::Error> {
-    let base = PathBuf::from(base_path);
-    let path = path.strip_prefix(&base)?;
-    Ok(path.to_str().unwrap_or_default().to_string())
-}
-
 /// Return abs path of (model, json) and http path of preview
 fn get_abs_path(config: &Config, label: &str, rel_path: &str) -> (String, String, String) {
     let (mut model, mut json, mut preview) = (String::new(), String::new(), String::new());
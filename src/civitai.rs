@@ -1,22 +1,44 @@
 //! Copyright (c) 2025 Trung Do <dothanhtrung@pm.me>.
 
+pub mod browse;
+pub mod client;
+pub mod download;
+pub mod gallery;
+pub mod queue;
+pub mod update;
+
 use crate::config::Config;
-use jwalk::{Parallelism, WalkDir};
-use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
-use reqwest::Client;
+use crate::db::civitai_info;
+use crate::db::civitai_lookup::{self, LookupStatus};
+use crate::db::item::{self, SyncItem};
+use crate::db::scan_error::{self, ScanStage};
+use crate::duplicate::abs_path;
+use crate::job::{unix_time, Job};
+use crate::provider::{self, MetadataProvider, ModelFile, ModelRecord, ProviderKind, Providers};
+use crate::scanner::{get_fingerprint, index_file, report};
+use client::{CivitaiClient, CivitaiError};
+use futures_util::future::BoxFuture;
+use futures_util::{stream, StreamExt};
+use reqwest::StatusCode;
 use serde::Deserialize;
 use serde_json::{to_string_pretty, Value};
 use sha2::{Digest, Sha256};
-use std::collections::HashSet;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufReader, Read, Write};
-use std::path::{Path, PathBuf};
+use std::io::{BufReader, ErrorKind, Read};
+use std::path::Path;
 use std::process::Command;
 use tokio::fs;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 pub const PREVIEW_EXT: &str = "jpeg";
 
+const HASH_BUFFER_SIZE: usize = 1024 * 1024;
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+
 #[derive(PartialEq)]
 enum FileType {
     NA,
@@ -24,107 +46,375 @@ enum FileType {
     Image,
 }
 
+#[derive(Default, Clone, Debug)]
+pub struct FileHashes {
+    pub blake3: String,
+    pub sha256: String,
+    pub autov2: String,
+}
+
 #[derive(Deserialize, Default)]
-pub struct CivitaiFileMetadata {
-    pub format: String,
-    pub fp: Option<u32>,
-    pub size: Option<u64>,
+#[serde(default)]
+struct CivitaiFileMetadata {
+    /// SafeTensor, PickleTensor, GGUF...
+    format: String,
+    /// fp16, fp32, bf16...
+    fp: Option<String>,
+    /// pruned or full
+    size: Option<String>,
 }
 
 #[derive(Deserialize, Default)]
-pub struct CivitaiModel {
-    pub name: String,
-    pub nsfw: bool,
-    pub poi: bool,
+struct CivitaiModel {
+    name: String,
+    nsfw: bool,
+    poi: bool,
     #[serde(rename = "type")]
-    pub model_type: String,
+    model_type: String,
 }
 
-pub async fn update_model_info(config: Config) -> anyhow::Result<()> {
-    let valid_ext = config.extensions.iter().collect::<HashSet<_>>();
-    let client = Client::new();
+/// Fetch model info of items without Civitai info, or with info older than `resync_after_days`.
+/// Hashes stored by the scanner are used, so files are not read again. `force` syncs all items.
+/// Items identified by a provider tried before Civitai are skipped.
+pub async fn update_model_info(
+    config: Config,
+    pool: &SqlitePool,
+    client: &CivitaiClient,
+    providers: &Providers,
+    job: &Job,
+    force: bool,
+) -> anyhow::Result<()> {
+    if !config.providers.order.contains(&ProviderKind::Civitai) {
+        info!("Civitai is not in providers.order, nothing to sync");
+        return Ok(());
+    }
 
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        AUTHORIZATION,
-        HeaderValue::from_str(&format!("Bearer {}", config.civitai.api_key))?,
-    );
+    let now = unix_time();
+    let (info_before, not_found_after) = if force {
+        (i64::MAX, i64::MAX)
+    } else {
+        let info_before = if config.civitai.resync_after_days > 0 {
+            (now - (config.civitai.resync_after_days * SECS_PER_DAY) as i64) * NANOS_PER_SEC
+        } else {
+            0
+        };
+        (
+            info_before,
+            now - (config.civitai.not_found_retry_days * SECS_PER_DAY) as i64,
+        )
+    };
 
-    let parallelism = Parallelism::RayonNewPool(config.walkdir_parallel);
-    for (_, base_path) in config.model_paths.iter() {
-        for entry in WalkDir::new(base_path)
-            .skip_hidden(true)
-            .parallelism(parallelism.clone())
-            .follow_links(true)
-            .into_iter()
-            .flatten()
-        {
-            let path = entry.path();
-            if entry.file_type().is_file() || entry.file_type().is_symlink() {
-                let file_ext = path.extension().unwrap_or_default().to_str().unwrap_or_default();
-                if valid_ext.contains(&file_ext.to_string()) {
-                    info!("Update model info: {}", entry.path().display());
-                    match get_model_info(&path, &client, &headers).await {
-                        Ok(info) => {
-                            if let Err(e) =
-                                save_info(&path, &info, config.civitai.overwrite_thumbnail, &client, &headers).await
-                            {
-                                error!("Failed to save model info: {}", e);
-                            }
-                        }
-                        Err(e) => error!("Failed to download model info: {}", e),
-                    }
+    let mut items = Vec::new();
+    for item in item::get_sync_candidates(pool, info_before, not_found_after).await? {
+        if provider::needs_sync(&config, pool, ProviderKind::Civitai, item.id).await? {
+            items.push(item);
+        }
+    }
+    stream::iter(items.chunks(config.civitai.batch_size.max(1)))
+        .for_each_concurrent(config.civitai.concurrency.max(1), |batch| {
+            let config = &config;
+            async move {
+                if job.is_cancelled() {
+                    return;
+                }
+                if let Err(e) = sync_batch(config, pool, client, providers, job, batch).await {
+                    error!("Failed to sync batch: {}", e);
+                }
+            }
+        })
+        .await;
+
+    Ok(())
+}
+
+/// Look up a batch of items in one request, then save the model info of each
+async fn sync_batch(
+    config: &Config,
+    pool: &SqlitePool,
+    client: &CivitaiClient,
+    providers: &Providers,
+    job: &Job,
+    items: &[SyncItem],
+) -> anyhow::Result<()> {
+    for item in items {
+        job.inc_seen();
+        if let Err(e) = scan_error::clear_path(pool, &item.path, &item.base_label, &ScanStage::SYNC).await {
+            error!("Failed to clear sync errors: {}", e);
+        }
+    }
+
+    let hashes = items.iter().map(|i| i.blake3.as_str()).collect::<Vec<_>>();
+    let versions = match client.get_model_versions_by_hashes(&hashes).await {
+        Ok(versions) => versions_by_hash(versions),
+        // A 404 for the whole batch does not tell which hashes are unknown, so ask for each one
+        Err(CivitaiError::NotFound) => {
+            for item in items {
+                if job.is_cancelled() {
+                    break;
                 }
+                match client.get_model_version_by_hash(&item.blake3).await {
+                    Ok(info) => save_model_info(config, pool, client, providers, job, item, &info).await,
+                    Err(CivitaiError::NotFound) => set_not_found(pool, item).await,
+                    Err(e) => lookup_failed(pool, job, item, &e).await,
+                }
+            }
+            return Ok(());
+        }
+        Err(e) => {
+            for item in items {
+                lookup_failed(pool, job, item, &e).await;
             }
+            return Ok(());
+        }
+    };
+
+    for item in items {
+        match versions.get(&item.blake3.to_lowercase()) {
+            Some(info) => save_model_info(config, pool, client, providers, job, item, info).await,
+            None => set_not_found(pool, item).await,
         }
     }
+
     Ok(())
 }
 
-async fn get_model_info(path: &PathBuf, client: &Client, headers: &HeaderMap) -> anyhow::Result<Value> {
-    let hash = calculate_blake3_hash(path)?;
-    let url = format!("https://civitai.com/api/v1/model-versions/by-hash/{}", hash);
+async fn set_not_found(pool: &SqlitePool, item: &SyncItem) {
+    info!("Not found on Civitai: {}/{}", item.base_label, item.path);
+    set_lookup_status(pool, item.id, LookupStatus::NotFound, Some(StatusCode::NOT_FOUND), "").await;
+}
+
+/// Record a failed lookup, the item is looked up again next sync
+async fn lookup_failed(pool: &SqlitePool, job: &Job, item: &SyncItem, e: &CivitaiError) {
+    set_lookup_status(pool, item.id, LookupStatus::Error, e.status(), &e.to_string()).await;
+    report(
+        pool,
+        Some(job),
+        &item.base_label,
+        &item.path,
+        ScanStage::CivitaiFetch,
+        &e.to_string(),
+    )
+    .await;
+}
+
+/// Map every file hash (lowercase) of model versions to its model version
+fn versions_by_hash(versions: Vec<Value>) -> HashMap<String, Value> {
+    let mut ret = HashMap::new();
+    for version in versions {
+        let hashes = version["files"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|file| file["hashes"].as_object())
+            .flat_map(|hashes| hashes.values())
+            .filter_map(|hash| hash.as_str())
+            .map(|hash| hash.to_lowercase())
+            .collect::<Vec<_>>();
+        for hash in hashes {
+            ret.insert(hash, version.clone());
+        }
+    }
+    ret
+}
+
+/// Save sidecar and preview of a found item, then index it again
+async fn save_model_info(
+    config: &Config,
+    pool: &SqlitePool,
+    client: &CivitaiClient,
+    providers: &Providers,
+    job: &Job,
+    item: &SyncItem,
+    info: &Value,
+) {
+    let path = abs_path(config, &item.base_label, &item.path);
+    info!("Update model info: {}", path.display());
+
+    if let Err(e) = save_info(config, pool, item.id, &path, info).await {
+        set_lookup_status(pool, item.id, LookupStatus::Error, Some(StatusCode::OK), &e.to_string()).await;
+        report(
+            pool,
+            Some(job),
+            &item.base_label,
+            &item.path,
+            ScanStage::CivitaiFetch,
+            &e.to_string(),
+        )
+        .await;
+        return;
+    }
+    set_lookup_status(pool, item.id, LookupStatus::Found, Some(StatusCode::OK), "").await;
+    job.inc_fetched();
+    if let Err(e) = save_preview(&path, info, config.civitai.overwrite_thumbnail, client).await {
+        report(
+            pool,
+            Some(job),
+            &item.base_label,
+            &item.path,
+            ScanStage::Thumbnail,
+            &e.to_string(),
+        )
+        .await;
+    }
+    if config.civitai.gallery.enabled {
+        if let Err(e) = gallery::save_gallery(&config.civitai.gallery, pool, client, item.id, &path, info).await {
+            report(
+                pool,
+                Some(job),
+                &item.base_label,
+                &item.path,
+                ScanStage::Gallery,
+                &e.to_string(),
+            )
+            .await;
+        }
+    }
 
-    let response = client.get(url).headers(headers.clone()).send().await?.json().await?;
+    // Index new info now, so tags are updated and item is not synced again before the next scan
+    match get_fingerprint(&path).await {
+        Ok(fingerprint) => {
+            index_file(
+                config,
+                pool,
+                providers,
+                &item.base_label,
+                &item.path,
+                &fingerprint,
+                Some(job),
+            )
+            .await
+        }
+        Err(e) => {
+            report(
+                pool,
+                Some(job),
+                &item.base_label,
+                &item.path,
+                ScanStage::Walk,
+                &e.to_string(),
+            )
+            .await
+        }
+    }
+}
 
-    Ok(response)
+/// Failing to record lookup status is only logged, the item is looked up again next sync
+async fn set_lookup_status(
+    pool: &SqlitePool,
+    item: i64,
+    status: LookupStatus,
+    http_status: Option<StatusCode>,
+    message: &str,
+) {
+    let http_status = http_status.map(|s| s.as_u16());
+    if let Err(e) = civitai_lookup::insert_or_update(pool, item, status, http_status, message).await {
+        error!("Failed to save Civitai lookup status: {}", e);
+    }
 }
 
+/// Save model version info in DB, and as json next to model file if `save_json` is enabled
 async fn save_info(
-    filepath: &PathBuf,
+    config: &Config,
+    pool: &SqlitePool,
+    item: i64,
+    filepath: &Path,
     mode_info: &Value,
-    overwrite_thumbnail: bool,
-    client: &Client,
-    headers: &HeaderMap,
 ) -> anyhow::Result<()> {
-    let mut info_file = filepath.clone();
+    if !mode_info["files"].is_array() {
+        return Err(anyhow::anyhow!("Response is not a model version: {}", mode_info));
+    }
+
+    let info_str = to_string_pretty(mode_info)?;
+    civitai_info::insert_or_update(pool, item, &info_str).await?;
+    if config.civitai.save_json {
+        let mut info_file = filepath.to_path_buf();
+        info_file.set_extension("json");
+        fs::write(info_file, info_str).await?;
+    }
+
+    Ok(())
+}
+
+/// Civitai info of model from its json sidecar, or from DB if it has no sidecar.
+/// Empty if there is neither.
+pub async fn read_info(pool: &SqlitePool, item: Option<i64>, model_path: &Path) -> anyhow::Result<String> {
+    let mut info_file = model_path.to_path_buf();
     info_file.set_extension("json");
+    match fs::read_to_string(&info_file).await {
+        Ok(info) => return Ok(info),
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    match item {
+        Some(item) => Ok(civitai_info::get(pool, item).await?.unwrap_or_default()),
+        None => Ok(String::new()),
+    }
+}
 
+/// Identify files from Civitai info saved by sync, read from json sidecar or DB
+pub struct CivitaiProvider {
+    pool: SqlitePool,
+}
+
+impl CivitaiProvider {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    async fn record(&self, file: &ModelFile<'_>) -> anyhow::Result<Option<ModelRecord>> {
+        let info = read_info(&self.pool, file.item, file.path).await?;
+        if info.is_empty() {
+            return Ok(None);
+        }
+        let info: Value = serde_json::from_str(&info)?;
+        if find_file(&info, file.hashes).is_none() {
+            warn!(
+                "No file in Civitai info of {} matches hash of model file",
+                file.path.display()
+            );
+        }
+
+        Ok(Some(model_record(&info, file.hashes)))
+    }
+}
+
+impl MetadataProvider for CivitaiProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Civitai
+    }
+
+    fn identify<'a>(&'a self, file: &'a ModelFile<'a>) -> BoxFuture<'a, anyhow::Result<Option<ModelRecord>>> {
+        Box::pin(self.record(file))
+    }
+}
+
+/// Download first image of model version as preview. Video is converted to a thumbnail.
+async fn save_preview(
+    filepath: &Path,
+    mode_info: &Value,
+    overwrite_thumbnail: bool,
+    client: &CivitaiClient,
+) -> anyhow::Result<()> {
     if let Some(images) = mode_info["images"].as_array() {
         if let Some(first_image) = images.first() {
             if let Some(url) = first_image["url"].as_str() {
                 let extension = url
                     .split('/')
-                    .last()
+                    .next_back()
                     .and_then(|filename| Path::new(filename).extension())
                     .and_then(|ext| ext.to_str())
                     .unwrap_or(PREVIEW_EXT);
 
-                let mut preview_file = filepath.clone();
+                let mut preview_file = filepath.to_path_buf();
                 preview_file.set_extension(extension);
 
-                let mut saved_file = File::create(info_file)?;
-                let info_str = to_string_pretty(mode_info)?;
-                saved_file
-                    .write_all(info_str.as_bytes())
-                    .map_err(|e| anyhow::anyhow!(e))?;
-
                 let image_path = Path::new(&preview_file);
                 if image_path.exists() && !overwrite_thumbnail {
                     info!("File already exists: {}", image_path.display());
                     return Ok(());
                 } else {
-                    let response = client.get(url).headers(headers.clone()).send().await?.bytes().await?;
+                    let response = client.download(url).await?;
                     let mut content = response.as_ref();
                     let mut file = File::create(image_path)?;
                     std::io::copy(&mut content, &mut file)?;
@@ -148,44 +438,150 @@ async fn save_info(
     Ok(())
 }
 
-pub(crate) fn calculate_autov2_hash(file_path: &PathBuf) -> std::io::Result<String> {
-    let file = File::open(file_path)?;
-    let mut reader = BufReader::new(file);
-    let mut hasher = Sha256::new();
-    let mut buffer = [0u8; 8192];
+/// Find the entry of `files` in model version info whose hash is the local file's
+pub fn find_file<'a>(info: &'a Value, hashes: &FileHashes) -> Option<&'a Value> {
+    let local = [&hashes.blake3, &hashes.sha256, &hashes.autov2];
+    info["files"].as_array()?.iter().find(|file| {
+        file["hashes"].as_object().is_some_and(|remote| {
+            remote
+                .values()
+                .filter_map(|h| h.as_str())
+                .any(|h| local.iter().any(|l| !l.is_empty() && l.eq_ignore_ascii_case(h)))
+        })
+    })
+}
+
+/// Normalize model version info. Metadata of the file matching `hashes` and NSFW/POI flags become tags.
+pub fn model_record(info: &Value, hashes: &FileHashes) -> ModelRecord {
+    let model = serde_json::from_value::<CivitaiModel>(info["model"].clone()).unwrap_or_default();
+    let metadata = find_file(info, hashes)
+        .and_then(|f| serde_json::from_value::<CivitaiFileMetadata>(f["metadata"].clone()).ok())
+        .unwrap_or_default();
+
+    let mut tags = Vec::new();
+    if model.nsfw {
+        tags.push(String::from("nsfw"));
+    }
+    if model.poi {
+        tags.push(String::from("poi"));
+    }
+    if !metadata.format.is_empty() {
+        tags.push(metadata.format);
+    }
+    tags.extend(metadata.fp);
+    tags.extend(metadata.size);
+
+    ModelRecord {
+        name: model.name,
+        version: info["name"].as_str().unwrap_or_default().to_string(),
+        model_type: model.model_type,
+        base_model: info["baseModel"].as_str().unwrap_or_default().to_string(),
+        tags,
+        trigger_words: trained_words(info),
+        images: info["images"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|image| image["url"].as_str())
+            .map(String::from)
+            .collect(),
+        // Only given by the model endpoint, not with model versions
+        license: String::new(),
+    }
+}
 
-    loop {
-        let bytes_read = reader.read(&mut buffer)?;
-        if bytes_read == 0 {
-            break;
+/// `trainedWords` of model version. Authors often put several words in one entry separated by commas.
+pub fn trained_words(info: &Value) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    for word in info["trainedWords"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|w| w.as_str())
+        .flat_map(|w| w.split(','))
+        .map(|w| w.trim())
+    {
+        if !word.is_empty() && !words.iter().any(|w| w == word) {
+            words.push(word.to_string());
         }
-        hasher.update(&buffer[..bytes_read]);
     }
+    words
+}
+
+/// Strength from recommended settings of model version, otherwise the median weight
+/// this version is used with in its own gallery images
+pub fn recommended_weight(info: &Value) -> Option<f64> {
+    if let Some(strength) = info["settings"]["strength"].as_f64() {
+        return Some(strength);
+    }
+
+    let version_id = info["id"].as_i64()?;
+    let mut weights = info["images"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|image| image["meta"]["civitaiResources"].as_array())
+        .flatten()
+        .filter(|r| r["modelVersionId"].as_i64() == Some(version_id))
+        .filter_map(|r| r["weight"].as_f64())
+        .collect::<Vec<_>>();
+    if weights.is_empty() {
+        return None;
+    }
+    weights.sort_by(|a, b| a.total_cmp(b));
+    Some(weights[weights.len() / 2])
+}
 
-    let result = hasher.finalize();
-    Ok(hex::encode(result)[..10].to_string())
+/// BLAKE3 and SHA256 of data fed in chunks
+#[derive(Default)]
+pub struct FileHasher {
+    blake3: blake3::Hasher,
+    sha256: Sha256,
 }
 
-fn calculate_blake3_hash(file_path: &PathBuf) -> std::io::Result<String> {
-    let file = File::open(file_path)?;
-    let mut reader = BufReader::new(file);
-    let mut hasher = blake3::Hasher::new();
-    let mut buffer = [0u8; 8192];
+impl FileHasher {
+    pub fn update(&mut self, data: &[u8]) {
+        self.blake3.update(data);
+        self.sha256.update(data);
+    }
 
-    loop {
-        let bytes_read = reader.read(&mut buffer)?;
-        if bytes_read == 0 {
-            break;
+    /// Feed whole content of file
+    pub fn update_file(&mut self, file_path: &Path) -> std::io::Result<()> {
+        let mut reader = BufReader::new(File::open(file_path)?);
+        let mut buffer = vec![0u8; HASH_BUFFER_SIZE];
+        loop {
+            let bytes_read = reader.read(&mut buffer)?;
+            if bytes_read == 0 {
+                return Ok(());
+            }
+            self.update(&buffer[..bytes_read]);
         }
-        hasher.update(&buffer[..bytes_read]);
     }
 
-    let result = hasher.finalize();
-    Ok(result.to_hex().to_string())
+    pub fn finish(self) -> FileHashes {
+        let sha256 = hex::encode(self.sha256.finalize());
+        FileHashes {
+            blake3: self.blake3.finalize().to_hex().to_string(),
+            autov2: calculate_autov2_hash(&sha256),
+            sha256,
+        }
+    }
 }
 
-fn generate_video_thumbnail(file_path: &PathBuf, overwrite: bool) -> anyhow::Result<()> {
-    let mut thumbnail_path = file_path.clone();
+/// Calculate BLAKE3, SHA256 and AutoV2 hash of file in one read
+pub fn calculate_hashes(file_path: &Path) -> std::io::Result<FileHashes> {
+    let mut hasher = FileHasher::default();
+    hasher.update_file(file_path)?;
+    Ok(hasher.finish())
+}
+
+/// AutoV2 hash is the first 10 characters of SHA256
+pub(crate) fn calculate_autov2_hash(sha256: &str) -> String {
+    sha256.chars().take(10).collect()
+}
+
+fn generate_video_thumbnail(file_path: &Path, overwrite: bool) -> anyhow::Result<()> {
+    let mut thumbnail_path = file_path.to_path_buf();
     thumbnail_path.set_extension("jpeg");
     if !overwrite && thumbnail_path.exists() {
         return Ok(());
@@ -204,7 +600,7 @@ fn generate_video_thumbnail(file_path: &PathBuf, overwrite: bool) -> anyhow::Res
             r#"select=not(mod(n\,3000)),scale=300:ih*300/iw"#,
             "-q:v",
             "10",
-            &thumbnail_path.to_str().unwrap_or_default(),
+            thumbnail_path.to_str().unwrap_or_default(),
         ])
         .status()?;
 
@@ -223,3 +619,232 @@ async fn file_type(path: &str) -> FileType {
 
     FileType::NA
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job::{JobKind, JobManager};
+    use crate::testing::{self, memory_pool, serve, TempDir, LABEL};
+    use actix_web::{web, HttpRequest, HttpResponse};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Collection with an empty safetensors file for each name
+    fn collection(dir: &TempDir, names: &[&str]) -> Config {
+        let mut config = testing::collection(dir, names);
+        config.civitai.save_json = false;
+        config.civitai.requests_per_second = 0.0;
+        config.civitai.retry_base_delay_ms = 1;
+        config
+    }
+
+    fn blake3_of(dir: &TempDir, name: &str) -> String {
+        calculate_hashes(&dir.path().join(format!("{}.safetensors", name)))
+            .unwrap()
+            .blake3
+    }
+
+    fn version(blake3: &str) -> Value {
+        json!({
+            "id": 11,
+            "modelId": 10,
+            "name": "v1",
+            "model": {"name": "Model", "type": "LORA", "nsfw": false, "poi": false},
+            "files": [{"name": "a.safetensors", "primary": true, "hashes": {"BLAKE3": blake3.to_uppercase()}}],
+            "images": [],
+        })
+    }
+
+    /// Scan collection, then sync it with Civitai stand-in at `base_url`
+    async fn sync(config: &mut Config, pool: &SqlitePool, base_url: String) {
+        config.civitai.base_url = base_url;
+        testing::scan(config, pool).await;
+
+        let job = JobManager::default().start(JobKind::CivitaiSync).unwrap();
+        let client = CivitaiClient::new(&config.civitai).unwrap();
+        let providers = Providers::new(config, pool);
+        update_model_info(config.clone(), pool, &client, &providers, &job, false)
+            .await
+            .unwrap();
+    }
+
+    async fn status(pool: &SqlitePool, name: &str) -> (String, Option<i64>) {
+        let (id, _) = item::get_fingerprint(pool, &format!("{}.safetensors", name), LABEL)
+            .await
+            .unwrap()
+            .unwrap();
+        let lookup = civitai_lookup::get(pool, id).await.unwrap().unwrap();
+        (lookup.status, lookup.http_status)
+    }
+
+    #[actix_web::test]
+    async fn sync_found_and_not_found() {
+        let dir = TempDir::new();
+        let mut config = collection(&dir, &["a", "b"]);
+        let pool = memory_pool().await;
+        let found = version(&blake3_of(&dir, "a"));
+        let base_url = serve(move |cfg| {
+            let found = found.clone();
+            cfg.route(
+                "/model-versions/by-hash",
+                web::post().to(move |hashes: web::Json<Vec<String>>| {
+                    let found = found.clone();
+                    async move {
+                        assert_eq!(hashes.len(), 2);
+                        HttpResponse::Ok().json(vec![found])
+                    }
+                }),
+            );
+        });
+
+        sync(&mut config, &pool, base_url).await;
+
+        assert_eq!(status(&pool, "a").await, ("found".to_string(), Some(200)));
+        assert_eq!(status(&pool, "b").await, ("not_found".to_string(), Some(404)));
+        let (id, _) = item::get_fingerprint(&pool, "a.safetensors", LABEL)
+            .await
+            .unwrap()
+            .unwrap();
+        let info = read_info(&pool, Some(id), &dir.path().join("a.safetensors"))
+            .await
+            .unwrap();
+        assert!(info.contains("\"modelId\": 10"));
+        assert!(!dir.path().join("a.json").exists());
+    }
+
+    #[actix_web::test]
+    async fn preview_and_gallery_without_sidecar() {
+        let dir = TempDir::new();
+        let mut config = collection(&dir, &["a"]);
+        config.civitai.gallery.enabled = true;
+        let pool = memory_pool().await;
+        let blake3 = blake3_of(&dir, "a");
+        let base_url = serve(move |cfg| {
+            let blake3 = blake3.clone();
+            cfg.route(
+                "/model-versions/by-hash",
+                web::post().to(move |request: HttpRequest| {
+                    let mut found = version(&blake3);
+                    found["images"] =
+                        json!([{"url": format!("http://{}/1.jpeg", request.connection_info().host()), "nsfwLevel": 1}]);
+                    async move { HttpResponse::Ok().json(vec![found]) }
+                }),
+            )
+            .route("/1.jpeg", web::get().to(|| async { HttpResponse::Ok().body("image") }));
+        });
+
+        sync(&mut config, &pool, base_url).await;
+
+        assert_eq!(status(&pool, "a").await, ("found".to_string(), Some(200)));
+        assert!(!dir.path().join("a.json").exists());
+        assert_eq!(std::fs::read(dir.path().join("a.jpeg")).unwrap(), b"image");
+        assert!(gallery::gallery_dir(&dir.path().join("a.safetensors"))
+            .join("000-1.jpeg")
+            .exists());
+    }
+
+    #[actix_web::test]
+    async fn sync_retries_after_too_many_requests() {
+        let dir = TempDir::new();
+        let mut config = collection(&dir, &["a"]);
+        let pool = memory_pool().await;
+        let found = version(&blake3_of(&dir, "a"));
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let base_url = serve(move |cfg| {
+            let (found, counter) = (found.clone(), counter.clone());
+            cfg.route(
+                "/model-versions/by-hash",
+                web::post().to(move || {
+                    let (found, counter) = (found.clone(), counter.clone());
+                    async move {
+                        if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                            HttpResponse::TooManyRequests()
+                                .insert_header(("Retry-After", "1"))
+                                .finish()
+                        } else {
+                            HttpResponse::Ok().json(vec![found])
+                        }
+                    }
+                }),
+            );
+        });
+
+        let started = std::time::Instant::now();
+        sync(&mut config, &pool, base_url).await;
+
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert!(started.elapsed() >= std::time::Duration::from_secs(1));
+        assert_eq!(status(&pool, "a").await, ("found".to_string(), Some(200)));
+    }
+
+    #[actix_web::test]
+    async fn sync_batch_not_found() {
+        let dir = TempDir::new();
+        let mut config = collection(&dir, &["a", "b", "c"]);
+        let pool = memory_pool().await;
+        let (a, c) = (blake3_of(&dir, "a"), blake3_of(&dir, "c"));
+        let found = version(&a);
+        let base_url = serve(move |cfg| {
+            let (a, c, found) = (a.clone(), c.clone(), found.clone());
+            cfg.route("/model-versions/by-hash", web::post().to(HttpResponse::NotFound))
+                .route(
+                    "/model-versions/by-hash/{hash}",
+                    web::get().to(move |hash: web::Path<String>| {
+                        let response = if hash.eq_ignore_ascii_case(&a) {
+                            HttpResponse::Ok().json(&found)
+                        } else if hash.eq_ignore_ascii_case(&c) {
+                            HttpResponse::InternalServerError().finish()
+                        } else {
+                            HttpResponse::NotFound().finish()
+                        };
+                        async move { response }
+                    }),
+                );
+        });
+
+        sync(&mut config, &pool, base_url).await;
+
+        assert_eq!(status(&pool, "a").await, ("found".to_string(), Some(200)));
+        assert_eq!(status(&pool, "b").await, ("not_found".to_string(), Some(404)));
+        assert_eq!(status(&pool, "c").await, ("error".to_string(), Some(500)));
+    }
+
+    #[actix_web::test]
+    async fn sync_follows_provider_order() {
+        let dir = TempDir::new();
+        let mut config = collection(&dir, &["a", "b"]);
+        let overrides = TempDir::new();
+        let path = overrides.path().join("overrides.json");
+        std::fs::write(&path, format!(r#"{{"{}": {{"name": "Local"}}}}"#, blake3_of(&dir, "a"))).unwrap();
+        config.providers.local_overrides = path.to_string_lossy().to_string();
+        let pool = memory_pool().await;
+        let b = blake3_of(&dir, "b");
+        let base_url = serve(move |cfg| {
+            let b = b.clone();
+            cfg.route(
+                "/model-versions/by-hash",
+                web::post().to(move |hashes: web::Json<Vec<String>>| {
+                    // Item identified by local overrides is not looked up
+                    assert_eq!(hashes.as_slice(), [b.as_str()]);
+                    async { HttpResponse::Ok().json(Vec::<Value>::new()) }
+                }),
+            );
+        });
+
+        sync(&mut config, &pool, base_url.clone()).await;
+        let (a, _) = item::get_fingerprint(&pool, "a.safetensors", LABEL)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(civitai_lookup::get(&pool, a).await.unwrap().is_none());
+        assert_eq!(status(&pool, "b").await, ("not_found".to_string(), Some(404)));
+
+        // Civitai is not asked at all when it is not a provider
+        config.providers.order = vec![ProviderKind::Local];
+        config.civitai.not_found_retry_days = 0;
+        sync(&mut config, &pool, "http://127.0.0.1:1".to_string()).await;
+        assert_eq!(status(&pool, "b").await, ("not_found".to_string(), Some(404)));
+    }
+}
@@ -0,0 +1,119 @@
+use super::client::CivitaiClient;
+use crate::db::item::{self, CivitaiMatch};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::SqlitePool;
+
+/// Number of models per page if not given
+const DEFAULT_LIMIT: u32 = 20;
+
+/// Civitai rejects a larger page
+const MAX_LIMIT: u32 = 100;
+
+/// Filters of Civitai model search. Empty values are not sent.
+#[derive(Default)]
+pub struct SearchQuery {
+    pub query: String,
+    /// Model type, e.g. `Checkpoint`, `LORA`
+    pub model_type: String,
+    /// e.g. `SDXL 1.0`, `Illustrious`
+    pub base_model: String,
+    /// `Highest Rated`, `Most Downloaded` or `Newest`
+    pub sort: String,
+    /// Include NSFW models
+    pub nsfw: bool,
+    /// Cursor of next page returned by previous search
+    pub cursor: String,
+    /// Models per page, at most 100
+    pub limit: u32,
+}
+
+#[derive(Serialize)]
+pub struct SearchResult {
+    /// Models as returned by Civitai. Each model has `in_library`, each of its versions `library_items`.
+    pub items: Vec<Value>,
+    pub next_cursor: Option<String>,
+}
+
+/// Search models on Civitai and mark versions already in library, by version ID or file hash
+pub async fn search(pool: &SqlitePool, client: &CivitaiClient, query: &SearchQuery) -> anyhow::Result<SearchResult> {
+    let limit = if query.limit > 0 { query.limit.min(MAX_LIMIT) } else { DEFAULT_LIMIT };
+    let mut params = vec![("limit", limit.to_string()), ("nsfw", query.nsfw.to_string())];
+    for (key, value) in [
+        ("query", &query.query),
+        ("types", &query.model_type),
+        ("baseModels", &query.base_model),
+        ("sort", &query.sort),
+        ("cursor", &query.cursor),
+    ] {
+        if !value.is_empty() {
+            params.push((key, value.clone()));
+        }
+    }
+
+    let mut result = client.search_models(&params).await?;
+    let next_cursor = match &result["metadata"]["nextCursor"] {
+        Value::String(cursor) => Some(cursor.clone()),
+        Value::Number(cursor) => Some(cursor.to_string()),
+        _ => None,
+    };
+    let mut items = match result["items"].take() {
+        Value::Array(items) => items,
+        _ => Vec::new(),
+    };
+
+    let mut version_ids = Vec::new();
+    let mut hashes = Vec::new();
+    for version in items.iter().flat_map(versions) {
+        version_ids.extend(version["id"].as_i64());
+        hashes.extend(file_hashes(version));
+    }
+    let matches = item::find_civitai_matches(pool, &version_ids, &hashes).await?;
+
+    for model in items.iter_mut() {
+        let mut in_library = false;
+        for version in versions_mut(model) {
+            let library_items = library_items(version, &matches);
+            in_library |= !library_items.is_empty();
+            version["library_items"] = library_items.into();
+        }
+        model["in_library"] = in_library.into();
+    }
+
+    Ok(SearchResult { items, next_cursor })
+}
+
+fn versions(model: &Value) -> impl Iterator<Item = &Value> {
+    model["modelVersions"].as_array().into_iter().flatten()
+}
+
+fn versions_mut(model: &mut Value) -> impl Iterator<Item = &mut Value> {
+    model["modelVersions"].as_array_mut().into_iter().flatten()
+}
+
+/// Lowercase BLAKE3 and SHA256 of all files of a version
+fn file_hashes(version: &Value) -> Vec<String> {
+    version["files"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|file| [&file["hashes"]["BLAKE3"], &file["hashes"]["SHA256"]])
+        .filter_map(|hash| hash.as_str())
+        .map(|hash| hash.to_lowercase())
+        .collect()
+}
+
+/// IDs of items matching version
+fn library_items(version: &Value, matches: &[CivitaiMatch]) -> Vec<i64> {
+    let version_id = version["id"].as_i64();
+    let hashes = file_hashes(version);
+    matches
+        .iter()
+        .filter(|m| {
+            (version_id.is_some() && m.civitai_version_id == version_id)
+                || hashes.contains(&m.blake3)
+                || m.sha256.as_ref().is_some_and(|sha256| hashes.contains(sha256))
+        })
+        .map(|m| m.id)
+        .collect()
+}
@@ -0,0 +1,335 @@
+use crate::config::CivitaiConfig;
+use bytes::Bytes;
+use reqwest::header::{HeaderValue, AUTHORIZATION};
+use reqwest::header::{RANGE, RETRY_AFTER};
+use reqwest::{Client, Method, Proxy, RequestBuilder, Response, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::cmp::{max, min};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::{sleep, sleep_until, Instant};
+use tracing::warn;
+
+/// Backoff delay never grows over this
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+#[derive(Debug)]
+pub enum CivitaiError {
+    /// Civitai has no model matching the request
+    NotFound,
+    /// Other non-success response, with its body
+    Status(StatusCode, String),
+    /// Connection, timeout or invalid response body
+    Request(reqwest::Error),
+}
+
+impl CivitaiError {
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            CivitaiError::NotFound => Some(StatusCode::NOT_FOUND),
+            CivitaiError::Status(status, _) => Some(*status),
+            CivitaiError::Request(e) => e.status(),
+        }
+    }
+}
+
+impl fmt::Display for CivitaiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CivitaiError::NotFound => write!(f, "Not found on Civitai"),
+            CivitaiError::Status(status, body) => write!(f, "Civitai returned {}: {}", status, body),
+            CivitaiError::Request(e) => write!(f, "Civitai request failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CivitaiError {}
+
+impl From<reqwest::Error> for CivitaiError {
+    fn from(e: reqwest::Error) -> Self {
+        CivitaiError::Request(e)
+    }
+}
+
+struct RateState {
+    /// Earliest time the next API request may be sent
+    next_slot: Instant,
+    paused_until: Instant,
+}
+
+/// All requests to Civitai API and its image CDN go through this client
+/// so base URL, timeouts, user agent and proxy are set in one place.
+#[derive(Clone)]
+pub struct CivitaiClient {
+    client: Client,
+    base_url: String,
+    /// `Authorization` header with API key, only sent to the host of `base_url`
+    authorization: Option<HeaderValue>,
+    /// Shared by clones so all tasks respect one limit
+    rate: Arc<Mutex<RateState>>,
+    interval: Duration,
+    max_retries: u32,
+    retry_base_delay: Duration,
+    download_timeout: Duration,
+}
+
+impl CivitaiClient {
+    pub fn new(config: &CivitaiConfig) -> anyhow::Result<Self> {
+        let authorization = if config.api_key.is_empty() {
+            None
+        } else {
+            let mut value = HeaderValue::from_str(&format!("Bearer {}", config.api_key))?;
+            value.set_sensitive(true);
+            Some(value)
+        };
+
+        let mut builder = Client::builder()
+            .user_agent(&config.user_agent)
+            .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+            .timeout(Duration::from_secs(config.timeout_secs));
+        if let Some(proxy) = &config.proxy {
+            builder = builder.proxy(Proxy::all(proxy)?);
+        }
+
+        let interval = config.request_interval()?;
+
+        Ok(Self {
+            client: builder.build()?,
+            base_url: config.base_url.trim_end_matches('/').to_string(),
+            authorization,
+            rate: Arc::new(Mutex::new(RateState {
+                next_slot: Instant::now(),
+                paused_until: Instant::now(),
+            })),
+            interval,
+            max_retries: config.max_retries,
+            retry_base_delay: Duration::from_millis(config.retry_base_delay_ms),
+            download_timeout: Duration::from_secs(config.download_timeout_secs),
+        })
+    }
+
+    /// Request with API key if `url` is on the API host. Images and files on other hosts, e.g. the CDN or
+    /// a download URL given by the API, do not get the key. Redirects to other hosts drop it as well.
+    fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        let request = self.client.request(method, url);
+        match &self.authorization {
+            Some(authorization) if same_origin(url, &self.base_url) => request.header(AUTHORIZATION, authorization),
+            _ => request,
+        }
+    }
+
+    /// Wait until the rate limit allows one more API request.
+    /// A slot reserved before a pause is given up and reserved again after the pause.
+    async fn wait_for_slot(&self) {
+        loop {
+            let slot = {
+                let mut rate = self.rate.lock().await;
+                let slot = max(rate.next_slot, Instant::now());
+                rate.next_slot = slot + self.interval;
+                slot
+            };
+            sleep_until(slot).await;
+
+            if self.rate.lock().await.paused_until <= Instant::now() {
+                return;
+            }
+        }
+    }
+
+    /// Hold all API requests back, e.g. when Civitai asks to retry later
+    async fn delay_slots(&self, delay: Duration) {
+        let mut rate = self.rate.lock().await;
+        let until = Instant::now() + delay;
+        rate.paused_until = max(rate.paused_until, until);
+        rate.next_slot = max(rate.next_slot, until);
+    }
+
+    /// Send request, retrying on 429, 5xx and connection errors with exponential backoff.
+    /// `Retry-After` of the response is used instead of the backoff delay if present.
+    async fn send(&self, request: RequestBuilder, rate_limited: bool) -> Result<Response, CivitaiError> {
+        let mut attempt = 0;
+        loop {
+            let Some(req) = request.try_clone() else {
+                return Ok(request.send().await?);
+            };
+            if rate_limited {
+                self.wait_for_slot().await;
+            }
+
+            let delay = match req.send().await {
+                Ok(response) => {
+                    let status = response.status();
+                    if !(status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error())
+                        || attempt >= self.max_retries
+                    {
+                        return Ok(response);
+                    }
+                    let delay = retry_after(&response).unwrap_or_else(|| self.backoff(attempt));
+                    warn!("Civitai returned {}, retry in {:?}", status, delay);
+                    delay
+                }
+                Err(e) if (e.is_timeout() || e.is_connect()) && attempt < self.max_retries => {
+                    let delay = self.backoff(attempt);
+                    warn!("Civitai request failed: {}, retry in {:?}", e, delay);
+                    delay
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            if rate_limited {
+                self.delay_slots(delay).await;
+            } else {
+                sleep(delay).await;
+            }
+            attempt += 1;
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        min(self.retry_base_delay * 2u32.saturating_pow(attempt), MAX_RETRY_DELAY)
+    }
+
+    /// Model versions of many files in one request. Hashes without a match are left out of the result.
+    pub async fn get_model_versions_by_hashes(&self, hashes: &[&str]) -> Result<Vec<Value>, CivitaiError> {
+        let url = format!("{}/model-versions/by-hash", self.base_url);
+        let response = self.send(self.request(Method::POST, &url).json(hashes), true).await?;
+
+        json_response(response).await
+    }
+
+    /// Model version of one file by any of its hashes
+    pub async fn get_model_version_by_hash(&self, hash: &str) -> Result<Value, CivitaiError> {
+        let url = format!("{}/model-versions/by-hash/{}", self.base_url, hash);
+        let response = self.send(self.request(Method::GET, &url), true).await?;
+
+        json_response(response).await
+    }
+
+    /// Model with all its versions, newest first
+    pub async fn get_model(&self, model_id: i64) -> Result<Value, CivitaiError> {
+        let url = format!("{}/models/{}", self.base_url, model_id);
+        let response = self.send(self.request(Method::GET, &url), true).await?;
+
+        json_response(response).await
+    }
+
+    /// Search models. `params` are query parameters of Civitai `/models` endpoint, e.g. `query`, `types`, `cursor`.
+    pub async fn search_models(&self, params: &[(&str, String)]) -> Result<Value, CivitaiError> {
+        let url = format!("{}/models", self.base_url);
+        let response = self.send(self.request(Method::GET, &url).query(params), true).await?;
+
+        json_response(response).await
+    }
+
+    pub async fn get_model_version(&self, version_id: i64) -> Result<Value, CivitaiError> {
+        let url = format!("{}/model-versions/{}", self.base_url, version_id);
+        let response = self.send(self.request(Method::GET, &url), true).await?;
+
+        json_response(response).await
+    }
+
+    /// Start downloading a model file from byte `offset`, to resume a partial download.
+    /// The server may ignore the range and send the whole file, check the response status.
+    pub async fn download_from(&self, url: &str, offset: u64) -> Result<Response, CivitaiError> {
+        let mut request = self.request(Method::GET, url).timeout(self.download_timeout);
+        if offset > 0 {
+            request = request.header(RANGE, format!("bytes={}-", offset));
+        }
+
+        self.send(request, false).await
+    }
+
+    /// Download a file, e.g. preview image, by its full URL
+    pub async fn download(&self, url: &str) -> anyhow::Result<Bytes> {
+        let response = self
+            .send(self.request(Method::GET, url), false)
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+
+        Ok(response)
+    }
+}
+
+async fn json_response<T: DeserializeOwned>(response: Response) -> Result<T, CivitaiError> {
+    let status = response.status();
+    if status == StatusCode::NOT_FOUND {
+        return Err(CivitaiError::NotFound);
+    }
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(CivitaiError::Status(status, body));
+    }
+
+    Ok(response.json().await?)
+}
+
+fn same_origin(a: &str, b: &str) -> bool {
+    match (Url::parse(a), Url::parse(b)) {
+        (Ok(a), Ok(b)) => a.origin() == b.origin(),
+        _ => false,
+    }
+}
+
+/// Delay asked by `Retry-After` header. Only the delay-seconds form is supported.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let secs = response
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(min(Duration::from_secs(secs), MAX_RETRY_DELAY))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use actix_web::{web, HttpRequest, HttpResponse};
+
+    /// Server answering every request with its `Authorization` header
+    fn echo_authorization() -> String {
+        testing::serve(|cfg| {
+            cfg.default_service(web::to(|req: HttpRequest| async move {
+                let authorization = req
+                    .headers()
+                    .get("authorization")
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or_default()
+                    .to_string();
+                HttpResponse::Ok().json(authorization)
+            }));
+        })
+    }
+
+    #[actix_web::test]
+    async fn api_key_only_sent_to_api_host() {
+        let api = echo_authorization();
+        let cdn = echo_authorization();
+        let client = CivitaiClient::new(&CivitaiConfig {
+            base_url: format!("{}/api/v1", api),
+            api_key: "secret".to_string(),
+            requests_per_second: 0.0,
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(client.get_model(1).await.unwrap(), "Bearer secret");
+        let download = client
+            .download(&format!("{}/api/download/models/1", api))
+            .await
+            .unwrap();
+        assert_eq!(&download[..], br#""Bearer secret""#);
+        let image = client.download(&format!("{}/image.jpeg", cdn)).await.unwrap();
+        assert_eq!(&image[..], br#""""#);
+        let file = client.download_from(&format!("{}/file", cdn), 0).await.unwrap();
+        assert_eq!(file.text().await.unwrap(), r#""""#);
+    }
+}
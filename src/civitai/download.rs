@@ -0,0 +1,435 @@
+use super::client::CivitaiClient;
+use super::{save_model_info, FileHasher, FileHashes};
+use crate::config::Config;
+use crate::db::hash;
+use crate::db::item::{self, SyncItem};
+use crate::job::Job;
+use crate::provider::Providers;
+use crate::scanner::{get_fingerprint, get_relative_path, index_file};
+use reqwest::{Response, StatusCode, Url};
+use serde_json::Value;
+use sqlx::SqlitePool;
+use std::cmp::max;
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::task::spawn_blocking;
+use tokio::time::{sleep_until, Instant};
+use tracing::{info, warn};
+
+const PART_EXT: &str = "part";
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// What to download, parsed from user input
+#[derive(Debug, PartialEq)]
+pub enum DownloadSource {
+    /// Latest version of a model
+    Model(i64),
+    Version(i64),
+}
+
+/// Accept a version ID, a Civitai model page or download URL, or an AIR URN
+/// like `urn:air:sdxl:lora:civitai:328553@368189`.
+pub fn parse_source(source: &str) -> anyhow::Result<DownloadSource> {
+    let source = source.trim();
+    if let Ok(id) = source.parse() {
+        return Ok(DownloadSource::Version(id));
+    }
+
+    if let Some(air) = source.strip_prefix("urn:air:") {
+        // <ecosystem>:<type>:<source>:<id>[@<version>], ecosystem and type are optional
+        let parts = air.split(':').collect::<Vec<_>>();
+        let (Some(id), Some(&"civitai")) = (parts.last(), parts.iter().rev().nth(1)) else {
+            return Err(anyhow::anyhow!("Not a Civitai AIR: {}", source));
+        };
+        let id = id.split('.').next().unwrap_or_default();
+        return match id.split_once('@') {
+            Some((_, version)) => Ok(DownloadSource::Version(version.parse()?)),
+            None => Ok(DownloadSource::Model(id.parse()?)),
+        };
+    }
+
+    let url = Url::parse(source)?;
+    if let Some((_, version)) = url.query_pairs().find(|(k, _)| k == "modelVersionId") {
+        return Ok(DownloadSource::Version(version.parse()?));
+    }
+    let segments = url.path_segments().map(|s| s.collect::<Vec<_>>()).unwrap_or_default();
+    match segments.as_slice() {
+        ["models", id, ..] => Ok(DownloadSource::Model(id.parse()?)),
+        ["api", "download", "models", id, ..] | ["api", "v1", "model-versions", id, ..] => {
+            Ok(DownloadSource::Version(id.parse()?))
+        }
+        _ => Err(anyhow::anyhow!("Unsupported Civitai URL: {}", source)),
+    }
+}
+
+/// Where to download from and to
+pub struct DownloadRequest {
+    pub source: String,
+    pub label: String,
+    pub subfolder: String,
+}
+
+/// Progress of a running download, read by the download queue
+#[derive(Default)]
+pub struct DownloadProgress {
+    /// Relative path of target file in collection, empty until version info is fetched
+    pub path: Mutex<String>,
+    pub downloaded: AtomicU64,
+    pub total: AtomicU64,
+}
+
+/// State shared by all running downloads: their target paths and the bandwidth limit
+#[derive(Default)]
+pub struct Transfers {
+    paths: Mutex<HashSet<PathBuf>>,
+    /// Earliest time the next chunk may be read. None for no limit.
+    next_read: Option<tokio::sync::Mutex<Instant>>,
+    bytes_per_second: u64,
+}
+
+impl Transfers {
+    /// Limit speed of all downloads together. 0 for no limit.
+    pub fn new(bytes_per_second: u64) -> Self {
+        Self {
+            paths: Mutex::new(HashSet::new()),
+            next_read: (bytes_per_second > 0).then(|| tokio::sync::Mutex::new(Instant::now())),
+            bytes_per_second,
+        }
+    }
+
+    /// Fail if another download already has `path`. It is released when the returned guard is dropped.
+    pub fn reserve(&self, path: &Path) -> anyhow::Result<Reservation<'_>> {
+        if !self.paths.lock().unwrap().insert(path.to_path_buf()) {
+            return Err(anyhow::anyhow!("Another download is writing {}", path.display()));
+        }
+        Ok(Reservation {
+            transfers: self,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Wait until `bytes` more can be received within the bandwidth limit
+    async fn throttle(&self, bytes: usize) {
+        let Some(next_read) = &self.next_read else {
+            return;
+        };
+        let until = {
+            let mut next = next_read.lock().await;
+            let start = max(*next, Instant::now());
+            *next = start + Duration::from_nanos((bytes as u64).saturating_mul(NANOS_PER_SEC) / self.bytes_per_second);
+            *next
+        };
+        sleep_until(until).await;
+    }
+}
+
+pub struct Reservation<'a> {
+    transfers: &'a Transfers,
+    path: PathBuf,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        self.transfers.paths.lock().unwrap().remove(&self.path);
+    }
+}
+
+/// Download primary file of a model version into `subfolder` of a collection, verify its hash,
+/// save its json sidecar and preview, then index it.
+/// A `.part` file left by an interrupted download is resumed.
+#[allow(clippy::too_many_arguments)]
+pub async fn download(
+    config: &Config,
+    pool: &SqlitePool,
+    client: &CivitaiClient,
+    providers: &Providers,
+    job: &Job,
+    request: &DownloadRequest,
+    progress: &DownloadProgress,
+    transfers: &Transfers,
+) -> anyhow::Result<()> {
+    let label = request.label.as_str();
+    let Some(base_path) = config.model_paths.get(label) else {
+        return Err(anyhow::anyhow!("Unknown collection {}", label));
+    };
+    let subfolder = Path::new(&request.subfolder);
+    if !subfolder.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(anyhow::anyhow!("Invalid subfolder {}", subfolder.display()));
+    }
+
+    let version_id = match parse_source(&request.source)? {
+        DownloadSource::Version(id) => id,
+        DownloadSource::Model(id) => client.get_model(id).await?["modelVersions"][0]["id"]
+            .as_i64()
+            .ok_or_else(|| anyhow::anyhow!("Model {} has no version", id))?,
+    };
+    let info = client.get_model_version(version_id).await?;
+    let file = primary_file(&info).ok_or_else(|| anyhow::anyhow!("Version {} has no model file", version_id))?;
+    let (Some(name), Some(url)) = (file["name"].as_str(), file["downloadUrl"].as_str()) else {
+        return Err(anyhow::anyhow!("Version {} has no download URL", version_id));
+    };
+    // Name comes from remote, only keep its last component
+    let name = Path::new(name)
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("Invalid file name {}", name))?;
+
+    let dir = PathBuf::from(base_path).join(subfolder);
+    let path = dir.join(name);
+    let _reservation = transfers.reserve(&path)?;
+    if path.exists() {
+        return Err(anyhow::anyhow!("File already exists: {}", path.display()));
+    }
+    let relative_path = get_relative_path(base_path, &path)?;
+    *progress.path.lock().unwrap() = relative_path.clone();
+    fs::create_dir_all(&dir).await?;
+    let part = part_path(&path);
+
+    let size = file["sizeKB"]
+        .as_f64()
+        .map(|kb| (kb * 1024.0) as u64)
+        .unwrap_or_default();
+    progress.total.store(size, Ordering::Relaxed);
+    check_free_space(config, &dir, &part, size).await?;
+
+    info!("Download version {} to {}", version_id, path.display());
+    job.inc_seen();
+    let hashes = download_part(client, transfers, job, url, &part, progress).await?;
+    if let Err(e) = verify(file, &hashes) {
+        // Resuming a corrupted part would fail again
+        fs::remove_file(&part).await?;
+        return Err(e);
+    }
+    fs::rename(&part, &path).await?;
+    job.inc_hashed();
+
+    // Hashes are already known, so indexing does not read the file again
+    let fingerprint = get_fingerprint(&path).await?;
+    hash::insert_or_update(
+        pool,
+        &relative_path,
+        label,
+        fingerprint.size,
+        fingerprint.mtime,
+        &hashes,
+    )
+    .await?;
+    index_file(config, pool, providers, label, &relative_path, &fingerprint, Some(job)).await;
+
+    let Some((id, _)) = item::get_fingerprint(pool, &relative_path, label).await? else {
+        return Err(anyhow::anyhow!("Downloaded file is not indexed: {}", path.display()));
+    };
+    let item = SyncItem {
+        id,
+        path: relative_path,
+        base_label: label.to_string(),
+        blake3: hashes.blake3,
+    };
+    save_model_info(config, pool, client, providers, job, &item, &info).await;
+
+    Ok(())
+}
+
+/// Unfinished download of `path`
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".");
+    part.push(PART_EXT);
+    PathBuf::from(part)
+}
+
+/// Fail if the rest of the file would leave less than `min_free_space_mb` in target directory
+pub async fn check_free_space(config: &Config, dir: &Path, part: &Path, size: u64) -> anyhow::Result<()> {
+    let downloaded = fs::metadata(part).await.map(|m| m.len()).unwrap_or(0);
+    let needed = size.saturating_sub(downloaded) + config.download.min_free_space_mb * 1024 * 1024;
+    let available = fs4::available_space(dir)?;
+    if available < needed {
+        return Err(anyhow::anyhow!(
+            "Not enough free space in {}: {} MB available, {} MB needed",
+            dir.display(),
+            available / 1024 / 1024,
+            needed / 1024 / 1024
+        ));
+    }
+
+    Ok(())
+}
+
+/// Primary file of model version, or its first model file
+fn primary_file(info: &Value) -> Option<&Value> {
+    let files = info["files"].as_array()?;
+    files
+        .iter()
+        .find(|f| f["primary"].as_bool() == Some(true))
+        .or_else(|| files.iter().find(|f| f["type"].as_str() == Some("Model")))
+        .or_else(|| files.first())
+}
+
+/// Append the rest of the file to `part` and return hashes of the whole file
+async fn download_part(
+    client: &CivitaiClient,
+    transfers: &Transfers,
+    job: &Job,
+    url: &str,
+    part: &Path,
+    progress: &DownloadProgress,
+) -> anyhow::Result<FileHashes> {
+    let offset = fs::metadata(part).await.map(|m| m.len()).unwrap_or(0);
+    let response = client.download_from(url, offset).await?;
+    write_part(transfers, job, response, offset, part, progress).await
+}
+
+/// Write response of a download started from byte `offset` into `part`, within the bandwidth limit,
+/// and return hashes of the whole file. The server may ignore the range and send the whole file.
+pub async fn write_part(
+    transfers: &Transfers,
+    job: &Job,
+    mut response: Response,
+    offset: u64,
+    part: &Path,
+    progress: &DownloadProgress,
+) -> anyhow::Result<FileHashes> {
+    let status = response.status();
+    let resume = if status == StatusCode::PARTIAL_CONTENT {
+        info!("Resume download from {} bytes: {}", offset, part.display());
+        true
+    } else if status == StatusCode::RANGE_NOT_SATISFIABLE && offset > 0 {
+        // Part is already complete
+        return Ok(hash_file_state(part).await?.finish());
+    } else if status.is_success() {
+        false
+    } else {
+        let body = response.text().await.unwrap_or_default();
+        return Err(anyhow::anyhow!("Download failed with {}: {}", status, body));
+    };
+
+    let downloaded = if resume { offset } else { 0 };
+    progress.downloaded.store(downloaded, Ordering::Relaxed);
+    if let Some(length) = response.content_length() {
+        progress.total.store(downloaded + length, Ordering::Relaxed);
+    }
+
+    let mut hasher = if resume { hash_file_state(part).await? } else { FileHasher::default() };
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resume)
+        .truncate(!resume)
+        .open(part)
+        .await?;
+    while let Some(chunk) = response.chunk().await? {
+        transfers.throttle(chunk.len()).await;
+        if job.is_cancelled() {
+            file.flush().await?;
+            return Err(anyhow::anyhow!(
+                "Download cancelled, {} is kept to resume",
+                part.display()
+            ));
+        }
+        hasher.update(&chunk);
+        file.write_all(&chunk).await?;
+        progress.downloaded.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+    }
+    file.flush().await?;
+
+    Ok(hasher.finish())
+}
+
+/// Compare hashes of downloaded file with those listed by Civitai
+fn verify(file: &Value, hashes: &FileHashes) -> anyhow::Result<()> {
+    let expected = [("BLAKE3", &hashes.blake3), ("SHA256", &hashes.sha256)];
+    let mut verified = false;
+    for (algo, actual) in expected {
+        if let Some(remote) = file["hashes"][algo].as_str() {
+            if !remote.eq_ignore_ascii_case(actual) {
+                return Err(anyhow::anyhow!(
+                    "{} mismatch: expected {}, got {}",
+                    algo,
+                    remote,
+                    actual
+                ));
+            }
+            verified = true;
+        }
+    }
+    if !verified {
+        warn!("No BLAKE3 or SHA256 to verify {}", file["name"]);
+    }
+
+    Ok(())
+}
+
+/// Hash content already downloaded, to continue hashing the rest while downloading
+pub async fn hash_file_state(path: &Path) -> anyhow::Result<FileHasher> {
+    let path = path.to_path_buf();
+    let hasher = spawn_blocking(move || -> std::io::Result<FileHasher> {
+        let mut hasher = FileHasher::default();
+        hasher.update_file(&path)?;
+        Ok(hasher)
+    })
+    .await??;
+
+    Ok(hasher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DownloadSource::{Model, Version};
+
+    #[test]
+    fn parse_source_ids_and_urls() {
+        assert_eq!(parse_source(" 368189 ").unwrap(), Version(368189));
+        assert_eq!(
+            parse_source("https://civitai.com/models/328553/some-lora").unwrap(),
+            Model(328553)
+        );
+        assert_eq!(
+            parse_source("https://civitai.com/models/328553/some-lora?modelVersionId=368189").unwrap(),
+            Version(368189)
+        );
+        assert_eq!(
+            parse_source("https://civitai.com/api/download/models/368189?type=Model&format=SafeTensor").unwrap(),
+            Version(368189)
+        );
+        assert_eq!(
+            parse_source("https://civitai.com/api/v1/model-versions/368189").unwrap(),
+            Version(368189)
+        );
+        assert!(parse_source("https://civitai.com/images/123").is_err());
+        assert!(parse_source("https://civitai.com/models/abc").is_err());
+        assert!(parse_source("some-lora").is_err());
+    }
+
+    #[test]
+    fn reserve_path_once() {
+        let transfers = Transfers::default();
+        let path = Path::new("/models/a.safetensors");
+        let reservation = transfers.reserve(path).unwrap();
+        assert!(transfers.reserve(path).is_err());
+        assert!(transfers.reserve(Path::new("/models/b.safetensors")).is_ok());
+        drop(reservation);
+        assert!(transfers.reserve(path).is_ok());
+    }
+
+    #[test]
+    fn parse_source_air() {
+        assert_eq!(
+            parse_source("urn:air:sdxl:lora:civitai:328553@368189").unwrap(),
+            Version(368189)
+        );
+        assert_eq!(parse_source("urn:air:sdxl:lora:civitai:328553").unwrap(), Model(328553));
+        assert_eq!(
+            parse_source("urn:air:sdxl:lora:civitai:328553@368189.safetensors").unwrap(),
+            Version(368189)
+        );
+        assert_eq!(parse_source("urn:air:civitai:328553").unwrap(), Model(328553));
+        assert!(parse_source("urn:air:flux1:checkpoint:huggingface:black-forest-labs/FLUX.1-dev").is_err());
+        assert!(parse_source("urn:air:sdxl:lora:civitai:328553@latest").is_err());
+    }
+}
@@ -0,0 +1,226 @@
+use super::client::CivitaiClient;
+use crate::config::GalleryConfig;
+use crate::db::gallery::{self, GalleryImage};
+use serde_json::Value;
+use sqlx::SqlitePool;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tracing::{info, warn};
+
+pub const GALLERY_EXT: &str = "gallery";
+
+/// Gallery images of a model are kept in `<model name>.gallery` next to the model file
+pub fn gallery_dir(model_path: &Path) -> PathBuf {
+    model_path.with_extension(GALLERY_EXT)
+}
+
+/// Download gallery images of model version allowed by `config` and save their generation parameters.
+/// Images already downloaded are not downloaded again.
+/// An image which fails to download is skipped, the others are still saved and the failures returned as error.
+pub async fn save_gallery(
+    config: &GalleryConfig,
+    pool: &SqlitePool,
+    client: &CivitaiClient,
+    item: i64,
+    model_path: &Path,
+    info: &Value,
+) -> anyhow::Result<()> {
+    let dir = gallery_dir(model_path);
+    let mut images = Vec::new();
+    let mut failed = 0;
+    let allowed = info["images"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|image| image["nsfwLevel"].as_u64().unwrap_or(0) <= config.max_nsfw_level as u64);
+    for (i, image) in allowed.enumerate() {
+        if config.max_images > 0 && i >= config.max_images {
+            break;
+        }
+        let Some(url) = image["url"].as_str() else {
+            continue;
+        };
+
+        let file = file_name(i, url);
+        let path = dir.join(&file);
+        if !path.exists() {
+            if let Err(e) = download_image(client, url, &path).await {
+                warn!("Failed to download gallery image {}: {}", url, e);
+                failed += 1;
+                continue;
+            }
+        }
+        images.push(parse_image(file, url, image));
+    }
+
+    if !images.is_empty() {
+        info!("Saved {} gallery images: {}", images.len(), dir.display());
+    }
+    gallery::replace(pool, item, &images).await?;
+
+    if failed > 0 {
+        return Err(anyhow::anyhow!("Failed to download {} gallery images", failed));
+    }
+    Ok(())
+}
+
+/// A partly written image is removed, so it is downloaded again next time
+async fn download_image(client: &CivitaiClient, url: &str, path: &Path) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).await?;
+    }
+    let content = client.download(url).await?;
+    if let Err(e) = fs::write(path, content).await {
+        let _ = fs::remove_file(path).await;
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Keep gallery order and the name Civitai gives the image, e.g. `000-12345.jpeg`
+fn file_name(index: usize, url: &str) -> String {
+    let name = url
+        .split('/')
+        .next_back()
+        .unwrap_or_default()
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default();
+    if name.is_empty() {
+        format!("{:03}", index)
+    } else {
+        format!("{:03}-{}", index, name)
+    }
+}
+
+fn parse_image(file: String, url: &str, image: &Value) -> GalleryImage {
+    let meta = &image["meta"];
+    let text = |key: &str| meta[key].as_str().map(|s| s.to_string());
+    // Older images list resources in `resources`, on-site generations in `civitaiResources`
+    let resources = [&meta["resources"], &meta["civitaiResources"]]
+        .into_iter()
+        .find(|r| r.is_array())
+        .cloned()
+        .unwrap_or_else(|| Value::Array(Vec::new()));
+
+    GalleryImage {
+        file,
+        url: url.to_string(),
+        nsfw_level: image["nsfwLevel"].as_i64().unwrap_or_default(),
+        width: image["width"].as_i64(),
+        height: image["height"].as_i64(),
+        prompt: text("prompt"),
+        negative_prompt: text("negativePrompt"),
+        sampler: text("sampler"),
+        cfg_scale: meta["cfgScale"].as_f64(),
+        steps: meta["steps"].as_i64(),
+        seed: meta["seed"].as_i64(),
+        resources,
+        meta: if meta.is_object() { meta.clone() } else { Value::Object(Default::default()) },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CivitaiConfig;
+    use crate::testing::{memory_pool, serve, TempDir};
+    use actix_web::{web, HttpResponse};
+    use bytes::Bytes;
+    use futures_util::stream;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    async fn item(pool: &SqlitePool) {
+        sqlx::query(r#"INSERT INTO item (path, base_label, name) VALUES ('a.safetensors', 'collection1', 'a')"#)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    fn client() -> CivitaiClient {
+        CivitaiClient::new(&CivitaiConfig {
+            max_retries: 0,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn failed_image_is_skipped() {
+        let dir = TempDir::new();
+        let pool = memory_pool().await;
+        item(&pool).await;
+        let base_url = serve(|cfg| {
+            cfg.route(
+                "/ok/{name}",
+                web::get().to(|| async { HttpResponse::Ok().body("image") }),
+            )
+            .route("/missing/{name}", web::get().to(HttpResponse::NotFound));
+        });
+        let info = json!({"images": [
+            {"url": format!("{}/ok/1.jpeg", base_url), "nsfwLevel": 1},
+            {"url": format!("{}/missing/2.jpeg", base_url), "nsfwLevel": 1},
+            {"url": format!("{}/ok/3.jpeg", base_url), "nsfwLevel": 1},
+        ]});
+        let model_path = dir.path().join("a.safetensors");
+
+        let result = save_gallery(&GalleryConfig::default(), &pool, &client(), 1, &model_path, &info).await;
+
+        assert!(result.is_err());
+        let files = gallery::get(&pool, 1)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|image| image.file)
+            .collect::<Vec<_>>();
+        assert_eq!(files, ["000-1.jpeg", "002-3.jpeg"]);
+        let gallery = gallery_dir(&model_path);
+        assert!(gallery.join("000-1.jpeg").exists());
+        assert!(!gallery.join("001-2.jpeg").exists());
+    }
+
+    #[actix_web::test]
+    async fn image_cut_off_is_downloaded_again() {
+        let dir = TempDir::new();
+        let pool = memory_pool().await;
+        item(&pool).await;
+        let broken = Arc::new(AtomicBool::new(true));
+        let server_broken = broken.clone();
+        let base_url = serve(move |cfg| {
+            let broken = server_broken.clone();
+            cfg.route(
+                "/{name}",
+                web::get().to(move || {
+                    let broken = broken.load(Ordering::Relaxed);
+                    async move {
+                        if broken {
+                            // Connection lost after the first bytes
+                            let chunks: [Result<Bytes, std::io::Error>; 2] =
+                                [Ok(Bytes::from_static(b"ima")), Err(std::io::Error::other("lost"))];
+                            HttpResponse::Ok().streaming(stream::iter(chunks))
+                        } else {
+                            HttpResponse::Ok().body("image")
+                        }
+                    }
+                }),
+            );
+        });
+        let info = json!({"images": [{"url": format!("{}/1.jpeg", base_url), "nsfwLevel": 1}]});
+        let model_path = dir.path().join("a.safetensors");
+        let image = gallery_dir(&model_path).join("000-1.jpeg");
+
+        let result = save_gallery(&GalleryConfig::default(), &pool, &client(), 1, &model_path, &info).await;
+        assert!(result.is_err());
+        assert!(!image.exists());
+        assert!(gallery::get(&pool, 1).await.unwrap().is_empty());
+
+        broken.store(false, Ordering::Relaxed);
+        save_gallery(&GalleryConfig::default(), &pool, &client(), 1, &model_path, &info)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&image).unwrap(), b"image");
+        assert_eq!(gallery::get(&pool, 1).await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,541 @@
+use super::client::CivitaiClient;
+use super::download::{download, parse_source, part_path, DownloadProgress, DownloadRequest, Transfers};
+use crate::config::Config;
+use crate::db::download::{self, Download, DownloadKind, DownloadStatus};
+use crate::duplicate::abs_path;
+use crate::huggingface::{self, HuggingFaceClient};
+use crate::job::{Job, JobKind, JobManager};
+use crate::provider::Providers;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use tokio::fs;
+use tokio::sync::Notify;
+use tracing::{error, info};
+
+/// Download in progress
+struct Running {
+    job: Arc<Job>,
+    progress: Arc<DownloadProgress>,
+    /// Status to record when the job stops because user paused or cancelled it
+    stop_as: Mutex<Option<DownloadStatus>>,
+}
+
+/// Downloads from Civitai and Hugging Face are persisted in DB and started by priority,
+/// at most `download.concurrency` at a time
+pub struct DownloadQueue {
+    config: Arc<Config>,
+    pool: SqlitePool,
+    jobs: Arc<JobManager>,
+    client: CivitaiClient,
+    hf_client: HuggingFaceClient,
+    providers: Arc<Providers>,
+    running: Mutex<HashMap<i64, Arc<Running>>>,
+    /// Target files of running downloads and the global bandwidth limit
+    transfers: Transfers,
+    /// Serialize status changes between user actions and the scheduler
+    state: tokio::sync::Mutex<()>,
+    notify: Notify,
+}
+
+impl DownloadQueue {
+    pub fn new(
+        config: Arc<Config>,
+        pool: SqlitePool,
+        jobs: Arc<JobManager>,
+        client: CivitaiClient,
+        hf_client: HuggingFaceClient,
+        providers: Arc<Providers>,
+    ) -> Self {
+        let transfers = Transfers::new(config.download.bytes_per_second);
+        Self {
+            config,
+            pool,
+            jobs,
+            client,
+            hf_client,
+            providers,
+            running: Mutex::new(HashMap::new()),
+            transfers,
+            state: tokio::sync::Mutex::new(()),
+            notify: Notify::new(),
+        }
+    }
+
+    /// Resume downloads interrupted by last shutdown, then keep starting queued downloads
+    pub fn start(self: &Arc<Self>) {
+        let queue = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = download::requeue_running(&queue.pool).await {
+                error!("Failed to requeue interrupted downloads: {}", e);
+            }
+            loop {
+                queue.fill().await;
+                queue.notify.notified().await;
+            }
+        });
+    }
+
+    pub async fn enqueue(&self, source: &str, label: &str, subfolder: &str, priority: i64) -> anyhow::Result<Download> {
+        if !self.config.model_paths.contains_key(label) {
+            return Err(anyhow::anyhow!("Unknown collection {}", label));
+        }
+        parse_source(source)?;
+
+        let id = download::insert(&self.pool, source, label, subfolder, priority).await?;
+        info!("Queue download {}: {}", id, source);
+        self.notify.notify_one();
+        Ok(download::get(&self.pool, id).await?)
+    }
+
+    /// Queue files of a Hugging Face repo, one download per file
+    pub async fn enqueue_huggingface(
+        &self,
+        repo_id: &str,
+        revision: &str,
+        files: &[String],
+        label: &str,
+        subfolder: &str,
+        priority: i64,
+    ) -> anyhow::Result<Vec<Download>> {
+        if !self.config.model_paths.contains_key(label) {
+            return Err(anyhow::anyhow!("Unknown collection {}", label));
+        }
+        if files.is_empty() {
+            return Err(anyhow::anyhow!("No file to download from {}", repo_id));
+        }
+
+        let mut ret = Vec::new();
+        for file in files {
+            let id =
+                download::insert_huggingface(&self.pool, repo_id, revision, file, label, subfolder, priority).await?;
+            info!("Queue download {}: {}/{}", id, repo_id, file);
+            ret.push(download::get(&self.pool, id).await?);
+        }
+        self.notify.notify_one();
+        Ok(ret)
+    }
+
+    /// Stop a running download and keep its part file, or hold a queued one
+    pub async fn pause(&self, id: i64) -> anyhow::Result<Download> {
+        let _state = self.state.lock().await;
+        if self.stop(id, DownloadStatus::Paused) {
+            return self.get(id).await;
+        }
+        let item = download::get(&self.pool, id).await?;
+        if item.status != DownloadStatus::Queued.as_str() {
+            return Err(anyhow::anyhow!("Download {} is {}", id, item.status));
+        }
+        download::set_status(&self.pool, id, DownloadStatus::Paused, "").await?;
+        self.get(id).await
+    }
+
+    /// Queue a paused or failed download again. It continues from its part file.
+    pub async fn resume(&self, id: i64) -> anyhow::Result<Download> {
+        let _state = self.state.lock().await;
+        let item = download::get(&self.pool, id).await?;
+        if item.status != DownloadStatus::Paused.as_str() && item.status != DownloadStatus::Failed.as_str() {
+            return Err(anyhow::anyhow!("Download {} is {}", id, item.status));
+        }
+        download::set_status(&self.pool, id, DownloadStatus::Queued, "").await?;
+        self.notify.notify_one();
+        self.get(id).await
+    }
+
+    /// Stop a download and remove its part file
+    pub async fn cancel(&self, id: i64) -> anyhow::Result<Download> {
+        let _state = self.state.lock().await;
+        if self.stop(id, DownloadStatus::Cancelled) {
+            return self.get(id).await;
+        }
+        let item = download::get(&self.pool, id).await?;
+        if item.status == DownloadStatus::Done.as_str() || item.status == DownloadStatus::Cancelled.as_str() {
+            return Err(anyhow::anyhow!("Download {} is {}", id, item.status));
+        }
+        download::set_status(&self.pool, id, DownloadStatus::Cancelled, "").await?;
+        self.remove_part(&item.base_label, &item.path).await;
+        self.get(id).await
+    }
+
+    /// Change order of queued downloads. A running download is not interrupted.
+    pub async fn set_priority(&self, id: i64, priority: i64) -> anyhow::Result<Download> {
+        download::set_priority(&self.pool, id, priority).await?;
+        self.get(id).await
+    }
+
+    /// All downloads with live progress of running ones
+    pub async fn list(&self) -> anyhow::Result<Vec<Download>> {
+        let mut items = download::get_all(&self.pool).await?;
+        for item in items.iter_mut() {
+            self.fill_progress(item);
+        }
+        Ok(items)
+    }
+
+    pub async fn get(&self, id: i64) -> anyhow::Result<Download> {
+        let mut item = download::get(&self.pool, id).await?;
+        self.fill_progress(&mut item);
+        Ok(item)
+    }
+
+    /// Remove done and cancelled downloads from the list
+    pub async fn clear(&self) -> anyhow::Result<()> {
+        download::clear_finished(&self.pool).await?;
+        Ok(())
+    }
+
+    fn fill_progress(&self, item: &mut Download) {
+        if let Some(running) = self.running.lock().unwrap().get(&item.id) {
+            let path = running.progress.path.lock().unwrap();
+            if !path.is_empty() {
+                item.path = path.clone();
+            }
+            item.downloaded_bytes = running.progress.downloaded.load(Ordering::Relaxed) as i64;
+            item.total_bytes = running.progress.total.load(Ordering::Relaxed) as i64;
+        }
+    }
+
+    /// Ask a running download to stop. Return false if it is not running.
+    fn stop(&self, id: i64, status: DownloadStatus) -> bool {
+        let Some(running) = self.running.lock().unwrap().get(&id).cloned() else {
+            return false;
+        };
+        *running.stop_as.lock().unwrap() = Some(status);
+        running.job.cancel();
+        true
+    }
+
+    async fn remove_part(&self, label: &str, path: &str) {
+        if path.is_empty() {
+            return;
+        }
+        let part = part_path(&abs_path(&self.config, label, path));
+        if part.exists() {
+            if let Err(e) = fs::remove_file(&part).await {
+                error!("Failed to remove {}: {}", part.display(), e);
+            }
+        }
+    }
+
+    /// Start queued downloads until all slots are taken
+    async fn fill(self: &Arc<Self>) {
+        let _state = self.state.lock().await;
+        while self.running.lock().unwrap().len() < self.config.download.concurrency.max(1) {
+            let item = match download::next_queued(&self.pool).await {
+                Ok(Some(item)) => item,
+                Ok(None) => break,
+                Err(e) => {
+                    error!("Failed to get queued download: {}", e);
+                    break;
+                }
+            };
+            if let Err(e) = download::set_status(&self.pool, item.id, DownloadStatus::Running, "").await {
+                error!("Failed to start download {}: {}", item.id, e);
+                break;
+            }
+            self.spawn(item);
+        }
+    }
+
+    fn spawn(self: &Arc<Self>, item: Download) {
+        let running = Arc::new(Running {
+            job: self.jobs.start_concurrent(JobKind::Download),
+            progress: Arc::new(DownloadProgress::default()),
+            stop_as: Mutex::new(None),
+        });
+        self.running.lock().unwrap().insert(item.id, running.clone());
+
+        let queue = self.clone();
+        tokio::spawn(async move {
+            let _guard = running.job.guard();
+            // Run download in its own task, so a panic fails this download instead of leaking its slot
+            let task = {
+                let (queue, item, running) = (queue.clone(), item.clone(), running.clone());
+                tokio::spawn(async move { queue.run(&item, &running).await })
+            };
+            let result = task
+                .await
+                .unwrap_or_else(|e| Err(anyhow::anyhow!("Download stopped: {}", e)));
+            running.job.finish(&result);
+
+            let _state = queue.state.lock().await;
+            queue.running.lock().unwrap().remove(&item.id);
+            let stop_as = *running.stop_as.lock().unwrap();
+            let (status, message) = match (&result, stop_as) {
+                (Ok(_), _) => (DownloadStatus::Done, String::new()),
+                (Err(_), Some(status)) => (status, String::new()),
+                (Err(e), None) => {
+                    error!("Failed to download {}: {}", item.source, e);
+                    (DownloadStatus::Failed, e.to_string())
+                }
+            };
+
+            let path = running.progress.path.lock().unwrap().clone();
+            if status == DownloadStatus::Cancelled {
+                queue.remove_part(&item.base_label, &path).await;
+            }
+            let downloaded = running.progress.downloaded.load(Ordering::Relaxed) as i64;
+            let total = running.progress.total.load(Ordering::Relaxed) as i64;
+            if let Err(e) = download::set_progress(&queue.pool, item.id, &path, downloaded, total).await {
+                error!("Failed to save progress of download {}: {}", item.id, e);
+            }
+            if let Err(e) = download::set_status(&queue.pool, item.id, status, &message).await {
+                error!("Failed to save status of download {}: {}", item.id, e);
+            }
+            queue.notify.notify_one();
+        });
+    }
+
+    /// Download with the downloader of its source
+    async fn run(&self, item: &Download, running: &Running) -> anyhow::Result<()> {
+        if item.kind == DownloadKind::Huggingface.as_str() {
+            let request = huggingface::DownloadRequest {
+                repo_id: item.source.clone(),
+                revision: item.revision.clone(),
+                file: item.file.clone(),
+                label: item.base_label.clone(),
+                subfolder: item.subfolder.clone(),
+            };
+            huggingface::download(
+                &self.config,
+                &self.pool,
+                &self.hf_client,
+                &self.providers,
+                &running.job,
+                &request,
+                &running.progress,
+                &self.transfers,
+            )
+            .await
+        } else {
+            let request = DownloadRequest {
+                source: item.source.clone(),
+                label: item.base_label.clone(),
+                subfolder: item.subfolder.clone(),
+            };
+            download(
+                &self.config,
+                &self.pool,
+                &self.client,
+                &self.providers,
+                &running.job,
+                &request,
+                &running.progress,
+                &self.transfers,
+            )
+            .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, memory_pool, serve, TempDir, LABEL};
+    use actix_web::{web, HttpResponse};
+    use bytes::Bytes;
+    use futures_util::{stream, StreamExt};
+    use serde_json::json;
+    use sha2::{Digest, Sha256};
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use tokio::sync::Semaphore;
+
+    const REPO: &str = "org/repo";
+    const CONTENT: &[u8] = b"model content";
+
+    /// Hub stand-in serving `a.safetensors` of `REPO`. Its content is sent in two chunks,
+    /// the second one once `gate` has a permit.
+    fn hub(gate: Arc<Semaphore>) -> String {
+        serve(move |cfg| {
+            let gate = gate.clone();
+            let info = json!({
+                "sha": "abc123",
+                "siblings": [{
+                    "rfilename": "a.safetensors",
+                    "lfs": {"sha256": hex::encode(Sha256::digest(CONTENT)), "size": CONTENT.len()},
+                }],
+            });
+            cfg.route(
+                "/api/models/{org}/{name}/revision/{revision}",
+                web::get().to(move || {
+                    let info = info.clone();
+                    async move { HttpResponse::Ok().json(info) }
+                }),
+            )
+            .route(
+                "/{org}/{name}/resolve/abc123/a.safetensors",
+                web::get().to(move || {
+                    let gate = gate.clone();
+                    async move {
+                        let (first, rest) = CONTENT.split_at(5);
+                        let first = stream::once(async move { Ok::<_, std::io::Error>(Bytes::from_static(first)) });
+                        let rest = stream::once(async move {
+                            let _permit = gate.acquire().await.unwrap();
+                            Ok(Bytes::from_static(rest))
+                        });
+                        HttpResponse::Ok().streaming(first.chain(rest))
+                    }
+                }),
+            );
+        })
+    }
+
+    struct Fixture {
+        dir: TempDir,
+        pool: SqlitePool,
+        gate: Arc<Semaphore>,
+        queue: Arc<DownloadQueue>,
+    }
+
+    impl Fixture {
+        async fn new() -> Self {
+            let dir = TempDir::new();
+            let mut config = testing::collection(&dir, &[]);
+            config.download.min_free_space_mb = 0;
+            let gate = Arc::new(Semaphore::new(0));
+            config.huggingface.base_url = hub(gate.clone());
+            let pool = memory_pool().await;
+            let queue = DownloadQueue::new(
+                Arc::new(config.clone()),
+                pool.clone(),
+                Arc::new(JobManager::default()),
+                CivitaiClient::new(&config.civitai).unwrap(),
+                HuggingFaceClient::new(&config.huggingface).unwrap(),
+                Arc::new(Providers::new(&config, &pool)),
+            );
+            Self {
+                dir,
+                pool,
+                gate,
+                queue: Arc::new(queue),
+            }
+        }
+
+        async fn enqueue(&self) -> i64 {
+            let files = ["a.safetensors".to_string()];
+            let downloads = self
+                .queue
+                .enqueue_huggingface(REPO, "main", &files, LABEL, "hf", 0)
+                .await
+                .unwrap();
+            downloads[0].id
+        }
+
+        fn path(&self) -> PathBuf {
+            self.dir.path().join("hf/a.safetensors")
+        }
+
+        /// Wait until download `id` matches `f`
+        async fn wait(&self, id: i64, f: impl Fn(&Download) -> bool) -> Download {
+            for _ in 0..500 {
+                let item = self.queue.get(id).await.unwrap();
+                if f(&item) {
+                    return item;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            panic!("Download {} is {}", id, self.queue.get(id).await.unwrap().status);
+        }
+
+        async fn wait_status(&self, id: i64, status: DownloadStatus) -> Download {
+            self.wait(id, |item| item.status == status.as_str()).await
+        }
+
+        /// Wait until the first chunk of download `id` is received
+        async fn wait_first_chunk(&self, id: i64) {
+            self.wait(id, |item| item.downloaded_bytes > 0).await;
+        }
+    }
+
+    #[actix_web::test]
+    async fn pause_and_resume_running() {
+        let f = Fixture::new().await;
+        f.queue.start();
+        let id = f.enqueue().await;
+        f.wait_first_chunk(id).await;
+
+        f.queue.pause(id).await.unwrap();
+        f.gate.add_permits(1);
+        let item = f.wait_status(id, DownloadStatus::Paused).await;
+        assert_eq!(item.path, "hf/a.safetensors");
+        assert!(part_path(&f.path()).exists());
+        assert!(!f.path().exists());
+        assert!(f.queue.pause(id).await.is_err());
+
+        f.queue.resume(id).await.unwrap();
+        f.wait_status(id, DownloadStatus::Done).await;
+        assert_eq!(std::fs::read(f.path()).unwrap(), CONTENT);
+        assert!(!part_path(&f.path()).exists());
+        assert!(f.queue.resume(id).await.is_err());
+    }
+
+    #[actix_web::test]
+    async fn paused_download_is_not_started() {
+        let f = Fixture::new().await;
+        let id = f.enqueue().await;
+        f.queue.pause(id).await.unwrap();
+        f.gate.add_permits(1);
+        f.queue.start();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(f.queue.get(id).await.unwrap().status, DownloadStatus::Paused.as_str());
+
+        f.queue.resume(id).await.unwrap();
+        f.wait_status(id, DownloadStatus::Done).await;
+        assert_eq!(std::fs::read(f.path()).unwrap(), CONTENT);
+    }
+
+    #[actix_web::test]
+    async fn cancel_removes_part() {
+        let f = Fixture::new().await;
+        f.queue.start();
+        let id = f.enqueue().await;
+        f.wait_first_chunk(id).await;
+
+        f.queue.cancel(id).await.unwrap();
+        f.gate.add_permits(1);
+        f.wait_status(id, DownloadStatus::Cancelled).await;
+        assert!(!part_path(&f.path()).exists());
+        assert!(!f.path().exists());
+        assert!(f.queue.cancel(id).await.is_err());
+        assert!(f.queue.resume(id).await.is_err());
+
+        f.queue.clear().await.unwrap();
+        assert!(f.queue.list().await.unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn cancel_paused_removes_part() {
+        let f = Fixture::new().await;
+        f.queue.start();
+        let id = f.enqueue().await;
+        f.wait_first_chunk(id).await;
+        f.queue.pause(id).await.unwrap();
+        f.gate.add_permits(1);
+        f.wait_status(id, DownloadStatus::Paused).await;
+        assert!(part_path(&f.path()).exists());
+
+        f.queue.cancel(id).await.unwrap();
+        assert_eq!(
+            f.queue.get(id).await.unwrap().status,
+            DownloadStatus::Cancelled.as_str()
+        );
+        assert!(!part_path(&f.path()).exists());
+    }
+
+    #[actix_web::test]
+    async fn requeue_running_on_restart() {
+        let f = Fixture::new().await;
+        let id = f.enqueue().await;
+        // Left running by a previous process
+        download::set_status(&f.pool, id, DownloadStatus::Running, "")
+            .await
+            .unwrap();
+        f.gate.add_permits(1);
+
+        f.queue.start();
+        f.wait_status(id, DownloadStatus::Done).await;
+        assert_eq!(std::fs::read(f.path()).unwrap(), CONTENT);
+    }
+}
@@ -0,0 +1,148 @@
+use super::client::CivitaiClient;
+use super::read_info;
+use crate::config::Config;
+use crate::db::item::{self, Item};
+use crate::db::model_update::{self, ModelUpdate};
+use crate::db::scan_error::ScanStage;
+use crate::duplicate::abs_path;
+use crate::job::Job;
+use crate::scanner::report;
+use futures_util::{stream, StreamExt};
+use serde_json::Value;
+use sqlx::SqlitePool;
+use std::collections::BTreeMap;
+use tracing::{error, info};
+
+/// Model version of an item, read from its Civitai info
+struct InstalledVersion {
+    item: Item,
+    version_id: i64,
+    version_name: String,
+    published_at: String,
+}
+
+/// Compare version in Civitai info of each item with the versions of its model on Civitai.
+/// Each model is requested once even if several of its versions are installed.
+pub async fn check_updates(
+    config: &Config,
+    pool: &SqlitePool,
+    client: &CivitaiClient,
+    job: &Job,
+) -> anyhow::Result<()> {
+    let mut models: BTreeMap<i64, Vec<InstalledVersion>> = BTreeMap::new();
+    for item in item::get_with_civitai_info(pool).await? {
+        job.inc_seen();
+        let path = abs_path(config, &item.base_label, &item.path);
+        let info = match read_info(pool, Some(item.id), &path).await {
+            Ok(info) => info,
+            Err(e) => {
+                report(
+                    pool,
+                    Some(job),
+                    &item.base_label,
+                    &item.path,
+                    ScanStage::ReadSidecar,
+                    &e.to_string(),
+                )
+                .await;
+                continue;
+            }
+        };
+        let v: Value = match serde_json::from_str(&info) {
+            Ok(v) => v,
+            Err(e) => {
+                report(
+                    pool,
+                    Some(job),
+                    &item.base_label,
+                    &item.path,
+                    ScanStage::Parse,
+                    &e.to_string(),
+                )
+                .await;
+                continue;
+            }
+        };
+        let (Some(model_id), Some(version_id)) = (v["modelId"].as_i64(), v["id"].as_i64()) else {
+            continue;
+        };
+        models.entry(model_id).or_default().push(InstalledVersion {
+            item,
+            version_id,
+            version_name: v["name"].as_str().unwrap_or_default().to_string(),
+            published_at: v["publishedAt"].as_str().unwrap_or_default().to_string(),
+        });
+    }
+
+    stream::iter(models)
+        .for_each_concurrent(config.civitai.concurrency.max(1), |(model_id, installed)| async move {
+            if job.is_cancelled() {
+                return;
+            }
+            let model = match client.get_model(model_id).await {
+                Ok(model) => model,
+                Err(e) => {
+                    for version in installed {
+                        report(
+                            pool,
+                            Some(job),
+                            &version.item.base_label,
+                            &version.item.path,
+                            ScanStage::CivitaiFetch,
+                            &e.to_string(),
+                        )
+                        .await;
+                    }
+                    return;
+                }
+            };
+            job.inc_fetched();
+
+            for version in installed {
+                let Some(update) = compare(model_id, &model, &version) else {
+                    continue;
+                };
+                if update.has_update {
+                    info!(
+                        "New version of {}/{}: {}",
+                        version.item.base_label, version.item.path, update.latest_version_name
+                    );
+                }
+                if let Err(e) = model_update::insert_or_update(pool, version.item.id, &update).await {
+                    error!("Failed to save update check: {}", e);
+                }
+            }
+        })
+        .await;
+
+    Ok(())
+}
+
+/// `modelVersions` are ordered newest first. A version missing from the list (e.g. deleted) is
+/// outdated if the latest version was published after it.
+fn compare(model_id: i64, model: &Value, installed: &InstalledVersion) -> Option<ModelUpdate> {
+    let versions = model["modelVersions"].as_array()?;
+    let latest = versions.first()?;
+    let latest_version_id = latest["id"].as_i64()?;
+    let latest_published_at = latest["publishedAt"].as_str().unwrap_or_default().to_string();
+
+    let has_update = latest_version_id != installed.version_id
+        && match versions
+            .iter()
+            .position(|v| v["id"].as_i64() == Some(installed.version_id))
+        {
+            Some(position) => position > 0,
+            None => latest_published_at > installed.published_at,
+        };
+
+    Some(ModelUpdate {
+        model_id,
+        version_id: installed.version_id,
+        version_name: installed.version_name.clone(),
+        latest_version_id,
+        latest_version_name: latest["name"].as_str().unwrap_or_default().to_string(),
+        latest_published_at,
+        latest_base_model: latest["baseModel"].as_str().unwrap_or_default().to_string(),
+        has_update,
+    })
+}
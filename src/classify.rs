@@ -0,0 +1,404 @@
+//! Copyright (c) 2025 Trung Do <dothanhtrung@pm.me>.
+//!
+//! Guess model type and base model from tensor names and shapes.
+//! Used for files without Civitai info. Names follow Civitai so tags are the same for both.
+
+use crate::safetensors::TensorHeader;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ModelKind {
+    Checkpoint,
+    Lora,
+    Lycoris,
+    TextualInversion,
+    Vae,
+    ControlNet,
+    Upscaler,
+}
+
+impl ModelKind {
+    /// Civitai model type
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModelKind::Checkpoint => "Checkpoint",
+            ModelKind::Lora => "LORA",
+            ModelKind::Lycoris => "LoCon",
+            ModelKind::TextualInversion => "TextualInversion",
+            ModelKind::Vae => "VAE",
+            ModelKind::ControlNet => "Controlnet",
+            ModelKind::Upscaler => "Upscaler",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BaseModel {
+    Sd1,
+    Sd2,
+    Sdxl,
+    Pony,
+    Sd3,
+    FluxDev,
+    FluxSchnell,
+}
+
+impl BaseModel {
+    /// Civitai base model
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BaseModel::Sd1 => "SD 1.5",
+            BaseModel::Sd2 => "SD 2.1",
+            BaseModel::Sdxl => "SDXL 1.0",
+            BaseModel::Pony => "Pony",
+            BaseModel::Sd3 => "SD 3",
+            BaseModel::FluxDev => "Flux.1 D",
+            BaseModel::FluxSchnell => "Flux.1 S",
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct Classification {
+    pub kind: Option<ModelKind>,
+    pub base_model: Option<BaseModel>,
+}
+
+pub fn classify(header: &TensorHeader) -> Classification {
+    let kind = detect_kind(header);
+    let base_model = kind.and_then(|k| detect_base_model(header, k));
+    Classification { kind, base_model }
+}
+
+fn detect_kind(header: &TensorHeader) -> Option<ModelKind> {
+    let any = |patterns: &[&str]| header.tensors.keys().any(|k| patterns.iter().any(|p| k.contains(p)));
+    let all_start = |prefixes: &[&str]| {
+        !header.tensors.is_empty() && header.tensors.keys().all(|k| prefixes.iter().any(|p| k.starts_with(p)))
+    };
+
+    if any(&["hada_w1", "lokr_w1", "oft_blocks", "oft_diag"]) {
+        return Some(ModelKind::Lycoris);
+    }
+    if any(&[
+        "lora_up.",
+        "lora_down.",
+        "lora_A.",
+        "lora_B.",
+        ".lora.up.",
+        ".lora.down.",
+    ]) {
+        return Some(ModelKind::Lora);
+    }
+    if any(&["control_model.", "controlnet_", "input_hint_block", "zero_convs."]) {
+        return Some(ModelKind::ControlNet);
+    }
+    if any(&["emb_params", "string_to_param"]) || (header.tensor_count() <= 2 && all_start(&["clip_l", "clip_g"])) {
+        return Some(ModelKind::TextualInversion);
+    }
+    if all_start(&["encoder.", "decoder.", "quant_conv.", "post_quant_conv."]) {
+        return Some(ModelKind::Vae);
+    }
+    if any(&["RRDB_trunk.", "conv_first.", "conv_body.", "conv_up1.", "upconv1."]) {
+        return Some(ModelKind::Upscaler);
+    }
+    if any(&[
+        "model.diffusion_model.",
+        "double_blocks.",
+        "single_blocks.",
+        "joint_blocks.",
+        "single_transformer_blocks.",
+        "context_embedder.",
+        "down_blocks.0.attentions",
+    ]) {
+        return Some(ModelKind::Checkpoint);
+    }
+
+    None
+}
+
+fn detect_base_model(header: &TensorHeader, kind: ModelKind) -> Option<BaseModel> {
+    let any = |patterns: &[&str]| header.tensors.keys().any(|k| patterns.iter().any(|p| k.contains(p)));
+
+    let base_model = match kind {
+        ModelKind::TextualInversion => {
+            if any(&["clip_g"]) {
+                Some(BaseModel::Sdxl)
+            } else {
+                match embedding_dim(header) {
+                    Some(768) => Some(BaseModel::Sd1),
+                    Some(1024) => Some(BaseModel::Sd2),
+                    _ => None,
+                }
+            }
+        }
+        // SD1 and SDXL share VAE architecture, only 16 channels latent (SD3, Flux) differs and is ambiguous
+        ModelKind::Vae | ModelKind::Upscaler => None,
+        _ => {
+            if any(&["double_blocks", "single_blocks", "single_transformer_blocks"]) {
+                if any(&["guidance_in.", "guidance_embedder."]) || kind != ModelKind::Checkpoint {
+                    Some(BaseModel::FluxDev)
+                } else {
+                    Some(BaseModel::FluxSchnell)
+                }
+            } else if any(&["joint_blocks", "context_embedder"]) {
+                Some(BaseModel::Sd3)
+            } else if any(&["conditioner.embedders.1", "lora_te2_", "label_emb.", "add_embedding."]) {
+                Some(BaseModel::Sdxl)
+            } else {
+                match context_dim(header) {
+                    Some(768) => Some(BaseModel::Sd1),
+                    Some(1024) => Some(BaseModel::Sd2),
+                    Some(2048) => Some(BaseModel::Sdxl),
+                    _ => None,
+                }
+            }
+        }
+    }
+    .or_else(
+        || match header.metadata.get("general.architecture").map(|a| a.as_str()) {
+            // GGUF conversions of diffusion models record their architecture
+            Some("flux") => Some(BaseModel::FluxDev),
+            Some("sd3") => Some(BaseModel::Sd3),
+            Some("sdxl") => Some(BaseModel::Sdxl),
+            Some("sd1") => Some(BaseModel::Sd1),
+            _ => None,
+        },
+    )
+    .or_else(|| {
+        // Written by kohya-ss sd-scripts, e.g. "sd_v1", "sdxl_base_v1-0", "flux1"
+        let version = header.metadata.get("ss_base_model_version")?;
+        if version.starts_with("sdxl") {
+            Some(BaseModel::Sdxl)
+        } else if version.starts_with("sd_v1") {
+            Some(BaseModel::Sd1)
+        } else if version.starts_with("sd_v2") {
+            Some(BaseModel::Sd2)
+        } else if version.starts_with("sd3") {
+            Some(BaseModel::Sd3)
+        } else if version.starts_with("flux") {
+            Some(BaseModel::FluxDev)
+        } else {
+            None
+        }
+    });
+
+    // Pony is SDXL architecture, only training metadata tells them apart
+    if base_model == Some(BaseModel::Sdxl) && mentions_pony(header) {
+        return Some(BaseModel::Pony);
+    }
+
+    base_model
+}
+
+/// Input dim of cross attention key projection: 768 for SD1, 1024 for SD2, 2048 for SDXL
+fn context_dim(header: &TensorHeader) -> Option<u64> {
+    header
+        .tensors
+        .iter()
+        .filter(|(k, _)| {
+            (k.contains("attn2.to_k") || k.contains("attn2_to_k"))
+                && k.ends_with("weight")
+                && !k.contains("lora_up")
+                && !k.contains("lora_B")
+        })
+        .find_map(|(_, t)| if t.shape.len() >= 2 { Some(t.shape[1]) } else { None })
+}
+
+fn embedding_dim(header: &TensorHeader) -> Option<u64> {
+    header.tensors.values().find_map(|t| t.shape.last().copied())
+}
+
+fn mentions_pony(header: &TensorHeader) -> bool {
+    [
+        "ss_sd_model_name",
+        "ss_base_model_version",
+        "modelspec.title",
+        "modelspec.architecture",
+    ]
+    .iter()
+    .filter_map(|k| header.metadata.get(*k))
+    .any(|v| v.to_lowercase().contains("pony"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::safetensors::TensorInfo;
+
+    fn header(tensors: &[(&str, &[u64])], metadata: &[(&str, &str)]) -> TensorHeader {
+        TensorHeader {
+            tensors: tensors
+                .iter()
+                .map(|(name, shape)| {
+                    let info = TensorInfo {
+                        dtype: "F16".to_string(),
+                        shape: shape.to_vec(),
+                    };
+                    (name.to_string(), info)
+                })
+                .collect(),
+            metadata: metadata.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        }
+    }
+
+    fn check(header: &TensorHeader, kind: Option<ModelKind>, base_model: Option<BaseModel>) {
+        let c = classify(header);
+        assert_eq!((c.kind, c.base_model), (kind, base_model));
+    }
+
+    #[test]
+    fn sd1_checkpoint() {
+        let h = header(
+            &[(
+                "model.diffusion_model.input_blocks.1.1.transformer_blocks.0.attn2.to_k.weight",
+                &[320, 768],
+            )],
+            &[],
+        );
+        check(&h, Some(ModelKind::Checkpoint), Some(BaseModel::Sd1));
+    }
+
+    #[test]
+    fn sdxl_checkpoint() {
+        let h = header(
+            &[
+                ("model.diffusion_model.label_emb.0.0.weight", &[1280, 2816]),
+                ("conditioner.embedders.1.model.ln_final.weight", &[1280]),
+            ],
+            &[],
+        );
+        check(&h, Some(ModelKind::Checkpoint), Some(BaseModel::Sdxl));
+    }
+
+    #[test]
+    fn flux_checkpoints() {
+        let schnell = header(&[("double_blocks.0.img_attn.proj.weight", &[3072, 3072])], &[]);
+        check(&schnell, Some(ModelKind::Checkpoint), Some(BaseModel::FluxSchnell));
+
+        let dev = header(
+            &[
+                ("double_blocks.0.img_attn.proj.weight", &[3072, 3072]),
+                ("guidance_in.in_layer.weight", &[3072, 256]),
+            ],
+            &[],
+        );
+        check(&dev, Some(ModelKind::Checkpoint), Some(BaseModel::FluxDev));
+    }
+
+    #[test]
+    fn sd3_checkpoint() {
+        let h = header(
+            &[(
+                "model.diffusion_model.joint_blocks.0.x_block.attn.qkv.weight",
+                &[4608, 1536],
+            )],
+            &[],
+        );
+        check(&h, Some(ModelKind::Checkpoint), Some(BaseModel::Sd3));
+    }
+
+    #[test]
+    fn loras() {
+        let sd1 = header(
+            &[(
+                "lora_unet_down_blocks_0_attentions_0_transformer_blocks_0_attn2_to_k.lora_down.weight",
+                &[32, 768],
+            )],
+            &[],
+        );
+        check(&sd1, Some(ModelKind::Lora), Some(BaseModel::Sd1));
+
+        let sdxl = header(
+            &[(
+                "lora_te2_text_model_encoder_layers_0_mlp_fc1.lora_down.weight",
+                &[32, 1280],
+            )],
+            &[],
+        );
+        check(&sdxl, Some(ModelKind::Lora), Some(BaseModel::Sdxl));
+
+        let flux = header(
+            &[(
+                "transformer.single_transformer_blocks.0.attn.to_k.lora_A.weight",
+                &[16, 3072],
+            )],
+            &[],
+        );
+        check(&flux, Some(ModelKind::Lora), Some(BaseModel::FluxDev));
+    }
+
+    #[test]
+    fn pony_from_metadata() {
+        let h = header(
+            &[(
+                "lora_te2_text_model_encoder_layers_0_mlp_fc1.lora_down.weight",
+                &[32, 1280],
+            )],
+            &[("ss_sd_model_name", "ponyDiffusionV6XL.safetensors")],
+        );
+        check(&h, Some(ModelKind::Lora), Some(BaseModel::Pony));
+    }
+
+    #[test]
+    fn base_model_from_kohya_metadata() {
+        let h = header(
+            &[(
+                "lora_unet_mid_block_resnets_0_conv1.lora_down.weight",
+                &[16, 1280, 3, 3],
+            )],
+            &[("ss_base_model_version", "sd_v2")],
+        );
+        check(&h, Some(ModelKind::Lora), Some(BaseModel::Sd2));
+    }
+
+    #[test]
+    fn lycoris() {
+        let h = header(
+            &[("lora_unet_mid_block_attentions_0_proj_in.hada_w1_a", &[1280, 8])],
+            &[],
+        );
+        check(&h, Some(ModelKind::Lycoris), None);
+    }
+
+    #[test]
+    fn textual_inversions() {
+        let sd1 = header(&[("emb_params", &[4, 768])], &[]);
+        check(&sd1, Some(ModelKind::TextualInversion), Some(BaseModel::Sd1));
+
+        let sdxl = header(&[("clip_l", &[4, 768]), ("clip_g", &[4, 1280])], &[]);
+        check(&sdxl, Some(ModelKind::TextualInversion), Some(BaseModel::Sdxl));
+    }
+
+    #[test]
+    fn vae_controlnet_upscaler() {
+        let vae = header(
+            &[
+                ("encoder.conv_in.weight", &[128, 3, 3, 3]),
+                ("decoder.conv_out.weight", &[3, 128, 3, 3]),
+                ("quant_conv.weight", &[8, 8, 1, 1]),
+            ],
+            &[],
+        );
+        check(&vae, Some(ModelKind::Vae), None);
+
+        let controlnet = header(&[("control_model.input_hint_block.0.weight", &[16, 3, 3, 3])], &[]);
+        check(&controlnet, Some(ModelKind::ControlNet), None);
+
+        let upscaler = header(&[("conv_first.weight", &[64, 3, 3, 3])], &[]);
+        check(&upscaler, Some(ModelKind::Upscaler), None);
+    }
+
+    #[test]
+    fn gguf_architecture() {
+        let h = header(
+            &[("model.diffusion_model.img_in.weight", &[3072, 64])],
+            &[("general.architecture", "flux")],
+        );
+        check(&h, Some(ModelKind::Checkpoint), Some(BaseModel::FluxDev));
+    }
+
+    #[test]
+    fn unknown() {
+        check(&header(&[], &[]), None, None);
+        check(&header(&[("some.random.weight", &[10])], &[]), None, None);
+    }
+}
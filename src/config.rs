@@ -1,11 +1,13 @@
 //! Copyright (c) 2025 Trung Do <dothanhtrung@pm.me>.
 
+use crate::provider::ProviderKind;
 use ron::ser::{to_string_pretty, PrettyConfig};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::Write;
 use std::path::PathBuf;
+use std::time::Duration;
 
 const DEFAULT_LISTEN_ADDR: &str = "0.0.0.0";
 const DEFAULT_LISTEN_PORT: u32 = 9696;
@@ -16,6 +18,31 @@ const DEFAULT_SQLITE_PATH: &str = "db/sd-models-manager.sqlite";
 
 const DEFAULT_API_PER_PAGE: u32 = 20;
 
+const DEFAULT_WATCHER_DEBOUNCE_MS: u64 = 2000;
+
+const DEFAULT_CIVITAI_BASE_URL: &str = "https://civitai.com/api/v1";
+const DEFAULT_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+const DEFAULT_CIVITAI_CONNECT_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_CIVITAI_TIMEOUT_SECS: u64 = 120;
+const DEFAULT_CIVITAI_DOWNLOAD_TIMEOUT_SECS: u64 = 6 * 60 * 60;
+const DEFAULT_CIVITAI_NOT_FOUND_RETRY_DAYS: u64 = 30;
+const DEFAULT_CIVITAI_REQUESTS_PER_SECOND: f64 = 2.0;
+const DEFAULT_CIVITAI_BATCH_SIZE: usize = 100;
+const DEFAULT_CIVITAI_CONCURRENCY: usize = 4;
+const DEFAULT_CIVITAI_MAX_RETRIES: u32 = 5;
+const DEFAULT_CIVITAI_RETRY_BASE_DELAY_MS: u64 = 1000;
+const DEFAULT_GALLERY_MAX_NSFW_LEVEL: u32 = 1;
+
+const DEFAULT_HUGGINGFACE_BASE_URL: &str = "https://huggingface.co";
+const DEFAULT_HUGGINGFACE_CONNECT_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_HUGGINGFACE_TIMEOUT_SECS: u64 = 120;
+const DEFAULT_HUGGINGFACE_DOWNLOAD_TIMEOUT_SECS: u64 = 6 * 60 * 60;
+
+const DEFAULT_QUARANTINE_DIR: &str = ".quarantine";
+
+const DEFAULT_DOWNLOAD_CONCURRENCY: usize = 2;
+const DEFAULT_DOWNLOAD_MIN_FREE_SPACE_MB: u64 = 1024;
+
 #[derive(Deserialize, Debug, Serialize, Clone)]
 pub struct SQLiteConfig {
     pub db_path: String,
@@ -57,11 +84,204 @@ impl Default for APIConfig {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
 pub struct CivitaiConfig {
     pub api_key: String,
     pub overwrite_thumbnail: bool,
+    /// Write Civitai info as json sidecar next to model file. It is always stored in DB.
     pub save_json: bool,
+    /// Civitai API or a mirror of it
+    pub base_url: String,
+    pub user_agent: String,
+    /// Proxy for all requests, e.g. `socks5://127.0.0.1:1080`
+    pub proxy: Option<String>,
+    pub connect_timeout_secs: u64,
+    /// Timeout of a whole request, including downloading response body
+    pub timeout_secs: u64,
+    /// Timeout of a whole model file download. Interrupted downloads are resumed.
+    pub download_timeout_secs: u64,
+    /// Sync models whose Civitai info is older than this again. 0 to only sync models without info.
+    pub resync_after_days: u64,
+    /// Do not look up models which were not found on Civitai again before this
+    pub not_found_retry_days: u64,
+    /// Limit of API requests per second. 0 for no limit.
+    pub requests_per_second: f64,
+    /// Number of hashes sent in one lookup request
+    pub batch_size: usize,
+    /// Number of lookup requests in flight at the same time
+    pub concurrency: usize,
+    /// Retries of a request on 429, 5xx or connection error
+    pub max_retries: u32,
+    /// First retry delay, doubled on each retry
+    pub retry_base_delay_ms: u64,
+    pub gallery: GalleryConfig,
+}
+
+impl Default for CivitaiConfig {
+    fn default() -> Self {
+        Self {
+            api_key: String::new(),
+            overwrite_thumbnail: false,
+            save_json: true,
+            base_url: DEFAULT_CIVITAI_BASE_URL.to_string(),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            proxy: None,
+            connect_timeout_secs: DEFAULT_CIVITAI_CONNECT_TIMEOUT_SECS,
+            timeout_secs: DEFAULT_CIVITAI_TIMEOUT_SECS,
+            download_timeout_secs: DEFAULT_CIVITAI_DOWNLOAD_TIMEOUT_SECS,
+            resync_after_days: 0,
+            not_found_retry_days: DEFAULT_CIVITAI_NOT_FOUND_RETRY_DAYS,
+            requests_per_second: DEFAULT_CIVITAI_REQUESTS_PER_SECOND,
+            batch_size: DEFAULT_CIVITAI_BATCH_SIZE,
+            concurrency: DEFAULT_CIVITAI_CONCURRENCY,
+            max_retries: DEFAULT_CIVITAI_MAX_RETRIES,
+            retry_base_delay_ms: DEFAULT_CIVITAI_RETRY_BASE_DELAY_MS,
+            gallery: GalleryConfig::default(),
+        }
+    }
+}
+
+impl CivitaiConfig {
+    /// Delay between API requests from `requests_per_second`. Zero for no limit.
+    pub fn request_interval(&self) -> anyhow::Result<Duration> {
+        let rps = self.requests_per_second;
+        if !(rps.is_finite() && rps >= 0.0) {
+            return Err(anyhow::anyhow!("Invalid civitai.requests_per_second: {}", rps));
+        }
+        if rps == 0.0 {
+            return Ok(Duration::ZERO);
+        }
+        Duration::try_from_secs_f64(1.0 / rps)
+            .map_err(|e| anyhow::anyhow!("Invalid civitai.requests_per_second {}: {}", rps, e))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct GalleryConfig {
+    /// Download gallery images of model version into `<model name>.gallery` next to model file
+    pub enabled: bool,
+    /// Number of images to download. 0 for all.
+    pub max_images: usize,
+    /// Skip images above this Civitai NSFW level: 1 PG, 2 PG-13, 4 R, 8 X, 16 XXX
+    pub max_nsfw_level: u32,
+}
+
+impl Default for GalleryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_images: 0,
+            max_nsfw_level: DEFAULT_GALLERY_MAX_NSFW_LEVEL,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct WatcherConfig {
+    pub enabled: bool,
+    /// Wait until a file has no new event for this long before indexing it
+    pub debounce_ms: u64,
+}
+
+impl Default for WatcherConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            debounce_ms: DEFAULT_WATCHER_DEBOUNCE_MS,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PickleConfig {
+    /// Move pickle files importing unsafe globals into `quarantine_dir` of their collection
+    pub quarantine: bool,
+    /// Relative to collection path. Start with `.` so the scanner skips it.
+    pub quarantine_dir: String,
+}
+
+impl Default for PickleConfig {
+    fn default() -> Self {
+        Self {
+            quarantine: false,
+            quarantine_dir: DEFAULT_QUARANTINE_DIR.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct HuggingFaceConfig {
+    /// Hugging Face Hub or a mirror of it
+    pub base_url: String,
+    /// Access token for gated and private repos
+    pub token: String,
+    /// Repos whose files are matched with local files by SHA256, e.g. `black-forest-labs/FLUX.1-dev`
+    pub repos: Vec<String>,
+    pub user_agent: String,
+    /// Proxy for all requests, e.g. `socks5://127.0.0.1:1080`
+    pub proxy: Option<String>,
+    pub connect_timeout_secs: u64,
+    /// Timeout of a whole API request
+    pub timeout_secs: u64,
+    /// Timeout of a whole file download. Interrupted downloads are resumed.
+    pub download_timeout_secs: u64,
+}
+
+impl Default for HuggingFaceConfig {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_HUGGINGFACE_BASE_URL.to_string(),
+            token: String::new(),
+            repos: Vec::new(),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            proxy: None,
+            connect_timeout_secs: DEFAULT_HUGGINGFACE_CONNECT_TIMEOUT_SECS,
+            timeout_secs: DEFAULT_HUGGINGFACE_TIMEOUT_SECS,
+            download_timeout_secs: DEFAULT_HUGGINGFACE_DOWNLOAD_TIMEOUT_SECS,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct ProvidersConfig {
+    /// Providers asked to identify a model file, in order. The first one which knows the file wins.
+    /// Civitai and Hugging Face syncs skip files identified by a provider before them, and do not run if not listed.
+    pub order: Vec<ProviderKind>,
+    /// JSON file of model records by BLAKE3, SHA256 or AutoV2 hash, used by the `local` provider
+    pub local_overrides: String,
+}
+
+impl Default for ProvidersConfig {
+    fn default() -> Self {
+        Self {
+            order: vec![ProviderKind::Local, ProviderKind::Civitai, ProviderKind::Huggingface],
+            local_overrides: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DownloadConfig {
+    /// Number of files downloaded at the same time
+    pub concurrency: usize,
+    /// Limit of all downloads together. 0 for no limit.
+    pub bytes_per_second: u64,
+    /// Do not start a download which would leave less free space than this in target collection
+    pub min_free_space_mb: u64,
+}
+
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: DEFAULT_DOWNLOAD_CONCURRENCY,
+            bytes_per_second: 0,
+            min_free_space_mb: DEFAULT_DOWNLOAD_MIN_FREE_SPACE_MB,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -74,6 +294,16 @@ pub struct Config {
     pub api: APIConfig,
     pub walkdir_parallel: usize,
     pub extensions: Vec<String>,
+    #[serde(default)]
+    pub watcher: WatcherConfig,
+    #[serde(default)]
+    pub pickle: PickleConfig,
+    #[serde(default)]
+    pub download: DownloadConfig,
+    #[serde(default)]
+    pub huggingface: HuggingFaceConfig,
+    #[serde(default)]
+    pub providers: ProvidersConfig,
 }
 
 impl Default for Config {
@@ -93,6 +323,11 @@ impl Default for Config {
             db: DBConfig::default(),
             api: APIConfig::default(),
             civitai: CivitaiConfig::default(),
+            watcher: WatcherConfig::default(),
+            pickle: PickleConfig::default(),
+            download: DownloadConfig::default(),
+            huggingface: HuggingFaceConfig::default(),
+            providers: ProvidersConfig::default(),
         }
     }
 }
@@ -101,7 +336,15 @@ impl Config {
     /// Load config from file
     pub fn load(config_path: &PathBuf) -> anyhow::Result<Self> {
         let file = File::open(config_path)?;
-        ron::de::from_reader(file).map_err(|e| anyhow::anyhow!(e))
+        let config: Self = ron::de::from_reader(file).map_err(|e| anyhow::anyhow!(e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Reject values which are parsed but cannot be used
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.civitai.request_interval()?;
+        Ok(())
     }
 
     /// Save config to file
@@ -120,3 +363,25 @@ impl Config {
         file.write_all(ron_str.as_bytes()).map_err(|e| anyhow::anyhow!(e))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn civitai(requests_per_second: f64) -> CivitaiConfig {
+        CivitaiConfig {
+            requests_per_second,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn request_interval() {
+        assert_eq!(civitai(0.0).request_interval().unwrap(), Duration::ZERO);
+        assert_eq!(civitai(2.0).request_interval().unwrap(), Duration::from_millis(500));
+        for rps in [-1.0, f64::NAN, f64::INFINITY, 1e-300] {
+            assert!(civitai(rps).request_interval().is_err(), "{}", rps);
+        }
+        assert!(Config::default().validate().is_ok());
+    }
+}
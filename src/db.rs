@@ -1,8 +1,22 @@
 //! Copyright (c) 2025 Trung Do <dothanhtrung@pm.me>.
 
+pub mod app_info;
 pub mod base;
+pub mod civitai_info;
+pub mod civitai_lookup;
+pub mod download;
+pub mod gallery;
+pub mod hash;
+pub mod hf_source;
 pub mod item;
+pub mod migration;
+pub mod model_header;
+pub mod model_record;
+pub mod model_update;
+pub mod pickle_scan;
+pub mod scan_error;
 pub mod tag;
+pub mod trigger_word;
 
 use crate::config::DBConfig;
 use sqlx::sqlite::SqlitePoolOptions;
@@ -16,6 +30,7 @@ impl DBPool {
     pub async fn init(config: &DBConfig) -> anyhow::Result<Self> {
         let sqlite_pool = SqlitePoolOptions::new().connect(&config.sqlite.db_path).await?;
         sqlx::query!("PRAGMA foreign_keys = ON").execute(&sqlite_pool).await?;
+        migration::run(&sqlite_pool).await?;
 
         Ok(Self {
             sqlite_pool,
@@ -0,0 +1,19 @@
+use sqlx::SqlitePool;
+
+/// Value stored under `label`, None if there is none
+pub async fn get(pool: &SqlitePool, label: &str) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query_scalar!(r#"SELECT value FROM app_info WHERE label = ?"#, label)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn set(pool: &SqlitePool, label: &str, value: i64) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query!(r#"DELETE FROM app_info WHERE label = ?"#, label)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!(r#"INSERT INTO app_info (label, value) VALUES (?, ?)"#, label, value)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await
+}
@@ -0,0 +1,22 @@
+use sqlx::sqlite::SqliteQueryResult;
+use sqlx::SqlitePool;
+
+/// Save full model version response of Civitai for item
+pub async fn insert_or_update(pool: &SqlitePool, item: i64, info: &str) -> Result<SqliteQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"INSERT INTO civitai_info (item, info, updated_at)
+           VALUES (?, ?, unixepoch())
+           ON CONFLICT (item) DO UPDATE
+           SET info = excluded.info, updated_at = excluded.updated_at"#,
+        item,
+        info,
+    )
+    .execute(pool)
+    .await
+}
+
+pub async fn get(pool: &SqlitePool, item: i64) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar!(r#"SELECT info FROM civitai_info WHERE item = ?"#, item)
+        .fetch_optional(pool)
+        .await
+}
@@ -0,0 +1,62 @@
+use serde::Serialize;
+use sqlx::sqlite::SqliteQueryResult;
+use sqlx::SqlitePool;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LookupStatus {
+    Found,
+    NotFound,
+    Error,
+}
+
+impl LookupStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LookupStatus::Found => "found",
+            LookupStatus::NotFound => "not_found",
+            LookupStatus::Error => "error",
+        }
+    }
+}
+
+/// Result of the last Civitai lookup of an item
+#[derive(sqlx::FromRow, Serialize)]
+pub struct CivitaiLookup {
+    pub status: String,
+    pub http_status: Option<i64>,
+    pub message: String,
+    pub checked_at: i64,
+}
+
+pub async fn insert_or_update(
+    pool: &SqlitePool,
+    item: i64,
+    status: LookupStatus,
+    http_status: Option<u16>,
+    message: &str,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    let status = status.as_str();
+    sqlx::query!(
+        r#"INSERT INTO civitai_lookup (item, status, http_status, message, checked_at)
+           VALUES (?, ?, ?, ?, unixepoch())
+           ON CONFLICT (item) DO UPDATE
+           SET status = excluded.status, http_status = excluded.http_status, message = excluded.message,
+               checked_at = excluded.checked_at"#,
+        item,
+        status,
+        http_status,
+        message,
+    )
+    .execute(pool)
+    .await
+}
+
+pub async fn get(pool: &SqlitePool, item: i64) -> Result<Option<CivitaiLookup>, sqlx::Error> {
+    sqlx::query_as!(
+        CivitaiLookup,
+        r#"SELECT status, http_status, message, checked_at FROM civitai_lookup WHERE item = ?"#,
+        item
+    )
+    .fetch_optional(pool)
+    .await
+}
@@ -0,0 +1,221 @@
+use serde::Serialize;
+use sqlx::sqlite::SqliteQueryResult;
+use sqlx::SqlitePool;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DownloadStatus {
+    Queued,
+    Running,
+    Paused,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl DownloadStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DownloadStatus::Queued => "queued",
+            DownloadStatus::Running => "running",
+            DownloadStatus::Paused => "paused",
+            DownloadStatus::Done => "done",
+            DownloadStatus::Failed => "failed",
+            DownloadStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// Where a download comes from
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DownloadKind {
+    Civitai,
+    Huggingface,
+}
+
+impl DownloadKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DownloadKind::Civitai => "civitai",
+            DownloadKind::Huggingface => "huggingface",
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Serialize, Clone)]
+pub struct Download {
+    pub id: i64,
+    /// `civitai` or `huggingface`
+    pub kind: String,
+    /// Civitai model URL, version ID or AIR URN, or Hugging Face repo ID
+    pub source: String,
+    /// Revision of Hugging Face repo
+    pub revision: String,
+    /// Path of file in Hugging Face repo
+    pub file: String,
+    pub base_label: String,
+    pub subfolder: String,
+    /// Higher is downloaded first
+    pub priority: i64,
+    pub status: String,
+    /// Relative path of target file in collection, known once download is started
+    pub path: String,
+    pub total_bytes: i64,
+    pub downloaded_bytes: i64,
+    pub error: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+pub async fn insert(
+    pool: &SqlitePool,
+    source: &str,
+    base_label: &str,
+    subfolder: &str,
+    priority: i64,
+) -> Result<i64, sqlx::Error> {
+    let (kind, status) = (DownloadKind::Civitai.as_str(), DownloadStatus::Queued.as_str());
+    let ret = sqlx::query!(
+        r#"INSERT INTO download (kind, source, base_label, subfolder, priority, status, created_at, updated_at)
+           VALUES (?, ?, ?, ?, ?, ?, unixepoch(), unixepoch())"#,
+        kind,
+        source,
+        base_label,
+        subfolder,
+        priority,
+        status,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(ret.last_insert_rowid())
+}
+
+/// Queue one file of a Hugging Face repo
+pub async fn insert_huggingface(
+    pool: &SqlitePool,
+    repo_id: &str,
+    revision: &str,
+    file: &str,
+    base_label: &str,
+    subfolder: &str,
+    priority: i64,
+) -> Result<i64, sqlx::Error> {
+    let (kind, status) = (DownloadKind::Huggingface.as_str(), DownloadStatus::Queued.as_str());
+    let ret = sqlx::query!(
+        r#"INSERT INTO download (kind, source, revision, file, base_label, subfolder, priority, status, created_at,
+                                 updated_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, unixepoch(), unixepoch())"#,
+        kind,
+        repo_id,
+        revision,
+        file,
+        base_label,
+        subfolder,
+        priority,
+        status,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(ret.last_insert_rowid())
+}
+
+pub async fn get(pool: &SqlitePool, id: i64) -> Result<Download, sqlx::Error> {
+    sqlx::query_as!(
+        Download,
+        r#"SELECT id, kind, source, revision, file, base_label, subfolder, priority, status, path, total_bytes,
+                  downloaded_bytes, error, created_at, updated_at
+           FROM download WHERE id = ?"#,
+        id
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// All downloads, unfinished ones first in the order they will be downloaded
+pub async fn get_all(pool: &SqlitePool) -> Result<Vec<Download>, sqlx::Error> {
+    sqlx::query_as!(
+        Download,
+        r#"SELECT id, kind, source, revision, file, base_label, subfolder, priority, status, path, total_bytes,
+                  downloaded_bytes, error, created_at, updated_at
+           FROM download
+           ORDER BY status IN ('done', 'failed', 'cancelled'), priority DESC, id"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Queued download with highest priority, oldest first
+pub async fn next_queued(pool: &SqlitePool) -> Result<Option<Download>, sqlx::Error> {
+    let status = DownloadStatus::Queued.as_str();
+    sqlx::query_as!(
+        Download,
+        r#"SELECT id, kind, source, revision, file, base_label, subfolder, priority, status, path, total_bytes,
+                  downloaded_bytes, error, created_at, updated_at
+           FROM download WHERE status = ? ORDER BY priority DESC, id LIMIT 1"#,
+        status
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn set_status(
+    pool: &SqlitePool,
+    id: i64,
+    status: DownloadStatus,
+    error: &str,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    let status = status.as_str();
+    sqlx::query!(
+        r#"UPDATE download SET status = ?, error = ?, updated_at = unixepoch() WHERE id = ?"#,
+        status,
+        error,
+        id
+    )
+    .execute(pool)
+    .await
+}
+
+pub async fn set_progress(
+    pool: &SqlitePool,
+    id: i64,
+    path: &str,
+    downloaded_bytes: i64,
+    total_bytes: i64,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE download SET path = ?, downloaded_bytes = ?, total_bytes = ?, updated_at = unixepoch()
+           WHERE id = ?"#,
+        path,
+        downloaded_bytes,
+        total_bytes,
+        id
+    )
+    .execute(pool)
+    .await
+}
+
+pub async fn set_priority(pool: &SqlitePool, id: i64, priority: i64) -> Result<SqliteQueryResult, sqlx::Error> {
+    sqlx::query!(r#"UPDATE download SET priority = ? WHERE id = ?"#, priority, id)
+        .execute(pool)
+        .await
+}
+
+/// Downloads interrupted by a shutdown are queued again and resume from their part file
+pub async fn requeue_running(pool: &SqlitePool) -> Result<SqliteQueryResult, sqlx::Error> {
+    let (running, queued) = (DownloadStatus::Running.as_str(), DownloadStatus::Queued.as_str());
+    sqlx::query!(
+        r#"UPDATE download SET status = ?, updated_at = unixepoch() WHERE status = ?"#,
+        queued,
+        running
+    )
+    .execute(pool)
+    .await
+}
+
+/// Remove finished downloads from the list
+pub async fn clear_finished(pool: &SqlitePool) -> Result<SqliteQueryResult, sqlx::Error> {
+    sqlx::query!(r#"DELETE FROM download WHERE status IN ('done', 'cancelled')"#)
+        .execute(pool)
+        .await
+}
@@ -0,0 +1,89 @@
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::SqlitePool;
+
+/// Gallery image of a model version and its generation parameters
+#[derive(Serialize)]
+pub struct GalleryImage {
+    /// File name inside gallery directory of the model
+    pub file: String,
+    /// Source URL on Civitai
+    pub url: String,
+    pub nsfw_level: i64,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    pub prompt: Option<String>,
+    pub negative_prompt: Option<String>,
+    pub sampler: Option<String>,
+    pub cfg_scale: Option<f64>,
+    pub steps: Option<i64>,
+    pub seed: Option<i64>,
+    /// Models used to generate the image
+    pub resources: Value,
+    /// Whole `meta` block from Civitai
+    pub meta: Value,
+}
+
+/// Replace gallery of item with new images
+pub async fn replace(pool: &SqlitePool, item: i64, images: &[GalleryImage]) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query!("DELETE FROM gallery_image WHERE item = ?", item)
+        .execute(&mut *tx)
+        .await?;
+    for image in images {
+        let resources = image.resources.to_string();
+        let meta = image.meta.to_string();
+        sqlx::query!(
+            r#"INSERT INTO gallery_image (item, file, url, nsfw_level, width, height, prompt, negative_prompt, sampler,
+                                          cfg_scale, steps, seed, resources, meta)
+               VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
+            item,
+            image.file,
+            image.url,
+            image.nsfw_level,
+            image.width,
+            image.height,
+            image.prompt,
+            image.negative_prompt,
+            image.sampler,
+            image.cfg_scale,
+            image.steps,
+            image.seed,
+            resources,
+            meta,
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+pub async fn get(pool: &SqlitePool, item: i64) -> Result<Vec<GalleryImage>, sqlx::Error> {
+    let ret = sqlx::query!(
+        r#"SELECT file, url, nsfw_level, width, height, prompt, negative_prompt, sampler, cfg_scale, steps, seed,
+                  resources, meta
+           FROM gallery_image WHERE item = ? ORDER BY id"#,
+        item
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| GalleryImage {
+        file: r.file,
+        url: r.url,
+        nsfw_level: r.nsfw_level,
+        width: r.width,
+        height: r.height,
+        prompt: r.prompt,
+        negative_prompt: r.negative_prompt,
+        sampler: r.sampler,
+        cfg_scale: r.cfg_scale,
+        steps: r.steps,
+        seed: r.seed,
+        resources: serde_json::from_str(&r.resources).unwrap_or_default(),
+        meta: serde_json::from_str(&r.meta).unwrap_or_default(),
+    })
+    .collect();
+
+    Ok(ret)
+}
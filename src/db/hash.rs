@@ -0,0 +1,68 @@
+use crate::civitai::FileHashes;
+use sqlx::sqlite::SqliteQueryResult;
+use sqlx::SqlitePool;
+
+/// Get cached hashes of file. Return None if file is changed since it was hashed.
+pub async fn get(
+    pool: &SqlitePool,
+    path: &str,
+    base_label: &str,
+    size: i64,
+    mtime: i64,
+) -> Result<Option<FileHashes>, sqlx::Error> {
+    sqlx::query_as!(
+        FileHashes,
+        r#"SELECT blake3, sha256, autov2 FROM file_hash WHERE path = ? AND base_label = ? AND size = ? AND mtime = ?"#,
+        path,
+        base_label,
+        size,
+        mtime
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn insert_or_update(
+    pool: &SqlitePool,
+    path: &str,
+    base_label: &str,
+    size: i64,
+    mtime: i64,
+    hashes: &FileHashes,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"INSERT INTO file_hash (path, base_label, size, mtime, blake3, sha256, autov2, updated_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, unixepoch())
+           ON CONFLICT (path, base_label) DO UPDATE
+           SET size = excluded.size, mtime = excluded.mtime, blake3 = excluded.blake3, sha256 = excluded.sha256,
+               autov2 = excluded.autov2, updated_at = excluded.updated_at"#,
+        path,
+        base_label,
+        size,
+        mtime,
+        hashes.blake3,
+        hashes.sha256,
+        hashes.autov2,
+    )
+    .execute(pool)
+    .await
+}
+
+/// Keep cached hashes of a moved file
+pub async fn rename(
+    pool: &SqlitePool,
+    path: &str,
+    base_label: &str,
+    new_path: &str,
+    new_base_label: &str,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE OR REPLACE file_hash SET path = ?, base_label = ? WHERE path = ? AND base_label = ?"#,
+        new_path,
+        new_base_label,
+        path,
+        base_label
+    )
+    .execute(pool)
+    .await
+}
@@ -0,0 +1,87 @@
+use serde::Serialize;
+use sqlx::sqlite::SqliteQueryResult;
+use sqlx::SqlitePool;
+
+/// File of a Hugging Face repo which an item is identical to
+#[derive(sqlx::FromRow, Serialize, Clone)]
+pub struct HfSource {
+    /// e.g. `black-forest-labs/FLUX.1-dev`
+    pub repo_id: String,
+    /// Commit hash the file was found in
+    pub revision: String,
+    /// Path of file in repo
+    pub file: String,
+    pub license: String,
+    /// README.md of repo
+    pub model_card: String,
+    /// Metadata of model card as JSON
+    pub card_data: String,
+}
+
+/// Hashed item not matched with a Hugging Face file yet
+#[derive(sqlx::FromRow)]
+pub struct UnresolvedItem {
+    pub id: i64,
+    pub path: String,
+    pub base_label: String,
+    pub sha256: String,
+}
+
+pub async fn insert_or_update(
+    pool: &SqlitePool,
+    item: i64,
+    source: &HfSource,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"INSERT INTO hf_source (item, repo_id, revision, file, license, model_card, card_data, updated_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, unixepoch())
+           ON CONFLICT (item) DO UPDATE
+           SET repo_id = excluded.repo_id, revision = excluded.revision, file = excluded.file,
+               license = excluded.license, model_card = excluded.model_card, card_data = excluded.card_data,
+               updated_at = excluded.updated_at"#,
+        item,
+        source.repo_id,
+        source.revision,
+        source.file,
+        source.license,
+        source.model_card,
+        source.card_data,
+    )
+    .execute(pool)
+    .await
+}
+
+pub async fn get(pool: &SqlitePool, item: i64) -> Result<Option<HfSource>, sqlx::Error> {
+    sqlx::query_as!(
+        HfSource,
+        r#"SELECT repo_id, revision, file, license, model_card, card_data FROM hf_source WHERE item = ?"#,
+        item
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Items with a cached SHA256 and no Hugging Face source
+pub async fn get_unresolved(pool: &SqlitePool) -> Result<Vec<UnresolvedItem>, sqlx::Error> {
+    sqlx::query_as!(
+        UnresolvedItem,
+        r#"SELECT item.id, item.path, item.base_label, file_hash.sha256 FROM item
+           JOIN file_hash ON file_hash.path = item.path AND file_hash.base_label = item.base_label
+           WHERE item.is_checked = true AND item.id NOT IN (SELECT item FROM hf_source)
+           ORDER BY item.id"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Cached SHA256 of item file, None if it is not hashed yet
+pub async fn get_sha256(pool: &SqlitePool, item: i64) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"SELECT file_hash.sha256 FROM item
+           JOIN file_hash ON file_hash.path = item.path AND file_hash.base_label = item.base_label
+           WHERE item.id = ?"#,
+        item
+    )
+    .fetch_optional(pool)
+    .await
+}
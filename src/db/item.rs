@@ -1,9 +1,5 @@
-use crate::civitai::{CivitaiFileMetadata, CivitaiModel};
-use crate::db::tag;
 use sqlx::sqlite::SqliteQueryResult;
-use sqlx::{QueryBuilder, SqlitePool};
-use std::path::PathBuf;
-use tracing::info;
+use sqlx::{QueryBuilder, Sqlite, SqlitePool};
 
 #[derive(sqlx::FromRow)]
 pub struct Item {
@@ -13,10 +9,64 @@ pub struct Item {
     pub base_label: String,
 }
 
-pub async fn mark_obsolete_all(pool: &SqlitePool) -> Result<SqliteQueryResult, sqlx::Error> {
-    sqlx::query!(r#"UPDATE item SET is_checked = false WHERE is_checked = true AND path != ''"#)
-        .execute(pool)
-        .await
+#[derive(sqlx::FromRow)]
+pub struct DuplicateItem {
+    pub id: i64,
+    pub name: String,
+    pub path: String,
+    pub base_label: String,
+    pub blake3: String,
+    pub size: i64,
+    pub mtime: i64,
+    pub inode: i64,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct SyncItem {
+    pub id: i64,
+    pub path: String,
+    pub base_label: String,
+    pub blake3: String,
+}
+
+/// Item matching a Civitai model version by version ID or file hash
+#[derive(sqlx::FromRow)]
+pub struct CivitaiMatch {
+    pub id: i64,
+    pub civitai_version_id: Option<i64>,
+    pub blake3: String,
+    pub sha256: Option<String>,
+}
+
+/// Size, mtime and inode of a model file plus mtime of its json sidecar.
+/// Used to skip files which are not changed since last scan.
+#[derive(Default, PartialEq, Debug, Clone, Copy)]
+pub struct Fingerprint {
+    pub size: i64,
+    pub mtime: i64,
+    pub inode: i64,
+    pub json_mtime: i64,
+}
+
+/// Mark items which are not seen since `since` (unix time) as obsolete
+pub async fn mark_obsolete_unseen(pool: &SqlitePool, since: i64) -> Result<SqliteQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE item SET is_checked = false WHERE is_checked = true AND path != '' AND seen_at < ?"#,
+        since
+    )
+    .execute(pool)
+    .await
+}
+
+/// Get checked items which are not seen since `since`
+pub async fn get_unseen(pool: &SqlitePool, since: i64) -> Result<Vec<Item>, sqlx::Error> {
+    sqlx::query_as!(
+        Item,
+        r#"SELECT id, name, path, base_label FROM item WHERE is_checked = true AND path != '' AND seen_at < ?"#,
+        since
+    )
+    .fetch_all(pool)
+    .await
 }
 
 /// Return (path, label)
@@ -28,7 +78,7 @@ pub async fn mark_obsolete(pool: &SqlitePool, id: i64) -> Result<(String, String
     struct Temp {
         path: String,
         base_label: String,
-    };
+    }
     let ret = sqlx::query_as!(Temp, r#"SELECT path, base_label FROM item WHERE id = ?"#, id)
         .fetch_one(pool)
         .await?;
@@ -36,6 +86,126 @@ pub async fn mark_obsolete(pool: &SqlitePool, id: i64) -> Result<(String, String
     Ok((ret.path, ret.base_label))
 }
 
+/// Return id and fingerprint of item if it exists
+pub async fn get_fingerprint(
+    pool: &SqlitePool,
+    path: &str,
+    base_label: &str,
+) -> Result<Option<(i64, Fingerprint)>, sqlx::Error> {
+    let ret = sqlx::query!(
+        r#"SELECT id, size, mtime, inode, json_mtime FROM item WHERE path = ? AND base_label = ?"#,
+        path,
+        base_label
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|r| {
+        (
+            r.id,
+            Fingerprint {
+                size: r.size,
+                mtime: r.mtime,
+                inode: r.inode,
+                json_mtime: r.json_mtime,
+            },
+        )
+    });
+
+    Ok(ret)
+}
+
+pub async fn mark_obsolete_by_path(
+    pool: &SqlitePool,
+    path: &str,
+    base_label: &str,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE item SET is_checked = false WHERE path = ? AND base_label = ?"#,
+        path,
+        base_label
+    )
+    .execute(pool)
+    .await
+}
+
+/// Return id, path and fingerprint of checked items whose path starts with `prefix`
+pub async fn get_checked_under(
+    pool: &SqlitePool,
+    prefix: &str,
+    base_label: &str,
+) -> Result<Vec<(i64, String, Fingerprint)>, sqlx::Error> {
+    // Not LIKE, which would need `%` and `_` in path escaped
+    let ret = sqlx::query!(
+        r#"SELECT id, path, size, mtime, inode, json_mtime FROM item
+           WHERE base_label = ? AND is_checked = true AND substr(path, 1, length(?)) = ?"#,
+        base_label,
+        prefix,
+        prefix
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| {
+        (
+            r.id,
+            r.path,
+            Fingerprint {
+                size: r.size,
+                mtime: r.mtime,
+                inode: r.inode,
+                json_mtime: r.json_mtime,
+            },
+        )
+    })
+    .collect();
+
+    Ok(ret)
+}
+
+/// Point an item to new location after its file is moved
+pub async fn update_path(
+    pool: &SqlitePool,
+    id: i64,
+    path: &str,
+    base_label: &str,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE item SET path = ?, base_label = ?, is_checked = true, seen_at = unixepoch() WHERE id = ?"#,
+        path,
+        base_label,
+        id
+    )
+    .execute(pool)
+    .await
+}
+
+/// Store new fingerprint of a file whose content is unchanged, e.g. replaced with a link
+pub async fn update_fingerprint(
+    pool: &SqlitePool,
+    id: i64,
+    fingerprint: &Fingerprint,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE item SET size = ?, mtime = ?, inode = ?, json_mtime = ? WHERE id = ?"#,
+        fingerprint.size,
+        fingerprint.mtime,
+        fingerprint.inode,
+        fingerprint.json_mtime,
+        id
+    )
+    .execute(pool)
+    .await
+}
+
+pub async fn mark_checked(pool: &SqlitePool, id: i64) -> Result<SqliteQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE item SET is_checked = true, seen_at = unixepoch() WHERE id = ?"#,
+        id
+    )
+    .execute(pool)
+    .await
+}
+
 pub async fn insert_or_update(
     pool: &SqlitePool,
     name: Option<&str>,
@@ -43,10 +213,9 @@ pub async fn insert_or_update(
     base_label: &str,
     blake3: &str,
     model_name: &str,
+    fingerprint: &Fingerprint,
 ) -> Result<i64, sqlx::Error> {
-    let mut ret_id = 0;
-
-    if let Ok(id) = sqlx::query_scalar!(
+    let ret_id = if let Ok(id) = sqlx::query_scalar!(
         r#"SELECT id FROM item WHERE path = ? AND base_label = ?"#,
         path,
         base_label
@@ -54,23 +223,40 @@ pub async fn insert_or_update(
     .fetch_one(pool)
     .await
     {
-        sqlx::query!(r#"UPDATE item SET is_checked=true WHERE id = ?"#, id,)
-            .execute(pool)
-            .await?;
-        ret_id = id;
+        sqlx::query!(
+            r#"UPDATE item SET is_checked = true, seen_at = unixepoch(), name = ?, model_name = ?, blake3 = ?,
+                size = ?, mtime = ?, inode = ?, json_mtime = ?
+               WHERE id = ?"#,
+            name,
+            model_name,
+            blake3,
+            fingerprint.size,
+            fingerprint.mtime,
+            fingerprint.inode,
+            fingerprint.json_mtime,
+            id,
+        )
+        .execute(pool)
+        .await?;
+        id
     } else {
-        ret_id = sqlx::query!(
-            r#"INSERT INTO item (name, model_name, path, base_label, blake3) VALUES (?, ?, ?, ?, ?) "#,
+        sqlx::query!(
+            r#"INSERT INTO item (name, model_name, path, base_label, blake3, size, mtime, inode, json_mtime, seen_at)
+               VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, unixepoch())"#,
             name,
             model_name,
             path,
             base_label,
             blake3,
+            fingerprint.size,
+            fingerprint.mtime,
+            fingerprint.inode,
+            fingerprint.json_mtime,
         )
         .execute(pool)
         .await?
-        .last_insert_rowid();
-    }
+        .last_insert_rowid()
+    };
 
     Ok(ret_id)
 }
@@ -117,44 +303,322 @@ pub async fn get_tags(pool: &SqlitePool, id: i64) -> Result<Vec<String>, sqlx::E
     .await
 }
 
+/// Search items by name, tags and trigger words.
+/// An item matches if its name or one of its trigger words contains the text, or one of its tags is a word of the text.
+/// Obsolete items are excluded, same as in `get`.
+/// `rank:N` and `alpha:N` tokens filter LoRA network dim and alpha. Tokens with a non-numeric value are plain text.
+/// `civitai:STATUS` filters last Civitai lookup: found, not_found, error or unknown (never looked up).
 pub async fn search(pool: &SqlitePool, search: &str, limit: i64, offset: i64) -> Result<(Vec<Item>, i64), sqlx::Error> {
-    let mut items = sqlx::query_as!(
-        Item,
-        r#"SELECT id,name, path, base_label FROM item WHERE name COLLATE NOCASE LIKE '%' || ? || '%' ORDER BY id DESC LIMIT ? OFFSET ?"#,
-        search,limit, offset
+    let mut query = QueryBuilder::new("SELECT id, name, path, base_label FROM item");
+    push_search_condition(&mut query, search);
+    query.push(" ORDER BY id DESC LIMIT ");
+    query.push_bind(limit);
+    query.push(" OFFSET ");
+    query.push_bind(offset);
+    let items = query.build_query_as::<Item>().fetch_all(pool).await?;
+
+    let mut count_query = QueryBuilder::new("SELECT count(id) FROM item");
+    push_search_condition(&mut count_query, search);
+    let count = count_query.build_query_scalar::<i64>().fetch_one(pool).await?;
+
+    Ok((items, count))
+}
+
+fn push_search_condition(query: &mut QueryBuilder<'_, Sqlite>, search: &str) {
+    // Only items found by the last scan, like `get`
+    query.push(" WHERE is_checked = true");
+
+    let mut words = Vec::new();
+    for token in search.split_whitespace() {
+        match token.split_once(':') {
+            Some(("rank", value)) | Some(("dim", value)) if value.parse::<i64>().is_ok() => {
+                query.push(" AND network_dim = ");
+                query.push_bind(value.parse::<i64>().unwrap_or_default());
+            }
+            Some(("alpha", value)) if value.parse::<f64>().is_ok_and(|v| v.is_finite()) => {
+                query.push(" AND network_alpha = ");
+                query.push_bind(value.parse::<f64>().unwrap_or_default());
+            }
+            Some(("civitai", "unknown")) => {
+                query.push(" AND id NOT IN (SELECT item FROM civitai_lookup)");
+            }
+            Some(("civitai", status)) => {
+                query.push(" AND id IN (SELECT item FROM civitai_lookup WHERE status = ");
+                query.push_bind(status.to_string());
+                query.push(")");
+            }
+            _ => words.push(token.to_string()),
+        }
+    }
+
+    if words.is_empty() {
+        return;
+    }
+    let text = words.join(" ");
+    query.push(" AND (name LIKE '%' || ");
+    query.push_bind(text.clone());
+    query.push(" || '%' OR id IN (SELECT item FROM trigger_word WHERE word LIKE '%' || ");
+    query.push_bind(text);
+    query.push(" || '%') OR id IN (SELECT tag_item.item FROM tag_item INNER JOIN tag ON tag.id = tag_item.tag WHERE tag.name IN (");
+    let mut separated = query.separated(", ");
+    for word in words {
+        separated.push_bind(word);
+    }
+    separated.push_unseparated("))) ");
+}
+
+/// Set model name if it is not known yet (from Civitai)
+pub async fn set_default_model_name(
+    pool: &SqlitePool,
+    id: i64,
+    model_name: &str,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE item SET model_name = ? WHERE id = ? AND (model_name IS NULL OR model_name = '')"#,
+        model_name,
+        id
     )
-        .fetch_all(pool)
-        .await?;
-    let mut count = sqlx::query_scalar!("SELECT count(id) FROM item WHERE name LIKE '%' || ? || '%'", search)
+    .execute(pool)
+    .await
+}
+
+/// Return LoRA network dim and alpha
+pub async fn get_network(pool: &SqlitePool, id: i64) -> Result<(Option<i64>, Option<f64>), sqlx::Error> {
+    let ret = sqlx::query!(r#"SELECT network_dim, network_alpha FROM item WHERE id = ?"#, id)
         .fetch_one(pool)
         .await?;
+    Ok((ret.network_dim, ret.network_alpha))
+}
 
-    let tags: Vec<String> = search.split_whitespace().map(|s| s.to_string()).collect();
+/// Store LoRA network dim and alpha from training metadata
+pub async fn update_network(
+    pool: &SqlitePool,
+    id: i64,
+    network_dim: Option<i64>,
+    network_alpha: Option<f64>,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE item SET network_dim = ?, network_alpha = ? WHERE id = ?"#,
+        network_dim,
+        network_alpha,
+        id
+    )
+    .execute(pool)
+    .await
+}
 
-    if !tags.is_empty() {
-        let condition = format!(
-            "FROM item
-          INNER JOIN tag_item ON item.id = tag_item.item
-          INNER JOIN tag ON tag.id = tag_item.tag
-          WHERE item.name NOT LIKE '%{}%'
-            AND tag.name IN",
-            search
-        );
-        let query = format!(
-            "SELECT item.id as id,  item.name as name, item.path as path, item.base_label as base_label {} ('{}') ORDER BY item.id DESC LIMIT {} OFFSET {}",
-            condition,
-            tags.join("','"),
-            limit,
-            offset
-        );
-        let mut search_by_tags: Vec<Item> = sqlx::query_as(&query).fetch_all(pool).await?;
+/// LoRA weight recommended by model author, None if unknown
+pub async fn get_recommended_weight(pool: &SqlitePool, id: i64) -> Result<Option<f64>, sqlx::Error> {
+    sqlx::query_scalar!(r#"SELECT recommended_weight FROM item WHERE id = ?"#, id)
+        .fetch_one(pool)
+        .await
+}
+
+pub async fn update_recommended_weight(
+    pool: &SqlitePool,
+    id: i64,
+    weight: Option<f64>,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    sqlx::query!(r#"UPDATE item SET recommended_weight = ? WHERE id = ?"#, weight, id)
+        .execute(pool)
+        .await
+}
+
+/// Civitai model and version of item, from its json sidecar
+pub async fn update_civitai_ids(
+    pool: &SqlitePool,
+    id: i64,
+    model_id: Option<i64>,
+    version_id: Option<i64>,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE item SET civitai_model_id = ?, civitai_version_id = ? WHERE id = ?"#,
+        model_id,
+        version_id,
+        id
+    )
+    .execute(pool)
+    .await
+}
 
-        let count_query = format!("SELECT count(*) {} ('{}')", condition, tags.join("','"));
-        let tags_count: i64 = sqlx::query_scalar(&count_query).fetch_one(pool).await?;
+/// Items whose Civitai version is one of `version_ids` or whose BLAKE3 or SHA256 is one of `hashes`.
+/// Hashes are lowercase hex.
+pub async fn find_civitai_matches(
+    pool: &SqlitePool,
+    version_ids: &[i64],
+    hashes: &[String],
+) -> Result<Vec<CivitaiMatch>, sqlx::Error> {
+    if version_ids.is_empty() && hashes.is_empty() {
+        return Ok(Vec::new());
+    }
 
-        count += tags_count;
-        items.append(&mut search_by_tags);
+    let mut query = QueryBuilder::<Sqlite>::new(
+        r#"SELECT item.id, item.civitai_version_id, item.blake3, file_hash.sha256 FROM item
+           LEFT JOIN file_hash ON file_hash.path = item.path AND file_hash.base_label = item.base_label
+           WHERE item.is_checked = true AND (item.civitai_version_id IN ("#,
+    );
+    let mut ids = query.separated(", ");
+    for id in version_ids {
+        ids.push_bind(*id);
+    }
+    query.push(") OR item.blake3 IN (");
+    let mut blake3 = query.separated(", ");
+    for hash in hashes {
+        blake3.push_bind(hash.clone());
     }
+    query.push(") OR file_hash.sha256 IN (");
+    let mut sha256 = query.separated(", ");
+    for hash in hashes {
+        sha256.push_bind(hash.clone());
+    }
+    query.push("))");
 
-    Ok((items, count))
+    query.build_query_as::<CivitaiMatch>().fetch_all(pool).await
+}
+
+/// Get items sharing their content hash with another item, ordered by hash
+pub async fn get_duplicates(pool: &SqlitePool) -> Result<Vec<DuplicateItem>, sqlx::Error> {
+    sqlx::query_as!(
+        DuplicateItem,
+        r#"SELECT id, name, path, base_label, blake3, size, mtime, inode FROM item
+           WHERE is_checked = true AND blake3 IN (
+               SELECT blake3 FROM item WHERE is_checked = true AND blake3 != '' GROUP BY blake3 HAVING count(id) > 1
+           )
+           ORDER BY blake3, id"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Get items with same content hash as the given item, include itself
+pub async fn get_same_hash(pool: &SqlitePool, id: i64) -> Result<Vec<DuplicateItem>, sqlx::Error> {
+    sqlx::query_as!(
+        DuplicateItem,
+        r#"SELECT id, name, path, base_label, blake3, size, mtime, inode FROM item
+           WHERE is_checked = true AND blake3 != '' AND blake3 = (SELECT blake3 FROM item WHERE id = ?)
+           ORDER BY id"#,
+        id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Get items which have Civitai info, in a json sidecar or in DB
+pub async fn get_with_civitai_info(pool: &SqlitePool) -> Result<Vec<Item>, sqlx::Error> {
+    sqlx::query_as!(
+        Item,
+        r#"SELECT id, name, path, base_label FROM item
+           WHERE is_checked = true AND (json_mtime > 0 OR id IN (SELECT item FROM civitai_info))
+           ORDER BY id"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Get hashed items without Civitai info, or whose json sidecar and info in DB are both older than
+/// `info_before` (ns). Items not found on Civitai since `not_found_after` (unix time) are skipped.
+pub async fn get_sync_candidates(
+    pool: &SqlitePool,
+    info_before: i64,
+    not_found_after: i64,
+) -> Result<Vec<SyncItem>, sqlx::Error> {
+    sqlx::query_as!(
+        SyncItem,
+        r#"SELECT id, path, base_label, blake3 FROM item
+           LEFT JOIN civitai_lookup ON civitai_lookup.item = item.id
+           LEFT JOIN civitai_info ON civitai_info.item = item.id
+           WHERE is_checked = true AND blake3 != ''
+               AND ((json_mtime = 0 AND civitai_info.item IS NULL)
+                    OR max(json_mtime, coalesce(civitai_info.updated_at, 0) * 1000000000) < ?)
+               AND (civitai_lookup.status IS NULL OR civitai_lookup.status != 'not_found'
+                    OR civitai_lookup.checked_at <= ?)
+           ORDER BY id"#,
+        info_before,
+        not_found_after
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, memory_pool, TempDir};
+    use serde_json::json;
+    use std::path::Path;
+
+    fn condition(search: &str) -> String {
+        let mut query = QueryBuilder::new("SELECT id FROM item");
+        push_search_condition(&mut query, search);
+        query.sql().to_string()
+    }
+
+    #[test]
+    fn numeric_filters() {
+        let sql = condition("rank:32 alpha:16");
+        assert!(sql.contains("network_dim = ?"));
+        assert!(sql.contains("network_alpha = ?"));
+        assert!(!sql.contains("name LIKE"));
+    }
+
+    #[test]
+    fn non_numeric_filter_is_text() {
+        let sql = condition("rank:abc");
+        assert!(!sql.contains("network_dim"));
+        assert!(sql.contains("name LIKE"));
+
+        let sql = condition("alpha:NaN");
+        assert!(!sql.contains("network_alpha"));
+    }
+
+    /// LoRA with metadata written by kohya-ss sd-scripts
+    fn write_lora(dir: &Path, name: &str, dim: &str, alpha: &str, tag_frequency: &str) {
+        let header = json!({"__metadata__": {
+            "ss_network_dim": dim,
+            "ss_network_alpha": alpha,
+            "ss_tag_frequency": tag_frequency,
+        }})
+        .to_string();
+        let mut content = (header.len() as u64).to_le_bytes().to_vec();
+        content.extend_from_slice(header.as_bytes());
+        std::fs::write(dir.join(format!("{}.safetensors", name)), content).unwrap();
+    }
+
+    #[tokio::test]
+    async fn search_kohya_items() {
+        let dir = TempDir::new();
+        let config = testing::collection(&dir, &["plain"]);
+        write_lora(
+            dir.path(),
+            "lora_a",
+            "32",
+            "16",
+            r#"{"10_ohwx": {"ohwx man": 10, "smile": 2}}"#,
+        );
+        write_lora(dir.path(), "lora_b", "8", "4", r#"{"5_style": {"watercolor": 5}}"#);
+        let pool = memory_pool().await;
+        testing::scan(&config, &pool).await;
+
+        let found = |text: &'static str| {
+            let pool = pool.clone();
+            async move {
+                let (items, total) = search(&pool, text, 10, 0).await.unwrap();
+                assert_eq!(total, items.len() as i64);
+                let mut paths = items.into_iter().map(|i| i.path).collect::<Vec<_>>();
+                paths.sort();
+                paths
+            }
+        };
+        assert_eq!(found("rank:32").await, vec!["lora_a.safetensors"]);
+        assert_eq!(found("dim:8").await, vec!["lora_b.safetensors"]);
+        assert_eq!(found("alpha:16.0").await, vec!["lora_a.safetensors"]);
+        assert!(found("rank:32 alpha:4").await.is_empty());
+        // Trigger words from training tags
+        assert_eq!(found("ohwx").await, vec!["lora_a.safetensors"]);
+        assert_eq!(found("rank:8 water").await, vec!["lora_b.safetensors"]);
+        assert!(found("rank:32 water").await.is_empty());
+        // Not a number, so searched as text
+        assert!(found("rank:abc").await.is_empty());
+        assert_eq!(found("plain").await, vec!["plain.safetensors"]);
+    }
 }
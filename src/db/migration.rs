@@ -0,0 +1,227 @@
+//! Upgrade schema of existing databases.
+//!
+//! `db/db_init.sql` is the full schema for a new database and records the version it matches in `app_info`.
+//! A database without version is at the baseline schema and gets all migrations.
+//! New columns and tables go into a new file in `db/migrations` as well as into `db/db_init.sql`.
+//! Columns added to an existing table go last in `db/db_init.sql`, where `ALTER TABLE` puts them.
+
+use sqlx::SqlitePool;
+use tracing::info;
+
+const SCHEMA_VERSION_LABEL: &str = "schema_version";
+
+/// Migrations in order. Version of a migration is its position, starting from 1.
+const MIGRATIONS: &[(&str, &str)] = &[
+    ("baseline", include_str!("../../db/migrations/0001_baseline.sql")),
+    (
+        "item_fingerprint",
+        include_str!("../../db/migrations/0002_item_fingerprint.sql"),
+    ),
+    (
+        "item_seen_at",
+        include_str!("../../db/migrations/0003_item_seen_at.sql"),
+    ),
+    ("file_hash", include_str!("../../db/migrations/0004_file_hash.sql")),
+    ("scan_error", include_str!("../../db/migrations/0005_scan_error.sql")),
+    (
+        "model_header",
+        include_str!("../../db/migrations/0006_model_header.sql"),
+    ),
+    (
+        "trigger_word",
+        include_str!("../../db/migrations/0007_trigger_word.sql"),
+    ),
+    ("pickle_scan", include_str!("../../db/migrations/0008_pickle_scan.sql")),
+    (
+        "civitai_lookup",
+        include_str!("../../db/migrations/0009_civitai_lookup.sql"),
+    ),
+    (
+        "gallery_image",
+        include_str!("../../db/migrations/0010_gallery_image.sql"),
+    ),
+    (
+        "item_recommended_weight",
+        include_str!("../../db/migrations/0011_item_recommended_weight.sql"),
+    ),
+    (
+        "model_update",
+        include_str!("../../db/migrations/0012_model_update.sql"),
+    ),
+    ("download", include_str!("../../db/migrations/0013_download.sql")),
+    (
+        "item_civitai_ids",
+        include_str!("../../db/migrations/0014_item_civitai_ids.sql"),
+    ),
+    (
+        "civitai_info",
+        include_str!("../../db/migrations/0015_civitai_info.sql"),
+    ),
+    ("hf_source", include_str!("../../db/migrations/0016_hf_source.sql")),
+    (
+        "model_record",
+        include_str!("../../db/migrations/0017_model_record.sql"),
+    ),
+    (
+        "pickle_scan_error",
+        include_str!("../../db/migrations/0018_pickle_scan_error.sql"),
+    ),
+    (
+        "download_kind",
+        include_str!("../../db/migrations/0019_download_kind.sql"),
+    ),
+];
+
+/// Apply migrations newer than the schema version of database, each in its own transaction
+pub async fn run(pool: &SqlitePool) -> anyhow::Result<()> {
+    // Not in app_info of a new empty database
+    sqlx::query!(r#"CREATE TABLE IF NOT EXISTS app_info (label TEXT NOT NULL, value INTEGER NOT NULL)"#)
+        .execute(pool)
+        .await?;
+    let current = sqlx::query_scalar!(r#"SELECT value FROM app_info WHERE label = ?"#, SCHEMA_VERSION_LABEL)
+        .fetch_optional(pool)
+        .await?
+        .unwrap_or_default();
+
+    for (i, (name, sql)) in MIGRATIONS.iter().enumerate() {
+        let version = i as i64 + 1;
+        if version <= current {
+            continue;
+        }
+
+        let mut tx = pool.begin().await?;
+        sqlx::raw_sql(sql)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to migrate to version {} ({}): {}", version, name, e))?;
+        sqlx::query!(r#"DELETE FROM app_info WHERE label = ?"#, SCHEMA_VERSION_LABEL)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!(
+            r#"INSERT INTO app_info (label, value) VALUES (?, ?)"#,
+            SCHEMA_VERSION_LABEL,
+            version
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        info!("Migrated database to version {} ({})", version, name);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use sqlx::Row;
+
+    const DB_INIT: &str = include_str!("../../db/db_init.sql");
+
+    fn latest_version() -> i64 {
+        MIGRATIONS.len() as i64
+    }
+
+    async fn memory_pool() -> SqlitePool {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    /// Columns and indexes of all tables, to compare schemas created in different ways
+    async fn schema(pool: &SqlitePool) -> Vec<String> {
+        let tables = sqlx::query(
+            r#"SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name"#,
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap();
+        let mut ret = Vec::new();
+        for table in tables {
+            let table: String = table.get(0);
+            let columns = sqlx::query(r#"SELECT name, type, "notnull", dflt_value, pk FROM pragma_table_info(?)"#)
+                .bind(&table)
+                .fetch_all(pool)
+                .await
+                .unwrap();
+            for c in columns {
+                ret.push(format!(
+                    "{}.{} {} notnull={} default={:?} pk={}",
+                    table,
+                    c.get::<String, _>(0),
+                    c.get::<String, _>(1).to_lowercase(),
+                    c.get::<i64, _>(2),
+                    c.get::<Option<String>, _>(3),
+                    c.get::<i64, _>(4)
+                ));
+            }
+        }
+        let indexes = sqlx::query(r#"SELECT tbl_name, name FROM sqlite_master WHERE type = 'index' ORDER BY name"#)
+            .fetch_all(pool)
+            .await
+            .unwrap();
+        for index in indexes {
+            ret.push(format!(
+                "{} index {}",
+                index.get::<String, _>(0),
+                index.get::<String, _>(1)
+            ));
+        }
+        ret
+    }
+
+    async fn version(pool: &SqlitePool) -> i64 {
+        sqlx::query_scalar(r#"SELECT value FROM app_info WHERE label = 'schema_version'"#)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn migrations_match_db_init() {
+        let init = memory_pool().await;
+        sqlx::raw_sql(DB_INIT).execute(&init).await.unwrap();
+        assert_eq!(version(&init).await, latest_version());
+
+        let migrated = memory_pool().await;
+        run(&migrated).await.unwrap();
+        assert_eq!(version(&migrated).await, latest_version());
+
+        let expected = schema(&init).await;
+        assert!(expected.iter().any(|c| c.starts_with("item.mtime ")));
+        assert_eq!(expected, schema(&migrated).await);
+    }
+
+    #[tokio::test]
+    async fn upgrade_baseline_database() {
+        let pool = memory_pool().await;
+        sqlx::raw_sql(MIGRATIONS[0].1).execute(&pool).await.unwrap();
+        sqlx::query(r#"INSERT INTO item (path, base_label, name) VALUES ('a.safetensors', 'collection1', 'a')"#)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        run(&pool).await.unwrap();
+        assert_eq!(version(&pool).await, latest_version());
+        let mtime: i64 = sqlx::query_scalar(r#"SELECT mtime FROM item WHERE name = 'a'"#)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(mtime, 0);
+
+        // Nothing left to apply
+        run(&pool).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn new_database_is_not_migrated() {
+        let pool = memory_pool().await;
+        sqlx::raw_sql(DB_INIT).execute(&pool).await.unwrap();
+        // Adding columns of item again would fail
+        run(&pool).await.unwrap();
+        assert_eq!(version(&pool).await, latest_version());
+    }
+}
@@ -0,0 +1,57 @@
+use serde::Serialize;
+use sqlx::sqlite::SqliteQueryResult;
+use sqlx::SqlitePool;
+use std::collections::BTreeMap;
+
+/// Summary of tensors and embedded metadata of a model file
+#[derive(Serialize)]
+pub struct ModelHeader {
+    pub format: String,
+    pub tensor_count: i64,
+    pub param_count: i64,
+    /// Number of tensors per dtype
+    pub dtypes: BTreeMap<String, u64>,
+    pub metadata: BTreeMap<String, String>,
+}
+
+pub async fn insert_or_update(
+    pool: &SqlitePool,
+    item: i64,
+    header: &ModelHeader,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    let dtypes = serde_json::to_string(&header.dtypes).unwrap_or_default();
+    let metadata = serde_json::to_string(&header.metadata).unwrap_or_default();
+    sqlx::query!(
+        r#"INSERT INTO model_header (item, format, tensor_count, param_count, dtypes, metadata)
+           VALUES (?, ?, ?, ?, ?, ?)
+           ON CONFLICT (item) DO UPDATE
+           SET format = excluded.format, tensor_count = excluded.tensor_count, param_count = excluded.param_count,
+               dtypes = excluded.dtypes, metadata = excluded.metadata"#,
+        item,
+        header.format,
+        header.tensor_count,
+        header.param_count,
+        dtypes,
+        metadata,
+    )
+    .execute(pool)
+    .await
+}
+
+pub async fn get(pool: &SqlitePool, item: i64) -> Result<Option<ModelHeader>, sqlx::Error> {
+    let ret = sqlx::query!(
+        r#"SELECT format, tensor_count, param_count, dtypes, metadata FROM model_header WHERE item = ?"#,
+        item
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|r| ModelHeader {
+        format: r.format,
+        tensor_count: r.tensor_count,
+        param_count: r.param_count,
+        dtypes: serde_json::from_str(&r.dtypes).unwrap_or_default(),
+        metadata: serde_json::from_str(&r.metadata).unwrap_or_default(),
+    });
+
+    Ok(ret)
+}
@@ -0,0 +1,52 @@
+use sqlx::sqlite::SqliteQueryResult;
+use sqlx::SqlitePool;
+
+#[derive(sqlx::FromRow)]
+pub struct StoredRecord {
+    pub provider: String,
+    /// Model record as JSON
+    pub record: String,
+}
+
+/// Save record of the provider which identified item
+pub async fn insert_or_update(
+    pool: &SqlitePool,
+    item: i64,
+    provider: &str,
+    record: &str,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"INSERT INTO model_record (item, provider, record, updated_at)
+           VALUES (?, ?, ?, unixepoch())
+           ON CONFLICT (item) DO UPDATE
+           SET provider = excluded.provider, record = excluded.record, updated_at = excluded.updated_at"#,
+        item,
+        provider,
+        record,
+    )
+    .execute(pool)
+    .await
+}
+
+pub async fn delete(pool: &SqlitePool, item: i64) -> Result<SqliteQueryResult, sqlx::Error> {
+    sqlx::query!(r#"DELETE FROM model_record WHERE item = ?"#, item)
+        .execute(pool)
+        .await
+}
+
+pub async fn get(pool: &SqlitePool, item: i64) -> Result<Option<StoredRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        StoredRecord,
+        r#"SELECT provider, record FROM model_record WHERE item = ?"#,
+        item
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Provider which identified item, None if no provider knows it
+pub async fn get_provider(pool: &SqlitePool, item: i64) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar!(r#"SELECT provider FROM model_record WHERE item = ?"#, item)
+        .fetch_optional(pool)
+        .await
+}
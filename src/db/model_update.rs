@@ -0,0 +1,75 @@
+use serde::Serialize;
+use sqlx::sqlite::SqliteQueryResult;
+use sqlx::SqlitePool;
+
+/// Installed version of a model and the latest version on Civitai
+pub struct ModelUpdate {
+    pub model_id: i64,
+    pub version_id: i64,
+    pub version_name: String,
+    pub latest_version_id: i64,
+    pub latest_version_name: String,
+    pub latest_published_at: String,
+    pub latest_base_model: String,
+    pub has_update: bool,
+}
+
+/// Item with a newer version on Civitai
+#[derive(sqlx::FromRow, Serialize)]
+pub struct OutdatedItem {
+    pub id: i64,
+    pub name: String,
+    pub path: String,
+    pub base_label: String,
+    pub model_id: i64,
+    pub version_id: i64,
+    pub version_name: String,
+    pub latest_version_id: i64,
+    pub latest_version_name: String,
+    pub latest_published_at: String,
+    pub latest_base_model: String,
+    pub checked_at: i64,
+}
+
+pub async fn insert_or_update(
+    pool: &SqlitePool,
+    item: i64,
+    update: &ModelUpdate,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"INSERT INTO model_update (item, model_id, version_id, version_name, latest_version_id, latest_version_name,
+                                     latest_published_at, latest_base_model, has_update, checked_at)
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, unixepoch())
+           ON CONFLICT (item) DO UPDATE
+           SET model_id = excluded.model_id, version_id = excluded.version_id, version_name = excluded.version_name,
+               latest_version_id = excluded.latest_version_id, latest_version_name = excluded.latest_version_name,
+               latest_published_at = excluded.latest_published_at, latest_base_model = excluded.latest_base_model,
+               has_update = excluded.has_update, checked_at = excluded.checked_at"#,
+        item,
+        update.model_id,
+        update.version_id,
+        update.version_name,
+        update.latest_version_id,
+        update.latest_version_name,
+        update.latest_published_at,
+        update.latest_base_model,
+        update.has_update,
+    )
+    .execute(pool)
+    .await
+}
+
+/// Items whose newer version was found by the last update check
+pub async fn get_outdated(pool: &SqlitePool) -> Result<Vec<OutdatedItem>, sqlx::Error> {
+    sqlx::query_as!(
+        OutdatedItem,
+        r#"SELECT item.id, item.name, item.path, item.base_label, model_id, version_id, version_name,
+                  latest_version_id, latest_version_name, latest_published_at, latest_base_model, checked_at
+           FROM model_update
+           INNER JOIN item ON item.id = model_update.item
+           WHERE has_update = true AND item.is_checked = true
+           ORDER BY latest_published_at DESC"#
+    )
+    .fetch_all(pool)
+    .await
+}
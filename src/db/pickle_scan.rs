@@ -0,0 +1,58 @@
+use crate::pickle::PickleScan;
+use serde::Serialize;
+use sqlx::sqlite::SqliteQueryResult;
+use sqlx::SqlitePool;
+use std::collections::BTreeSet;
+
+/// Verdict of pickle scanner for an item
+#[derive(Serialize)]
+pub struct PickleVerdict {
+    pub safe: bool,
+    pub globals: BTreeSet<String>,
+    pub unsafe_globals: BTreeSet<String>,
+    pub scanned_at: i64,
+    /// Why the file could not be fully parsed
+    pub error: Option<String>,
+}
+
+pub async fn insert_or_update(
+    pool: &SqlitePool,
+    item: i64,
+    scan: &PickleScan,
+) -> Result<SqliteQueryResult, sqlx::Error> {
+    let safe = scan.is_safe();
+    let globals = serde_json::to_string(&scan.globals).unwrap_or_default();
+    let unsafe_globals = serde_json::to_string(&scan.unsafe_globals).unwrap_or_default();
+    sqlx::query!(
+        r#"INSERT INTO pickle_scan (item, safe, globals, unsafe_globals, scanned_at, error)
+           VALUES (?, ?, ?, ?, unixepoch(), ?)
+           ON CONFLICT (item) DO UPDATE
+           SET safe = excluded.safe, globals = excluded.globals, unsafe_globals = excluded.unsafe_globals,
+               scanned_at = excluded.scanned_at, error = excluded.error"#,
+        item,
+        safe,
+        globals,
+        unsafe_globals,
+        scan.error,
+    )
+    .execute(pool)
+    .await
+}
+
+pub async fn get(pool: &SqlitePool, item: i64) -> Result<Option<PickleVerdict>, sqlx::Error> {
+    let ret = sqlx::query!(
+        r#"SELECT safe, globals, unsafe_globals, scanned_at, error FROM pickle_scan WHERE item = ?"#,
+        item
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|r| PickleVerdict {
+        safe: r.safe,
+        globals: serde_json::from_str(&r.globals).unwrap_or_default(),
+        unsafe_globals: serde_json::from_str(&r.unsafe_globals).unwrap_or_default(),
+        scanned_at: r.scanned_at.unwrap_or_default(),
+        error: r.error,
+    });
+
+    Ok(ret)
+}
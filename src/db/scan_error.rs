@@ -0,0 +1,148 @@
+use serde::Serialize;
+use sqlx::sqlite::SqliteQueryResult;
+use sqlx::SqlitePool;
+use tracing::error;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ScanStage {
+    Walk,
+    ReadSidecar,
+    Parse,
+    Identify,
+    Hash,
+    DBInsert,
+    Quarantine,
+    CivitaiFetch,
+    Thumbnail,
+    Gallery,
+}
+
+impl ScanStage {
+    /// Stages run by reload from disk. Others are run by Civitai sync.
+    pub const SCAN: [ScanStage; 7] = [
+        ScanStage::Walk,
+        ScanStage::ReadSidecar,
+        ScanStage::Parse,
+        ScanStage::Identify,
+        ScanStage::Hash,
+        ScanStage::DBInsert,
+        ScanStage::Quarantine,
+    ];
+    pub const SYNC: [ScanStage; 3] = [ScanStage::CivitaiFetch, ScanStage::Thumbnail, ScanStage::Gallery];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScanStage::Walk => "walk",
+            ScanStage::ReadSidecar => "read_sidecar",
+            ScanStage::Parse => "parse",
+            ScanStage::Identify => "identify",
+            ScanStage::Hash => "hash",
+            ScanStage::DBInsert => "db_insert",
+            ScanStage::Quarantine => "quarantine",
+            ScanStage::CivitaiFetch => "civitai_fetch",
+            ScanStage::Thumbnail => "thumbnail",
+            ScanStage::Gallery => "gallery",
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Serialize)]
+pub struct ScanError {
+    pub id: i64,
+    pub path: String,
+    pub base_label: String,
+    pub stage: String,
+    pub message: String,
+    pub created_at: Option<i64>,
+}
+
+/// Record error of a file. Failing to record is only logged.
+pub async fn insert(pool: &SqlitePool, path: &str, base_label: &str, stage: ScanStage, message: &str) {
+    let stage = stage.as_str();
+    if let Err(e) = sqlx::query!(
+        r#"INSERT INTO scan_error (path, base_label, stage, message, created_at) VALUES (?, ?, ?, ?, unixepoch())"#,
+        path,
+        base_label,
+        stage,
+        message
+    )
+    .execute(pool)
+    .await
+    {
+        error!("Failed to record scan error: {}", e);
+    }
+}
+
+pub async fn get(
+    pool: &SqlitePool,
+    stage: Option<&str>,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<ScanError>, i64), sqlx::Error> {
+    let errors = sqlx::query_as!(
+        ScanError,
+        r#"SELECT id, path, base_label, stage, message, created_at FROM scan_error
+           WHERE ? IS NULL OR stage = ? ORDER BY id DESC LIMIT ? OFFSET ?"#,
+        stage,
+        stage,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let total = sqlx::query_scalar!(
+        r#"SELECT count(id) FROM scan_error WHERE ? IS NULL OR stage = ?"#,
+        stage,
+        stage
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok((errors, total))
+}
+
+/// Remove errors of a file in the given stages before it is processed again
+pub async fn clear_path(
+    pool: &SqlitePool,
+    path: &str,
+    base_label: &str,
+    stages: &[ScanStage],
+) -> Result<(), sqlx::Error> {
+    for stage in stages {
+        let stage = stage.as_str();
+        sqlx::query!(
+            r#"DELETE FROM scan_error WHERE path = ? AND base_label = ? AND stage = ?"#,
+            path,
+            base_label,
+            stage
+        )
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
+}
+
+pub async fn clear_stage(pool: &SqlitePool, stage: ScanStage) -> Result<SqliteQueryResult, sqlx::Error> {
+    let stage = stage.as_str();
+    sqlx::query!(r#"DELETE FROM scan_error WHERE stage = ?"#, stage)
+        .execute(pool)
+        .await
+}
+
+/// Remove errors of files which are no longer in the library.
+/// Walk and DB insert errors have no item, they are cleared when a scan starts instead.
+pub async fn clear_obsolete(pool: &SqlitePool) -> Result<SqliteQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM scan_error WHERE stage NOT IN ('walk', 'db_insert') AND NOT EXISTS (
+               SELECT 1 FROM item WHERE item.is_checked = true
+                   AND item.path = scan_error.path AND item.base_label = scan_error.base_label
+           )"#
+    )
+    .execute(pool)
+    .await
+}
+
+pub async fn clear(pool: &SqlitePool) -> Result<SqliteQueryResult, sqlx::Error> {
+    sqlx::query!(r#"DELETE FROM scan_error"#).execute(pool).await
+}
@@ -1,6 +1,6 @@
-use crate::civitai::{CivitaiFileMetadata, CivitaiModel};
 use sqlx::SqlitePool;
 
+#[allow(dead_code)]
 pub async fn add_tag(pool: &SqlitePool, name: &str) -> anyhow::Result<()> {
     sqlx::query!("INSERT OR IGNORE INTO tag (name) VALUES (?)", name)
         .execute(pool)
@@ -8,6 +8,7 @@ pub async fn add_tag(pool: &SqlitePool, name: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
+#[allow(dead_code)]
 pub async fn remove_tag(pool: &SqlitePool, name: &str) -> anyhow::Result<()> {
     sqlx::query!("DELETE FROM tag WHERE name = ?", name)
         .execute(pool)
@@ -15,6 +16,7 @@ pub async fn remove_tag(pool: &SqlitePool, name: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
+#[allow(dead_code)]
 pub async fn rename_tag(pool: &SqlitePool, name: &str, new_name: &str) -> anyhow::Result<()> {
     if let Ok(exist_name) = sqlx::query_scalar!("SELECT name FROM tag WHERE name = ?", new_name)
         .fetch_one(pool)
@@ -50,35 +52,18 @@ pub async fn add_tag_item(pool: &SqlitePool, item: i64, tags: &Vec<String>) -> R
     Ok(())
 }
 
-pub async fn add_tag_from_model_info(
-    pool: &SqlitePool,
-    item: i64,
-    extra_tags: &Vec<String>,
-    model_info: &CivitaiModel,
-    file_metadata: &CivitaiFileMetadata,
-) -> Result<(), sqlx::Error> {
-    let mut tags = Vec::new();
-    for tag in extra_tags {
-        tags.push(tag.clone().replace(" ", "_").to_lowercase());
-    }
-
-    tags.push(model_info.model_type.clone().replace(" ", "_").to_lowercase());
-    if model_info.nsfw {
-        tags.push(String::from("nsfw"));
-    }
-    if model_info.poi {
-        tags.push(String::from("poi"));
-    }
-    tags.push(file_metadata.format.clone().replace(" ", "_").to_lowercase());
-    if let Some(fp) = file_metadata.fp {
-        tags.push(fp.to_string());
-    }
-    add_tag_item(pool, item, &tags).await
+/// Tag name as stored: lowercase, spaces replaced by underscores
+pub fn normalize(tag: &str) -> String {
+    tag.replace(" ", "_").to_lowercase()
 }
 
 pub async fn remove_tag_item(pool: &SqlitePool, item: i64, tag: &str) -> anyhow::Result<()> {
-    sqlx::query!("DELETE FROM tag_item WHERE item = ? AND tag = ?", item, tag)
-        .execute(pool)
-        .await?;
+    sqlx::query!(
+        "DELETE FROM tag_item WHERE item = ? AND tag IN (SELECT id FROM tag WHERE name = ?)",
+        item,
+        tag
+    )
+    .execute(pool)
+    .await?;
     Ok(())
 }
@@ -0,0 +1,43 @@
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+/// Words from safetensors metadata. Other words come from the provider which identified the item.
+pub const SOURCE_KOHYA: &str = "kohya";
+
+#[derive(sqlx::FromRow, Serialize)]
+pub struct TriggerWord {
+    pub word: String,
+    pub source: String,
+    /// Number of training images with this tag. 0 if unknown.
+    pub count: i64,
+}
+
+/// Replace trigger words of item from a source with new ones
+pub async fn replace(pool: &SqlitePool, item: i64, source: &str, words: &[(String, i64)]) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query!("DELETE FROM trigger_word WHERE item = ? AND source = ?", item, source)
+        .execute(&mut *tx)
+        .await?;
+    for (word, count) in words {
+        sqlx::query!(
+            "INSERT OR IGNORE INTO trigger_word (item, word, source, count) VALUES (?, ?, ?, ?)",
+            item,
+            word,
+            source,
+            count
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+pub async fn get(pool: &SqlitePool, item: i64) -> Result<Vec<TriggerWord>, sqlx::Error> {
+    sqlx::query_as!(
+        TriggerWord,
+        "SELECT word, source, count FROM trigger_word WHERE item = ? ORDER BY count DESC, rowid",
+        item
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,290 @@
+//! Copyright (c) 2025 Trung Do <dothanhtrung@pm.me>.
+//!
+//! Find items with same content across all collections and replace copies with links.
+
+use crate::civitai::calculate_hashes;
+use crate::config::Config;
+use crate::db::item;
+use crate::db::item::DuplicateItem;
+use crate::scanner::get_fingerprint;
+use crate::trash;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Suffix of temporary link before it replaces the duplicate file
+const LINK_TMP_EXT: &str = "sdmm-link";
+
+#[derive(Serialize)]
+pub struct DuplicateGroup {
+    pub blake3: String,
+    pub size: i64,
+    /// Bytes which can be freed by keeping only one copy. Files already hardlinked are counted once.
+    pub wasted: i64,
+    pub items: Vec<DuplicateEntry>,
+}
+
+#[derive(Serialize)]
+pub struct DuplicateEntry {
+    pub id: i64,
+    pub name: String,
+    pub base_label: String,
+    pub path: String,
+}
+
+#[derive(Serialize, Default)]
+pub struct Resolution {
+    /// Items which are trashed or linked
+    pub resolved: Vec<i64>,
+    pub errors: Vec<String>,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResolveAction {
+    Trash,
+    Hardlink,
+    Symlink,
+}
+
+/// Group items by content hash. Groups where all files are already the same inode are skipped.
+pub async fn find(config: &Config, pool: &SqlitePool) -> Result<Vec<DuplicateGroup>, sqlx::Error> {
+    let mut groups: Vec<DuplicateGroup> = Vec::new();
+    let mut inodes = HashSet::new();
+
+    for dup in item::get_duplicates(pool).await? {
+        if groups.last().is_none_or(|g| g.blake3 != dup.blake3) {
+            inodes.clear();
+            groups.push(DuplicateGroup {
+                blake3: dup.blake3.clone(),
+                size: dup.size,
+                wasted: 0,
+                items: Vec::new(),
+            });
+        }
+
+        let Some(group) = groups.last_mut() else {
+            continue;
+        };
+        if !group.items.is_empty() && (dup.inode == 0 || inodes.insert(dup.inode)) {
+            group.wasted += dup.size;
+        } else {
+            inodes.insert(dup.inode);
+        }
+        group.items.push(DuplicateEntry {
+            id: dup.id,
+            name: dup.name,
+            path: abs_path(config, &dup.base_label, &dup.path)
+                .to_str()
+                .unwrap_or_default()
+                .to_string(),
+            base_label: dup.base_label,
+        });
+    }
+
+    groups.retain(|g| g.wasted > 0);
+    Ok(groups)
+}
+
+/// Keep one item of a duplicate group and trash or link the others to it.
+/// Files changed since last scan are left alone.
+pub async fn resolve(config: &Config, pool: &SqlitePool, keep: i64, action: ResolveAction) -> Resolution {
+    let mut ret = Resolution::default();
+    let group = match item::get_same_hash(pool, keep).await {
+        Ok(group) => group,
+        Err(e) => {
+            ret.errors.push(e.to_string());
+            return ret;
+        }
+    };
+    let Some(keep) = group.iter().find(|i| i.id == keep) else {
+        ret.errors.push(format!("Item {} not found or has no hash", keep));
+        return ret;
+    };
+    let keep_path = abs_path(config, &keep.base_label, &keep.path);
+    if let Err(e) = verify(keep, &keep_path).await {
+        ret.errors.push(format!("Item {}: {}", keep.id, e));
+        return ret;
+    }
+
+    for dup in group.iter().filter(|i| i.id != keep.id) {
+        let dup_path = abs_path(config, &dup.base_label, &dup.path);
+        let result = if dup.size != keep.size {
+            Err(anyhow::anyhow!("Size is different from item {}", keep.id))
+        } else if let Err(e) = verify(dup, &dup_path).await {
+            Err(e)
+        } else if action == ResolveAction::Trash {
+            trash::trash_item(config, pool, dup.id).await
+        } else {
+            link_item(pool, dup.id, &keep_path, &dup_path, action).await
+        };
+
+        match result {
+            Ok(_) => ret.resolved.push(dup.id),
+            Err(e) => ret.errors.push(format!("Item {}: {}", dup.id, e)),
+        }
+    }
+
+    ret
+}
+
+/// Link file of item to `keep` and store its new fingerprint, so it is not hashed again by next scan
+async fn link_item(pool: &SqlitePool, id: i64, keep: &Path, dup: &Path, action: ResolveAction) -> anyhow::Result<()> {
+    link(keep, dup, action)?;
+    let fingerprint = get_fingerprint(dup).await?;
+    item::update_fingerprint(pool, id, &fingerprint).await?;
+    Ok(())
+}
+
+/// Replace `dup` with a hardlink or symlink to `keep`.
+/// The link is created next to `dup` first so `dup` is untouched if linking fails.
+fn link(keep: &Path, dup: &Path, action: ResolveAction) -> anyhow::Result<()> {
+    let mut tmp = dup.to_path_buf();
+    tmp.set_extension(LINK_TMP_EXT);
+
+    match action {
+        ResolveAction::Hardlink => std::fs::hard_link(keep, &tmp)?,
+        ResolveAction::Symlink => symlink(keep, &tmp)?,
+        ResolveAction::Trash => return Err(anyhow::anyhow!("Trash is not a link action")),
+    }
+
+    if let Err(e) = std::fs::rename(&tmp, dup) {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+/// Check that file of item still has the content found by last scan.
+/// File is hashed again only if its size, mtime or inode changed.
+async fn verify(item: &DuplicateItem, path: &Path) -> anyhow::Result<()> {
+    let fingerprint = get_fingerprint(path).await?;
+    if fingerprint.size == item.size && fingerprint.mtime == item.mtime && fingerprint.inode == item.inode {
+        return Ok(());
+    }
+
+    let file = path.to_path_buf();
+    let hashes = tokio::task::spawn_blocking(move || calculate_hashes(&file)).await??;
+    if hashes.blake3 != item.blake3 {
+        return Err(anyhow::anyhow!("{} changed since last scan", path.display()));
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+pub fn abs_path(config: &Config, label: &str, rel_path: &str) -> PathBuf {
+    config
+        .model_paths
+        .get(label)
+        .map(|base_path| PathBuf::from(base_path).join(rel_path))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, memory_pool, TempDir, LABEL};
+    use std::fs;
+
+    async fn item_id(pool: &SqlitePool, path: &str) -> i64 {
+        item::get_fingerprint(pool, path, LABEL).await.unwrap().unwrap().0
+    }
+
+    /// Collection with `a` copied to `sub/a` and `b` hardlinked to `c`
+    async fn duplicates(dir: &TempDir) -> (Config, SqlitePool) {
+        let config = testing::collection(dir, &["a", "b"]);
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::copy(dir.path().join("a.safetensors"), dir.path().join("sub/a.safetensors")).unwrap();
+        fs::hard_link(dir.path().join("b.safetensors"), dir.path().join("c.safetensors")).unwrap();
+        let pool = memory_pool().await;
+        testing::scan(&config, &pool).await;
+        (config, pool)
+    }
+
+    #[tokio::test]
+    async fn find_copies() {
+        let dir = TempDir::new();
+        let (config, pool) = duplicates(&dir).await;
+
+        let groups = find(&config, &pool).await.unwrap();
+        // b and c are the same file already
+        assert_eq!(groups.len(), 1);
+        let group = &groups[0];
+        let size = fs::metadata(dir.path().join("a.safetensors")).unwrap().len() as i64;
+        assert_eq!((group.size, group.wasted), (size, size));
+        let ids = group.items.iter().map(|i| i.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            vec![
+                item_id(&pool, "a.safetensors").await,
+                item_id(&pool, "sub/a.safetensors").await
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn resolve_with_hardlink() {
+        let dir = TempDir::new();
+        let (config, pool) = duplicates(&dir).await;
+        let keep = item_id(&pool, "a.safetensors").await;
+        let dup = item_id(&pool, "sub/a.safetensors").await;
+
+        let resolution = resolve(&config, &pool, keep, ResolveAction::Hardlink).await;
+        assert_eq!(resolution.resolved, vec![dup]);
+        assert!(resolution.errors.is_empty());
+
+        let fingerprint = get_fingerprint(&dir.path().join("sub/a.safetensors")).await.unwrap();
+        let kept = get_fingerprint(&dir.path().join("a.safetensors")).await.unwrap();
+        assert_eq!(fingerprint.inode, kept.inode);
+        // Next scan does not hash the linked file again
+        let (_, stored) = item::get_fingerprint(&pool, "sub/a.safetensors", LABEL)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored, fingerprint);
+        assert!(find(&config, &pool).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn resolve_with_trash() {
+        let dir = TempDir::new();
+        let (config, pool) = duplicates(&dir).await;
+        let keep = item_id(&pool, "sub/a.safetensors").await;
+        let dup = item_id(&pool, "a.safetensors").await;
+
+        let resolution = resolve(&config, &pool, keep, ResolveAction::Trash).await;
+        assert_eq!(resolution.resolved, vec![dup]);
+        assert!(!dir.path().join("a.safetensors").exists());
+        assert!(dir.path().join(trash::TRASH_DIR).join("a.safetensors").exists());
+        assert!(find(&config, &pool).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn changed_file_is_kept() {
+        let dir = TempDir::new();
+        let (config, pool) = duplicates(&dir).await;
+        let keep = item_id(&pool, "a.safetensors").await;
+        let copy = dir.path().join("sub/a.safetensors");
+        // Same size, other content
+        let mut content = fs::read(&copy).unwrap();
+        *content.last_mut().unwrap() ^= 1;
+        fs::write(&copy, &content).unwrap();
+
+        let resolution = resolve(&config, &pool, keep, ResolveAction::Hardlink).await;
+        assert!(resolution.resolved.is_empty());
+        assert_eq!(resolution.errors.len(), 1);
+        assert_eq!(fs::read(&copy).unwrap(), content);
+    }
+}
@@ -0,0 +1,311 @@
+//! Copyright (c) 2025 Trung Do <dothanhtrung@pm.me>.
+//!
+//! Read key/values and tensor infos of GGUF file (version 2 and 3) without loading tensors.
+
+use crate::safetensors::{TensorHeader, TensorInfo};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+const GGUF_MAGIC: &[u8; 4] = b"GGUF";
+
+/// Refuse strings and arrays longer than this to avoid allocating on corrupted files
+const MAX_LEN: u64 = 64 * 1024 * 1024;
+
+/// Arrays (e.g. tokenizer vocab) longer than this are stored as a summary in metadata
+const MAX_ARRAY_IN_METADATA: u64 = 16;
+
+/// Refuse arrays nested deeper than this. GGUF writers only nest arrays one level.
+const MAX_ARRAY_DEPTH: u32 = 4;
+
+/// GGML tensors have at most 4 dimensions
+const MAX_DIMS: u32 = 4;
+
+pub fn read_header(path: &Path) -> anyhow::Result<TensorHeader> {
+    parse_header(&mut BufReader::new(File::open(path)?))
+}
+
+fn parse_header(reader: &mut impl Read) -> anyhow::Result<TensorHeader> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != GGUF_MAGIC {
+        return Err(anyhow::anyhow!("Not a GGUF file"));
+    }
+    let version = read_u32(reader)?;
+    if version < 2 {
+        return Err(anyhow::anyhow!("Unsupported GGUF version {}", version));
+    }
+
+    let tensor_count = read_u64(reader)?;
+    let kv_count = read_u64(reader)?;
+
+    let mut metadata = BTreeMap::new();
+    for _ in 0..kv_count {
+        let key = read_string(reader)?;
+        let value_type = read_u32(reader)?;
+        let value = read_value(reader, value_type, 0)?;
+        metadata.insert(key, value);
+    }
+
+    let mut tensors = BTreeMap::new();
+    for _ in 0..tensor_count {
+        let name = read_string(reader)?;
+        let n_dims = read_u32(reader)?;
+        if n_dims > MAX_DIMS {
+            return Err(anyhow::anyhow!(
+                "GGUF tensor {} has too many dimensions: {}",
+                name,
+                n_dims
+            ));
+        }
+        let mut shape = Vec::with_capacity(n_dims as usize);
+        for _ in 0..n_dims {
+            shape.push(read_u64(reader)?);
+        }
+        // GGUF stores dims from innermost, reverse to match safetensors/torch order
+        shape.reverse();
+        let dtype = ggml_type_name(read_u32(reader)?);
+        let _offset = read_u64(reader)?;
+        tensors.insert(name, TensorInfo { dtype, shape });
+    }
+
+    TensorHeader::new(tensors, metadata)
+}
+
+/// Read a value and format it as string. `depth` is the number of arrays the value is nested in.
+fn read_value(reader: &mut impl Read, value_type: u32, depth: u32) -> anyhow::Result<String> {
+    let value = match value_type {
+        0 => read_bytes::<1>(reader)?[0].to_string(),
+        1 => (read_bytes::<1>(reader)?[0] as i8).to_string(),
+        2 => u16::from_le_bytes(read_bytes(reader)?).to_string(),
+        3 => i16::from_le_bytes(read_bytes(reader)?).to_string(),
+        4 => read_u32(reader)?.to_string(),
+        5 => i32::from_le_bytes(read_bytes(reader)?).to_string(),
+        6 => f32::from_le_bytes(read_bytes(reader)?).to_string(),
+        7 => (read_bytes::<1>(reader)?[0] != 0).to_string(),
+        8 => read_string(reader)?,
+        9 => {
+            if depth >= MAX_ARRAY_DEPTH {
+                return Err(anyhow::anyhow!("GGUF arrays nested too deep"));
+            }
+            let item_type = read_u32(reader)?;
+            let len = read_u64(reader)?;
+            if len > MAX_LEN {
+                return Err(anyhow::anyhow!("GGUF array too long: {}", len));
+            }
+            let mut items = Vec::with_capacity(len.min(MAX_ARRAY_IN_METADATA) as usize);
+            for _ in 0..len {
+                let item = read_value(reader, item_type, depth + 1)?;
+                if len <= MAX_ARRAY_IN_METADATA {
+                    items.push(item);
+                }
+            }
+            if len <= MAX_ARRAY_IN_METADATA {
+                serde_json::to_string(&items)?
+            } else {
+                format!("[{} items]", len)
+            }
+        }
+        10 => read_u64(reader)?.to_string(),
+        11 => i64::from_le_bytes(read_bytes(reader)?).to_string(),
+        12 => f64::from_le_bytes(read_bytes(reader)?).to_string(),
+        t => return Err(anyhow::anyhow!("Unknown GGUF value type {}", t)),
+    };
+
+    Ok(value)
+}
+
+fn read_bytes<const N: usize>(reader: &mut impl Read) -> std::io::Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_u32(reader: &mut impl Read) -> std::io::Result<u32> {
+    Ok(u32::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_u64(reader: &mut impl Read) -> std::io::Result<u64> {
+    Ok(u64::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_string(reader: &mut impl Read) -> anyhow::Result<String> {
+    let len = read_u64(reader)?;
+    if len > MAX_LEN {
+        return Err(anyhow::anyhow!("GGUF string too long: {}", len));
+    }
+    // Buffer grows with bytes actually read, so a corrupted length does not allocate up front
+    let mut buf = Vec::new();
+    reader.take(len).read_to_end(&mut buf)?;
+    if buf.len() as u64 != len {
+        return Err(anyhow::anyhow!("GGUF string truncated"));
+    }
+    Ok(String::from_utf8_lossy(&buf).to_string())
+}
+
+fn ggml_type_name(t: u32) -> String {
+    let name = match t {
+        0 => "F32",
+        1 => "F16",
+        2 => "Q4_0",
+        3 => "Q4_1",
+        6 => "Q5_0",
+        7 => "Q5_1",
+        8 => "Q8_0",
+        9 => "Q8_1",
+        10 => "Q2_K",
+        11 => "Q3_K",
+        12 => "Q4_K",
+        13 => "Q5_K",
+        14 => "Q6_K",
+        15 => "Q8_K",
+        16 => "IQ2_XXS",
+        17 => "IQ2_XS",
+        18 => "IQ3_XXS",
+        19 => "IQ1_S",
+        20 => "IQ4_NL",
+        21 => "IQ3_S",
+        22 => "IQ2_S",
+        23 => "IQ4_XS",
+        24 => "I8",
+        25 => "I16",
+        26 => "I32",
+        27 => "I64",
+        28 => "F64",
+        29 => "IQ1_M",
+        30 => "BF16",
+        _ => return format!("TYPE_{}", t),
+    };
+    name.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writer of crafted GGUF headers
+    #[derive(Default)]
+    struct Gguf(Vec<u8>);
+
+    impl Gguf {
+        fn new(tensor_count: u64, kv_count: u64) -> Self {
+            Self::default().bytes(GGUF_MAGIC).u32(3).u64(tensor_count).u64(kv_count)
+        }
+
+        fn bytes(mut self, b: &[u8]) -> Self {
+            self.0.extend_from_slice(b);
+            self
+        }
+
+        fn u32(self, v: u32) -> Self {
+            self.bytes(&v.to_le_bytes())
+        }
+
+        fn u64(self, v: u64) -> Self {
+            self.bytes(&v.to_le_bytes())
+        }
+
+        fn string(self, s: &str) -> Self {
+            self.u64(s.len() as u64).bytes(s.as_bytes())
+        }
+
+        fn tensor(self, name: &str, dims: &[u64], dtype: u32) -> Self {
+            let mut ret = self.string(name).u32(dims.len() as u32);
+            for dim in dims {
+                ret = ret.u64(*dim);
+            }
+            ret.u32(dtype).u64(0)
+        }
+
+        fn parse(&self) -> anyhow::Result<TensorHeader> {
+            parse_header(&mut &self.0[..])
+        }
+    }
+
+    #[test]
+    fn parse_metadata_and_tensors() {
+        let header = Gguf::new(2, 3)
+            .string("general.architecture")
+            .u32(8)
+            .string("flux")
+            .string("general.file_type")
+            .u32(4)
+            .u32(8)
+            .string("tokens")
+            .u32(9)
+            .u32(8)
+            .u64(2)
+            .string("a")
+            .string("b")
+            .tensor("img_in.weight", &[64, 3072], 8)
+            .tensor("img_in.bias", &[3072], 0)
+            .parse()
+            .unwrap();
+
+        assert_eq!(header.metadata["general.architecture"], "flux");
+        assert_eq!(header.metadata["general.file_type"], "8");
+        assert_eq!(header.metadata["tokens"], r#"["a","b"]"#);
+        assert_eq!(header.tensors["img_in.weight"].shape, vec![3072, 64]);
+        assert_eq!(header.tensors["img_in.weight"].dtype, "Q8_0");
+        assert_eq!(header.param_count(), 3072 * 64 + 3072);
+    }
+
+    #[test]
+    fn summarize_long_array() {
+        let mut gguf = Gguf::new(0, 1).string("scores").u32(9).u32(4).u64(100);
+        for i in 0..100 {
+            gguf = gguf.u32(i);
+        }
+        assert_eq!(gguf.parse().unwrap().metadata["scores"], "[100 items]");
+    }
+
+    #[test]
+    fn reject_bad_magic_and_version() {
+        assert!(Gguf::default().bytes(b"GGUX").u32(3).u64(0).u64(0).parse().is_err());
+        assert!(Gguf::default().bytes(GGUF_MAGIC).u32(1).u64(0).u64(0).parse().is_err());
+    }
+
+    #[test]
+    fn reject_too_many_dims() {
+        let gguf = Gguf::new(1, 0).tensor("t", &[1, 1, 1, 1, 1], 0);
+        assert!(gguf.parse().is_err());
+        // A huge dimension count fails before allocating
+        let gguf = Gguf::new(1, 0).string("t").u32(u32::MAX);
+        assert!(gguf.parse().is_err());
+    }
+
+    #[test]
+    fn reject_deeply_nested_arrays() {
+        let mut gguf = Gguf::new(0, 1).string("nested").u32(9);
+        for _ in 0..=MAX_ARRAY_DEPTH {
+            gguf = gguf.u32(9).u64(1);
+        }
+        let err = gguf.u32(4).u64(1).u32(0).parse().err().unwrap();
+        assert!(err.to_string().contains("nested too deep"));
+    }
+
+    #[test]
+    fn reject_oversized_lengths() {
+        // Array and string lengths are checked before reading items
+        let gguf = Gguf::new(0, 1).string("a").u32(9).u32(4).u64(u64::MAX);
+        assert!(gguf.parse().is_err());
+        let gguf = Gguf::new(0, 1).string("s").u32(8).u64(MAX_LEN + 1);
+        assert!(gguf.parse().is_err());
+        // Length within limit but longer than the file
+        let gguf = Gguf::new(0, 1).string("s").u32(8).u64(MAX_LEN).bytes(b"short");
+        assert!(gguf.parse().is_err());
+    }
+
+    #[test]
+    fn reject_overflowing_shape() {
+        let gguf = Gguf::new(1, 0).tensor("t", &[u64::MAX, 2], 0);
+        assert!(gguf.parse().is_err());
+    }
+
+    #[test]
+    fn reject_unknown_value_type() {
+        let gguf = Gguf::new(0, 1).string("k").u32(13).u32(0);
+        assert!(gguf.parse().is_err());
+    }
+}
@@ -0,0 +1,711 @@
+//! Copyright (c) 2025 Trung Do <dothanhtrung@pm.me>.
+//!
+//! Hugging Face Hub as a source of models: match local files with repo files by SHA256 (LFS oid),
+//! and download repo files into a collection. Matched items are identified from their model card.
+
+use crate::civitai::download::{check_free_space, part_path, write_part, DownloadProgress, Transfers};
+use crate::config::{Config, HuggingFaceConfig};
+use crate::db::hash;
+use crate::db::hf_source::{self, HfSource, UnresolvedItem};
+use crate::db::item;
+use crate::duplicate::abs_path;
+use crate::job::Job;
+use crate::provider::{self, MetadataProvider, ModelFile, ModelRecord, ProviderKind, Providers};
+use crate::scanner::{get_fingerprint, get_relative_path, index_file};
+use futures_util::future::BoxFuture;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, RANGE};
+use reqwest::{Client, Proxy, Response, StatusCode};
+use serde_json::Value;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio::fs;
+use tracing::{info, warn};
+
+pub const DEFAULT_REVISION: &str = "main";
+
+/// File of a repo, from `siblings` of repo info
+struct RepoFile {
+    path: String,
+    size: u64,
+    /// LFS oid. Small files stored in git have none.
+    sha256: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct HuggingFaceClient {
+    client: Client,
+    base_url: String,
+    timeout: Duration,
+    download_timeout: Duration,
+}
+
+impl HuggingFaceClient {
+    pub fn new(config: &HuggingFaceConfig) -> anyhow::Result<Self> {
+        let mut headers = HeaderMap::new();
+        if !config.token.is_empty() {
+            let mut auth = HeaderValue::from_str(&format!("Bearer {}", config.token))?;
+            auth.set_sensitive(true);
+            headers.insert(AUTHORIZATION, auth);
+        }
+        let mut builder = Client::builder()
+            .user_agent(&config.user_agent)
+            .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+            .default_headers(headers);
+        if let Some(proxy) = &config.proxy {
+            builder = builder.proxy(Proxy::all(proxy)?);
+        }
+
+        Ok(Self {
+            client: builder.build()?,
+            base_url: config.base_url.trim_end_matches('/').to_string(),
+            timeout: Duration::from_secs(config.timeout_secs),
+            download_timeout: Duration::from_secs(config.download_timeout_secs),
+        })
+    }
+
+    /// Repo info at `revision`, with LFS hash of each file
+    async fn get_repo(&self, repo_id: &str, revision: &str) -> anyhow::Result<Value> {
+        let url = format!("{}/api/models/{}/revision/{}", self.base_url, repo_id, revision);
+        let response = self
+            .client
+            .get(url)
+            .query(&[("blobs", "true")])
+            .timeout(self.timeout)
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!(
+                "Hugging Face returned {} for {}: {}",
+                status,
+                repo_id,
+                body
+            ));
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// README.md of repo, empty if it has none
+    async fn get_model_card(&self, repo_id: &str, revision: &str) -> anyhow::Result<String> {
+        let url = format!("{}/{}/raw/{}/README.md", self.base_url, repo_id, revision);
+        let response = self.client.get(url).timeout(self.timeout).send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(String::new());
+        }
+
+        Ok(response.error_for_status()?.text().await?)
+    }
+
+    /// Start downloading a repo file from byte `offset`. Check the response status, the range may be ignored.
+    async fn download_from(&self, repo_id: &str, revision: &str, file: &str, offset: u64) -> reqwest::Result<Response> {
+        let url = format!("{}/{}/resolve/{}/{}", self.base_url, repo_id, revision, file);
+        let mut request = self.client.get(url).timeout(self.download_timeout);
+        if offset > 0 {
+            request = request.header(RANGE, format!("bytes={}-", offset));
+        }
+
+        request.send().await
+    }
+}
+
+/// File to download from a repo into `subfolder` of a collection
+pub struct DownloadRequest {
+    pub repo_id: String,
+    pub revision: String,
+    /// Path of file in repo. Its folders in repo are kept under `subfolder`.
+    pub file: String,
+    pub label: String,
+    pub subfolder: String,
+}
+
+/// Match hashed items with files of repos in config, by SHA256.
+/// Each repo is requested once, items already matched or identified by a provider tried before are skipped.
+pub async fn sync(
+    config: &Config,
+    pool: &SqlitePool,
+    client: &HuggingFaceClient,
+    providers: &Providers,
+    job: &Job,
+) -> anyhow::Result<()> {
+    if !config.providers.order.contains(&ProviderKind::Huggingface) {
+        info!("Hugging Face is not in providers.order, nothing to sync");
+        return Ok(());
+    }
+    if config.huggingface.repos.is_empty() {
+        info!("No Hugging Face repo to match with");
+        return Ok(());
+    }
+
+    let mut items: HashMap<String, Vec<UnresolvedItem>> = HashMap::new();
+    for item in hf_source::get_unresolved(pool).await? {
+        if !provider::needs_sync(config, pool, ProviderKind::Huggingface, item.id).await? {
+            continue;
+        }
+        job.inc_seen();
+        items.entry(item.sha256.to_lowercase()).or_default().push(item);
+    }
+
+    for repo_id in &config.huggingface.repos {
+        if job.is_cancelled() || items.is_empty() {
+            break;
+        }
+        let info = match client.get_repo(repo_id, DEFAULT_REVISION).await {
+            Ok(info) => info,
+            Err(e) => {
+                job.fail(e.to_string());
+                continue;
+            }
+        };
+        job.inc_fetched();
+
+        let found = repo_files(&info)
+            .into_iter()
+            .filter_map(|file| {
+                let found = items.remove(file.sha256.as_ref()?)?;
+                Some((file, found))
+            })
+            .collect::<Vec<_>>();
+        if found.is_empty() {
+            continue;
+        }
+
+        let source = repo_source(client, repo_id, &info).await;
+        for (file, found) in found {
+            for item in found {
+                info!("Found {}/{} in {}: {}", item.base_label, item.path, repo_id, file.path);
+                let source = HfSource {
+                    file: file.path.clone(),
+                    ..source.clone()
+                };
+                if let Err(e) = set_source(config, pool, providers, Some(job), item.id, &source).await {
+                    job.fail(format!("{}/{}: {}", item.base_label, item.path, e));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Match an item with a file of a user given repo
+pub async fn resolve(
+    config: &Config,
+    pool: &SqlitePool,
+    client: &HuggingFaceClient,
+    providers: &Providers,
+    item: i64,
+    repo_id: &str,
+    revision: &str,
+) -> anyhow::Result<HfSource> {
+    let sha256 = hf_source::get_sha256(pool, item)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Item {} is not hashed yet", item))?;
+    let info = client.get_repo(repo_id, revision).await?;
+    let file = repo_files(&info)
+        .into_iter()
+        .find(|f| f.sha256.as_deref() == Some(sha256.to_lowercase().as_str()))
+        .ok_or_else(|| anyhow::anyhow!("No file in {} matches SHA256 of item {}", repo_id, item))?;
+
+    let source = HfSource {
+        file: file.path,
+        ..repo_source(client, repo_id, &info).await
+    };
+    set_source(config, pool, providers, None, item, &source).await?;
+
+    Ok(source)
+}
+
+/// Download a file of a repo, verify its SHA256, index it and record where it comes from.
+/// A `.part` file left by an interrupted download is resumed.
+#[allow(clippy::too_many_arguments)]
+pub async fn download(
+    config: &Config,
+    pool: &SqlitePool,
+    client: &HuggingFaceClient,
+    providers: &Providers,
+    job: &Job,
+    request: &DownloadRequest,
+    progress: &DownloadProgress,
+    transfers: &Transfers,
+) -> anyhow::Result<()> {
+    let label = request.label.as_str();
+    let Some(base_path) = config.model_paths.get(label) else {
+        return Err(anyhow::anyhow!("Unknown collection {}", label));
+    };
+    let subfolder = Path::new(&request.subfolder);
+    if !is_relative(subfolder) {
+        return Err(anyhow::anyhow!("Invalid subfolder {}", subfolder.display()));
+    }
+
+    let info = client.get_repo(&request.repo_id, &request.revision).await?;
+    let file = repo_files(&info)
+        .into_iter()
+        .find(|f| f.path == request.file)
+        .ok_or_else(|| anyhow::anyhow!("{} is not in {}", request.file, request.repo_id))?;
+    if !is_relative(Path::new(&file.path)) {
+        return Err(anyhow::anyhow!("Invalid file name {}", file.path));
+    }
+    // Download the resolved commit, so the file matches the model card recorded with it
+    let source = HfSource {
+        file: file.path.clone(),
+        ..repo_source(client, &request.repo_id, &info).await
+    };
+
+    let path = PathBuf::from(base_path).join(subfolder).join(&file.path);
+    let _reservation = transfers.reserve(&path)?;
+    if path.exists() {
+        return Err(anyhow::anyhow!("File already exists: {}", path.display()));
+    }
+    let relative_path = get_relative_path(base_path, &path)?;
+    *progress.path.lock().unwrap() = relative_path.clone();
+    let dir = path.parent().unwrap_or(&path);
+    fs::create_dir_all(dir).await?;
+    let part = part_path(&path);
+    progress.total.store(file.size, Ordering::Relaxed);
+    check_free_space(config, dir, &part, file.size).await?;
+
+    info!("Download {}/{} to {}", source.repo_id, file.path, path.display());
+    job.inc_seen();
+    let offset = fs::metadata(&part).await.map(|m| m.len()).unwrap_or(0);
+    let response = client
+        .download_from(&source.repo_id, &source.revision, &file.path, offset)
+        .await?;
+    let hashes = write_part(transfers, job, response, offset, &part, progress).await?;
+    match &file.sha256 {
+        Some(expected) if !expected.eq_ignore_ascii_case(&hashes.sha256) => {
+            // Resuming a corrupted part would fail again
+            fs::remove_file(&part).await?;
+            return Err(anyhow::anyhow!(
+                "SHA256 mismatch: expected {}, got {}",
+                expected,
+                hashes.sha256
+            ));
+        }
+        Some(_) => {}
+        None => warn!("No SHA256 to verify {}", file.path),
+    }
+    fs::rename(&part, &path).await?;
+    job.inc_hashed();
+
+    // Hashes are already known, so indexing does not read the file again
+    let fingerprint = get_fingerprint(&path).await?;
+    hash::insert_or_update(
+        pool,
+        &relative_path,
+        label,
+        fingerprint.size,
+        fingerprint.mtime,
+        &hashes,
+    )
+    .await?;
+    index_file(config, pool, providers, label, &relative_path, &fingerprint, Some(job)).await;
+    let Some((id, _)) = item::get_fingerprint(pool, &relative_path, label).await? else {
+        return Err(anyhow::anyhow!("Downloaded file is not indexed: {}", path.display()));
+    };
+    set_source(config, pool, providers, Some(job), id, &source).await
+}
+
+/// Repo, revision, license and model card shared by all files of repo.
+/// A missing model card is only logged.
+async fn repo_source(client: &HuggingFaceClient, repo_id: &str, info: &Value) -> HfSource {
+    let revision = info["sha"].as_str().unwrap_or(DEFAULT_REVISION).to_string();
+    let model_card = client
+        .get_model_card(repo_id, &revision)
+        .await
+        .inspect_err(|e| warn!("Failed to get model card of {}: {}", repo_id, e))
+        .unwrap_or_default();
+
+    HfSource {
+        repo_id: repo_id.to_string(),
+        revision,
+        file: String::new(),
+        license: license(info),
+        model_card,
+        card_data: card_data(info).to_string(),
+    }
+}
+
+/// Model card metadata, with pipeline and library of repo when the card does not set them
+fn card_data(info: &Value) -> Value {
+    let mut card = match &info["cardData"] {
+        Value::Object(card) => card.clone(),
+        _ => Default::default(),
+    };
+    for key in ["pipeline_tag", "library_name"] {
+        if let (None, Some(value)) = (card.get(key), info[key].as_str()) {
+            card.insert(key.to_string(), value.into());
+        }
+    }
+    Value::Object(card)
+}
+
+/// Identify items matched with a repo file by sync, resolve or download
+pub struct HuggingFaceProvider {
+    config: HuggingFaceConfig,
+    pool: SqlitePool,
+}
+
+impl HuggingFaceProvider {
+    pub fn new(config: &HuggingFaceConfig, pool: SqlitePool) -> Self {
+        Self {
+            config: config.clone(),
+            pool,
+        }
+    }
+
+    async fn record(&self, file: &ModelFile<'_>) -> anyhow::Result<Option<ModelRecord>> {
+        let Some(item) = file.item else {
+            return Ok(None);
+        };
+        let source = hf_source::get(&self.pool, item).await?;
+        Ok(source.map(|source| model_record(&self.config, &source)))
+    }
+}
+
+impl MetadataProvider for HuggingFaceProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Huggingface
+    }
+
+    fn identify<'a>(&'a self, file: &'a ModelFile<'a>) -> BoxFuture<'a, anyhow::Result<Option<ModelRecord>>> {
+        Box::pin(self.record(file))
+    }
+}
+
+/// Normalize source of an item: name of repo, pipeline, base model, tags, instance prompt
+/// and widget images of model card
+pub fn model_record(config: &HuggingFaceConfig, source: &HfSource) -> ModelRecord {
+    let card: Value = serde_json::from_str(&source.card_data).unwrap_or_default();
+    let model_type = card["pipeline_tag"]
+        .as_str()
+        .or(card["library_name"].as_str())
+        .unwrap_or_default()
+        .to_string();
+    let images = card["widget"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|w| w["output"]["url"].as_str())
+        .map(|url| {
+            if url.starts_with("http://") || url.starts_with("https://") {
+                url.to_string()
+            } else {
+                let base_url = config.base_url.trim_end_matches('/');
+                format!("{}/{}/resolve/{}/{}", base_url, source.repo_id, source.revision, url)
+            }
+        })
+        .collect();
+
+    ModelRecord {
+        name: source.repo_id.rsplit('/').next().unwrap_or_default().to_string(),
+        version: source.revision.clone(),
+        model_type,
+        base_model: strings(&card["base_model"]).into_iter().next().unwrap_or_default(),
+        tags: strings(&card["tags"]),
+        trigger_words: strings(&card["instance_prompt"])
+            .iter()
+            .flat_map(|p| p.split(','))
+            .map(|w| w.trim().to_string())
+            .filter(|w| !w.is_empty())
+            .collect(),
+        images,
+        license: source.license.clone(),
+    }
+}
+
+/// Model card metadata which can be a string or a list of strings
+fn strings(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(s) => vec![s.clone()],
+        Value::Array(values) => values.iter().filter_map(|v| v.as_str()).map(String::from).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Record source of item and index it again, so it is identified from its new source
+async fn set_source(
+    config: &Config,
+    pool: &SqlitePool,
+    providers: &Providers,
+    job: Option<&Job>,
+    item: i64,
+    source: &HfSource,
+) -> anyhow::Result<()> {
+    hf_source::insert_or_update(pool, item, source).await?;
+    let indexed = item::get_by_id(pool, item).await?;
+    let path = abs_path(config, &indexed.base_label, &indexed.path);
+    let fingerprint = get_fingerprint(&path).await?;
+    index_file(
+        config,
+        pool,
+        providers,
+        &indexed.base_label,
+        &indexed.path,
+        &fingerprint,
+        job,
+    )
+    .await;
+    Ok(())
+}
+
+fn repo_files(info: &Value) -> Vec<RepoFile> {
+    info["siblings"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|f| {
+            Some(RepoFile {
+                path: f["rfilename"].as_str()?.to_string(),
+                size: f["lfs"]["size"].as_u64().or(f["size"].as_u64()).unwrap_or_default(),
+                sha256: f["lfs"]["sha256"].as_str().map(|s| s.to_lowercase()),
+            })
+        })
+        .collect()
+}
+
+/// License from model card metadata, otherwise from `license:` tag of repo
+fn license(info: &Value) -> String {
+    if let Some(license) = info["cardData"]["license"].as_str() {
+        return license.to_string();
+    }
+    info["tags"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|t| t.as_str())
+        .find_map(|t| t.strip_prefix("license:"))
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn is_relative(path: &Path) -> bool {
+    path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job::{JobKind, JobManager};
+    use crate::testing::{self, memory_pool, serve, TempDir, LABEL};
+    use actix_web::{web, HttpRequest, HttpResponse};
+    use serde_json::json;
+    use sha2::{Digest, Sha256};
+
+    const REPO: &str = "org/repo";
+    const CONTENT: &[u8] = b"model content";
+
+    fn sha256(content: &[u8]) -> String {
+        hex::encode(Sha256::digest(content))
+    }
+
+    /// Hub stand-in with one repo. `files` are (path, LFS oid), each served with `CONTENT`, from the requested offset.
+    fn hub(files: Vec<(String, String)>) -> String {
+        serve(move |cfg| {
+            let siblings = files
+                .iter()
+                .map(|(path, oid)| json!({"rfilename": path, "lfs": {"sha256": oid, "size": CONTENT.len()}}))
+                .collect::<Vec<_>>();
+            let info = json!({
+                "sha": "abc123",
+                "pipeline_tag": "text-to-image",
+                "siblings": siblings,
+                "cardData": {"license": "mit", "base_model": "base/model", "instance_prompt": "foo, bar"},
+            });
+            cfg.route(
+                "/api/models/{org}/{name}/revision/{revision}",
+                web::get().to(move || {
+                    let info = info.clone();
+                    async move { HttpResponse::Ok().json(info) }
+                }),
+            )
+            .route(
+                "/{org}/{name}/resolve/abc123/{file:.*}",
+                web::get().to(|request: HttpRequest| async move {
+                    let offset = request.headers().get("range").and_then(|range| {
+                        range
+                            .to_str()
+                            .ok()?
+                            .strip_prefix("bytes=")?
+                            .strip_suffix('-')?
+                            .parse()
+                            .ok()
+                    });
+                    match offset {
+                        Some(offset) => HttpResponse::PartialContent().body(&CONTENT[offset..]),
+                        None => HttpResponse::Ok().body(CONTENT),
+                    }
+                }),
+            );
+        })
+    }
+
+    async fn item_id(pool: &SqlitePool, path: &str) -> i64 {
+        item::get_fingerprint(pool, path, LABEL).await.unwrap().unwrap().0
+    }
+
+    fn model_sha256(dir: &TempDir, name: &str) -> String {
+        sha256(&std::fs::read(dir.path().join(name)).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn resolve_by_lfs_oid() {
+        let dir = TempDir::new();
+        let mut config = testing::collection(&dir, &["a"]);
+        let pool = memory_pool().await;
+        testing::scan(&config, &pool).await;
+        let oid = model_sha256(&dir, "a.safetensors");
+        config.huggingface.base_url = hub(vec![
+            ("other.safetensors".to_string(), sha256(b"other")),
+            ("lora/a.safetensors".to_string(), oid),
+        ]);
+        let id = item_id(&pool, "a.safetensors").await;
+        let client = HuggingFaceClient::new(&config.huggingface).unwrap();
+        let providers = Providers::new(&config, &pool);
+
+        let source = resolve(&config, &pool, &client, &providers, id, REPO, DEFAULT_REVISION)
+            .await
+            .unwrap();
+
+        assert_eq!(source.file, "lora/a.safetensors");
+        assert_eq!(source.revision, "abc123");
+        assert_eq!(source.license, "mit");
+        let stored = hf_source::get(&pool, id).await.unwrap().unwrap();
+        assert_eq!(stored.file, "lora/a.safetensors");
+        let record = model_record(&config.huggingface, &stored);
+        assert_eq!(record.model_type, "text-to-image");
+        assert_eq!(record.base_model, "base/model");
+        assert_eq!(record.trigger_words, ["foo", "bar"]);
+    }
+
+    #[actix_web::test]
+    async fn sync_matches_lfs_oid() {
+        let dir = TempDir::new();
+        let mut config = testing::collection(&dir, &["a", "b"]);
+        let pool = memory_pool().await;
+        testing::scan(&config, &pool).await;
+        let oid = model_sha256(&dir, "a.safetensors");
+        config.huggingface.base_url = hub(vec![("a.safetensors".to_string(), oid)]);
+        config.huggingface.repos = vec![REPO.to_string()];
+
+        let client = HuggingFaceClient::new(&config.huggingface).unwrap();
+        let providers = Providers::new(&config, &pool);
+        let job = JobManager::default().start(JobKind::HuggingfaceSync).unwrap();
+        sync(&config, &pool, &client, &providers, &job).await.unwrap();
+
+        let a = hf_source::get(&pool, item_id(&pool, "a.safetensors").await)
+            .await
+            .unwrap();
+        assert_eq!(a.unwrap().repo_id, REPO);
+        let b = hf_source::get(&pool, item_id(&pool, "b.safetensors").await)
+            .await
+            .unwrap();
+        assert!(b.is_none());
+    }
+
+    #[actix_web::test]
+    async fn download_verifies_sha256() {
+        let dir = TempDir::new();
+        let mut config = testing::collection(&dir, &[]);
+        config.download.min_free_space_mb = 0;
+        config.huggingface.base_url = hub(vec![
+            ("unet/good.safetensors".to_string(), sha256(CONTENT)),
+            ("bad.safetensors".to_string(), sha256(b"other")),
+        ]);
+        let pool = memory_pool().await;
+        let client = HuggingFaceClient::new(&config.huggingface).unwrap();
+        let providers = Providers::new(&config, &pool);
+        let transfers = Transfers::default();
+        let jobs = JobManager::default();
+        let request = |file: &str| DownloadRequest {
+            repo_id: REPO.to_string(),
+            revision: DEFAULT_REVISION.to_string(),
+            file: file.to_string(),
+            label: LABEL.to_string(),
+            subfolder: "hf".to_string(),
+        };
+
+        let job = jobs.start_concurrent(JobKind::Download);
+        let progress = DownloadProgress::default();
+        let good = request("unet/good.safetensors");
+        download(&config, &pool, &client, &providers, &job, &good, &progress, &transfers)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(dir.path().join("hf/unet/good.safetensors")).unwrap(),
+            CONTENT
+        );
+        assert_eq!(*progress.path.lock().unwrap(), "hf/unet/good.safetensors");
+        let id = item_id(&pool, "hf/unet/good.safetensors").await;
+        let source = hf_source::get(&pool, id).await.unwrap().unwrap();
+        assert_eq!(
+            (source.repo_id.as_str(), source.file.as_str()),
+            (REPO, "unet/good.safetensors")
+        );
+
+        let job = jobs.start_concurrent(JobKind::Download);
+        let bad = request("bad.safetensors");
+        let result = download(
+            &config,
+            &pool,
+            &client,
+            &providers,
+            &job,
+            &bad,
+            &DownloadProgress::default(),
+            &transfers,
+        )
+        .await;
+        assert!(result.unwrap_err().to_string().contains("SHA256 mismatch"));
+        let path = dir.path().join("hf/bad.safetensors");
+        assert!(!path.exists());
+        assert!(!part_path(&path).exists());
+    }
+
+    #[actix_web::test]
+    async fn corrupted_part_is_removed() {
+        let dir = TempDir::new();
+        let mut config = testing::collection(&dir, &[]);
+        config.download.min_free_space_mb = 0;
+        config.huggingface.base_url = hub(vec![("a.safetensors".to_string(), sha256(CONTENT))]);
+        let pool = memory_pool().await;
+        let client = HuggingFaceClient::new(&config.huggingface).unwrap();
+        let providers = Providers::new(&config, &pool);
+        let transfers = Transfers::default();
+        let jobs = JobManager::default();
+        let request = DownloadRequest {
+            repo_id: REPO.to_string(),
+            revision: DEFAULT_REVISION.to_string(),
+            file: "a.safetensors".to_string(),
+            label: LABEL.to_string(),
+            subfolder: String::new(),
+        };
+        let path = dir.path().join("a.safetensors");
+        // Left by an interrupted download, but not the start of the file
+        std::fs::write(part_path(&path), b"other").unwrap();
+
+        let job = jobs.start_concurrent(JobKind::Download);
+        let progress = DownloadProgress::default();
+        let result = download(
+            &config, &pool, &client, &providers, &job, &request, &progress, &transfers,
+        )
+        .await;
+        assert!(result.unwrap_err().to_string().contains("SHA256 mismatch"));
+        assert_eq!(progress.downloaded.load(Ordering::Relaxed), CONTENT.len() as u64);
+        assert!(!path.exists());
+        assert!(!part_path(&path).exists());
+
+        // Downloaded again from the start
+        let job = jobs.start_concurrent(JobKind::Download);
+        download(
+            &config, &pool, &client, &providers, &job, &request, &progress, &transfers,
+        )
+        .await
+        .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), CONTENT);
+    }
+}
@@ -0,0 +1,300 @@
+//! Copyright (c) 2025 Trung Do <dothanhtrung@pm.me>.
+//!
+//! Registry of background jobs (scan, Civitai sync, ...) with progress and cancellation.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of finished jobs to keep in history
+const MAX_FINISHED_JOBS: usize = 50;
+
+/// Number of error messages to keep per job
+const MAX_JOB_ERRORS: usize = 1000;
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    Scan,
+    CivitaiSync,
+    UpdateCheck,
+    Download,
+    HuggingfaceSync,
+}
+
+impl JobKind {
+    /// Jobs of these kinds write item, tag and hash rows, so only one of them runs at a time
+    fn writes_items(self) -> bool {
+        matches!(self, JobKind::Scan | JobKind::CivitaiSync | JobKind::HuggingfaceSync)
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Default)]
+pub struct JobCounters {
+    pub seen: AtomicU64,
+    pub hashed: AtomicU64,
+    pub fetched: AtomicU64,
+    pub failed: AtomicU64,
+}
+
+pub struct Job {
+    pub id: u64,
+    pub kind: JobKind,
+    pub counters: JobCounters,
+    status: Mutex<JobStatus>,
+    errors: Mutex<Vec<String>>,
+    cancelled: AtomicBool,
+    started_at: i64,
+    ended_at: Mutex<Option<i64>>,
+}
+
+/// Snapshot of a job for API
+#[derive(Serialize)]
+pub struct JobInfo {
+    pub id: u64,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    pub seen: u64,
+    pub hashed: u64,
+    pub fetched: u64,
+    pub failed: u64,
+    pub errors: Vec<String>,
+    pub started_at: i64,
+    pub ended_at: Option<i64>,
+}
+
+impl Job {
+    fn new(id: u64, kind: JobKind) -> Self {
+        Self {
+            id,
+            kind,
+            counters: JobCounters::default(),
+            status: Mutex::new(JobStatus::Running),
+            errors: Mutex::new(Vec::new()),
+            cancelled: AtomicBool::new(false),
+            started_at: unix_time(),
+            ended_at: Mutex::new(None),
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_running(&self) -> bool {
+        *self.status.lock().unwrap() == JobStatus::Running
+    }
+
+    pub fn inc_seen(&self) {
+        self.counters.seen.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_hashed(&self) {
+        self.counters.hashed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_fetched(&self) {
+        self.counters.fetched.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a failed file and record its error
+    pub fn fail(&self, msg: String) {
+        self.counters.failed.fetch_add(1, Ordering::Relaxed);
+        let mut errors = self.errors.lock().unwrap();
+        if errors.len() < MAX_JOB_ERRORS {
+            errors.push(msg);
+        }
+    }
+
+    /// Mark job as ended with the result of its task
+    pub fn finish(&self, result: &anyhow::Result<()>) {
+        let status = match result {
+            Ok(_) if self.is_cancelled() => JobStatus::Cancelled,
+            Ok(_) => JobStatus::Done,
+            Err(e) => {
+                self.errors.lock().unwrap().push(e.to_string());
+                JobStatus::Failed
+            }
+        };
+        *self.status.lock().unwrap() = status;
+        *self.ended_at.lock().unwrap() = Some(unix_time());
+    }
+
+    /// Guard which marks job as failed if its task ends without calling `finish`, e.g. on panic
+    pub fn guard(self: &Arc<Self>) -> JobGuard {
+        JobGuard(self.clone())
+    }
+
+    pub fn info(&self) -> JobInfo {
+        JobInfo {
+            id: self.id,
+            kind: self.kind,
+            status: *self.status.lock().unwrap(),
+            seen: self.counters.seen.load(Ordering::Relaxed),
+            hashed: self.counters.hashed.load(Ordering::Relaxed),
+            fetched: self.counters.fetched.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+            errors: self.errors.lock().unwrap().clone(),
+            started_at: self.started_at,
+            ended_at: *self.ended_at.lock().unwrap(),
+        }
+    }
+}
+
+pub struct JobGuard(Arc<Job>);
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        if self.0.is_running() {
+            let msg = if std::thread::panicking() {
+                "Job panicked"
+            } else {
+                "Job stopped unexpectedly"
+            };
+            self.0.finish(&Err(anyhow::anyhow!(msg)));
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct JobManager {
+    next_id: AtomicU64,
+    jobs: Mutex<BTreeMap<u64, Arc<Job>>>,
+}
+
+impl JobManager {
+    /// Register a new running job. Fail if a job of same kind is still running,
+    /// or if both the new job and a running one write items, e.g. a scan and a Civitai sync.
+    pub fn start(&self, kind: JobKind) -> anyhow::Result<Arc<Job>> {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(job) = jobs
+            .values()
+            .find(|j| j.is_running() && (j.kind == kind || (j.kind.writes_items() && kind.writes_items())))
+        {
+            return Err(anyhow::anyhow!("Job {} ({:?}) is still running", job.id, job.kind));
+        }
+
+        Ok(self.register(&mut jobs, kind))
+    }
+
+    /// Register a new running job of a kind which may run alongside others, e.g. downloads
+    pub fn start_concurrent(&self, kind: JobKind) -> Arc<Job> {
+        let mut jobs = self.jobs.lock().unwrap();
+        self.register(&mut jobs, kind)
+    }
+
+    fn register(&self, jobs: &mut BTreeMap<u64, Arc<Job>>, kind: JobKind) -> Arc<Job> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let job = Arc::new(Job::new(id, kind));
+        jobs.insert(id, job.clone());
+
+        let finished = jobs
+            .values()
+            .filter(|j| !j.is_running())
+            .map(|j| j.id)
+            .collect::<Vec<_>>();
+        if finished.len() > MAX_FINISHED_JOBS {
+            for id in finished.iter().take(finished.len() - MAX_FINISHED_JOBS) {
+                jobs.remove(id);
+            }
+        }
+
+        job
+    }
+
+    /// Whether a job writing items is running
+    pub fn is_writing_items(&self) -> bool {
+        self.jobs
+            .lock()
+            .unwrap()
+            .values()
+            .any(|j| j.kind.writes_items() && j.is_running())
+    }
+
+    pub fn get(&self, id: u64) -> Option<Arc<Job>> {
+        self.jobs.lock().unwrap().get(&id).cloned()
+    }
+
+    /// All jobs, newest first
+    pub fn list(&self) -> Vec<Arc<Job>> {
+        self.jobs.lock().unwrap().values().rev().cloned().collect()
+    }
+}
+
+pub fn unix_time() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn panicking_job_is_failed() {
+        let jobs = JobManager::default();
+        let job = jobs.start(JobKind::Scan).unwrap();
+
+        let task_job = job.clone();
+        let handle = tokio::spawn(async move {
+            let _guard = task_job.guard();
+            panic!("boom");
+        });
+        assert!(handle.await.is_err());
+
+        let info = job.info();
+        assert_eq!(info.status, JobStatus::Failed);
+        assert!(info.ended_at.is_some());
+        assert_eq!(info.errors, vec!["Job panicked".to_string()]);
+        // A new job of same kind can be started again
+        assert!(jobs.start(JobKind::Scan).is_ok());
+    }
+
+    #[test]
+    fn guard_keeps_finished_status() {
+        let jobs = JobManager::default();
+        let job = jobs.start(JobKind::Scan).unwrap();
+        {
+            let _guard = job.guard();
+            job.finish(&Ok(()));
+        }
+        assert_eq!(job.info().status, JobStatus::Done);
+    }
+
+    #[test]
+    fn jobs_writing_items_do_not_overlap() {
+        let jobs = JobManager::default();
+        let scan = jobs.start(JobKind::Scan).unwrap();
+        assert!(jobs.start(JobKind::Scan).is_err());
+        assert!(jobs.start(JobKind::CivitaiSync).is_err());
+        assert!(jobs.start(JobKind::HuggingfaceSync).is_err());
+
+        // Update check only writes model_update rows
+        let check = jobs.start(JobKind::UpdateCheck).unwrap();
+        assert!(jobs.start(JobKind::UpdateCheck).is_err());
+        check.finish(&Ok(()));
+
+        scan.finish(&Ok(()));
+        let sync = jobs.start(JobKind::CivitaiSync).unwrap();
+        assert!(jobs.start(JobKind::Scan).is_err());
+        sync.finish(&Ok(()));
+        assert!(jobs.start(JobKind::Scan).is_ok());
+    }
+}
@@ -0,0 +1,55 @@
+//! Copyright (c) 2025 Trung Do <dothanhtrung@pm.me>.
+//!
+//! Training info which kohya-ss sd-scripts writes into safetensors metadata (`ss_*` keys).
+
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+/// Number of most frequent training tags kept as suggested trigger words
+const MAX_TRIGGER_WORDS: usize = 10;
+
+#[derive(Default)]
+pub struct KohyaInfo {
+    pub output_name: Option<String>,
+    pub network_dim: Option<i64>,
+    pub network_alpha: Option<f64>,
+    /// Most frequent training tags with their image count
+    pub trigger_words: Vec<(String, i64)>,
+}
+
+/// Return None if file was not trained by kohya-ss
+pub fn parse(metadata: &BTreeMap<String, String>) -> Option<KohyaInfo> {
+    if !metadata.keys().any(|k| k.starts_with("ss_")) {
+        return None;
+    }
+
+    let mut info = KohyaInfo {
+        output_name: metadata.get("ss_output_name").filter(|v| !v.is_empty()).cloned(),
+        network_dim: metadata.get("ss_network_dim").and_then(|v| v.trim().parse().ok()),
+        network_alpha: metadata.get("ss_network_alpha").and_then(|v| v.trim().parse().ok()),
+        trigger_words: Vec::new(),
+    };
+
+    // {"<repeat>_<dataset dir>": {"<tag>": <count>, ...}, ...}
+    if let Some(Ok(Value::Object(datasets))) = metadata.get("ss_tag_frequency").map(|v| serde_json::from_str(v)) {
+        let mut frequency: HashMap<String, i64> = HashMap::new();
+        for tags in datasets.values() {
+            let Some(tags) = tags.as_object() else {
+                continue;
+            };
+            for (tag, count) in tags {
+                let tag = tag.trim();
+                if !tag.is_empty() {
+                    *frequency.entry(tag.to_string()).or_insert(0) += count.as_i64().unwrap_or_default();
+                }
+            }
+        }
+
+        let mut words = frequency.into_iter().collect::<Vec<_>>();
+        words.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        words.truncate(MAX_TRIGGER_WORDS);
+        info.trigger_words = words;
+    }
+
+    Some(info)
+}
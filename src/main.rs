@@ -9,9 +9,6 @@
 //!   * Auto tag from json info
 //!   * Edit tag
 //!   * Tag depend
-//! * Browsing and download model from Civitai
-//! * Download new version of model from Civitai
-//! * Duplicate check
 
 #[cfg(not(target_env = "msvc"))]
 use tikv_jemallocator::Jemalloc;
@@ -22,26 +19,45 @@ static GLOBAL: Jemalloc = Jemalloc;
 
 mod api;
 mod civitai;
+mod classify;
 mod config;
 mod db;
+mod duplicate;
+mod gguf;
+mod huggingface;
+mod job;
+mod kohya;
+mod pickle;
+mod prompt;
+mod provider;
+mod safetensors;
+mod scanner;
+#[cfg(test)]
+mod testing;
+mod trash;
 mod ui;
+mod watcher;
 
-use crate::api::{get, reload_from_disk};
+use crate::civitai::client::CivitaiClient;
+use crate::civitai::queue::DownloadQueue;
+use crate::civitai::update::check_updates;
 use crate::civitai::update_model_info;
 use crate::config::Config;
 use crate::db::DBPool;
+use crate::huggingface::HuggingFaceClient;
+use crate::job::{JobKind, JobManager};
+use crate::provider::Providers;
+use crate::scanner::reload_from_disk;
 use actix_cors::Cors;
 use actix_files::Files;
 use actix_web::web::Data;
-use actix_web::{middleware, web, App, HttpServer, Scope};
+use actix_web::{middleware, web, App, HttpServer};
 use clap::Parser;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
-use tera::Tera;
-use tokio::sync::Mutex;
 use tokio::time::sleep;
-use tracing::{error, info, warn};
+use tracing::{error, warn};
 use tracing_subscriber::EnvFilter;
 
 const BASE_PATH_PREFIX: &str = "base_";
@@ -57,9 +73,13 @@ struct Cli {
     #[clap(short, long)]
     export_config: Option<PathBuf>,
 
-    /// Update model info
+    /// Scan model paths, then update model info
     #[clap(short, long, default_value = "false")]
     update_model_info: bool,
+
+    /// Check installed models for newer versions on Civitai
+    #[clap(long, default_value = "false")]
+    check_updates: bool,
 }
 
 #[tokio::main]
@@ -90,11 +110,6 @@ async fn main() -> anyhow::Result<()> {
         Config::default()
     });
 
-    if args.update_model_info {
-        update_model_info(config.clone()).await?;
-        return Ok(());
-    }
-
     let db_pool;
     loop {
         match DBPool::init(&config.db).await {
@@ -112,16 +127,71 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
+    if args.update_model_info {
+        // Sync uses hashes stored by the scanner, so a fresh or stale DB would sync nothing
+        let jobs = JobManager::default();
+        let job = jobs.start(JobKind::Scan)?;
+        let providers = Providers::new(&config, &db_pool.sqlite_pool);
+        let result = reload_from_disk(&config, &db_pool.sqlite_pool, &providers, &job, false).await;
+        job.finish(&result);
+        result?;
+
+        let job = jobs.start(JobKind::CivitaiSync)?;
+        let client = CivitaiClient::new(&config.civitai)?;
+        update_model_info(config.clone(), &db_pool.sqlite_pool, &client, &providers, &job, false).await?;
+        return Ok(());
+    }
+
+    if args.check_updates {
+        let job = JobManager::default().start(JobKind::UpdateCheck)?;
+        let client = CivitaiClient::new(&config.civitai)?;
+        check_updates(&config, &db_pool.sqlite_pool, &client, &job).await?;
+        return Ok(());
+    }
+
     let listen_addr = format!("{}:{}", &config.listen_addr, &config.listen_port);
     let model_paths = config.model_paths.clone();
     let ref_db_pool = Arc::new(db_pool);
     let ref_config = Arc::new(config);
+    let ref_jobs = Arc::new(JobManager::default());
+    // One client for all Civitai requests so they share the rate limit
+    let ref_civitai = Arc::new(CivitaiClient::new(&ref_config.civitai)?);
+    let ref_huggingface = Arc::new(HuggingFaceClient::new(&ref_config.huggingface)?);
+    let ref_providers = Arc::new(Providers::new(&ref_config, &ref_db_pool.sqlite_pool));
+
+    let ref_downloads = Arc::new(DownloadQueue::new(
+        ref_config.clone(),
+        ref_db_pool.sqlite_pool.clone(),
+        ref_jobs.clone(),
+        (*ref_civitai).clone(),
+        (*ref_huggingface).clone(),
+        ref_providers.clone(),
+    ));
+    ref_downloads.start();
+
+    let _watcher = if ref_config.watcher.enabled {
+        watcher::start(
+            ref_config.clone(),
+            ref_db_pool.sqlite_pool.clone(),
+            ref_providers.clone(),
+            ref_jobs.clone(),
+        )
+        .inspect_err(|e| error!("Failed to start file watcher: {}", e))
+        .ok()
+    } else {
+        None
+    };
 
     HttpServer::new(move || {
         let mut app = App::new()
             .wrap(Cors::default().allow_any_origin())
             .app_data(Data::from(ref_db_pool.clone()))
             .app_data(Data::from(ref_config.clone()))
+            .app_data(Data::from(ref_jobs.clone()))
+            .app_data(Data::from(ref_downloads.clone()))
+            .app_data(Data::from(ref_civitai.clone()))
+            .app_data(Data::from(ref_huggingface.clone()))
+            .app_data(Data::from(ref_providers.clone()))
             .wrap(middleware::NormalizePath::trim());
         for (label, base_path) in model_paths.iter() {
             app = app.service(
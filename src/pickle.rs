@@ -0,0 +1,651 @@
+//! Copyright (c) 2025 Trung Do <dothanhtrung@pm.me>.
+//!
+//! List globals imported by pickle based model files (.ckpt, .pt, .pth) without running them.
+//! Loading a pickle can call any imported global, so files importing anything outside the allowlist are unsafe.
+//! Files which cannot be fully parsed are unsafe as well, since the unread part may import anything.
+
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+const ZIP_MAGIC: &[u8; 4] = b"PK\x03\x04";
+
+/// Legacy torch format is a sequence of pickles: magic number, protocol, sys info, model and storage keys
+const MAX_LEGACY_PICKLES: usize = 5;
+
+/// Refuse pickles and bytes longer than this to avoid reading on forever in corrupted files
+const MAX_LEN: u64 = 1024 * 1024 * 1024;
+
+/// Strings and lines are read into memory up to this length. Module and class names are much shorter,
+/// so a longer string is data and is skipped.
+const MAX_STRING_LEN: u64 = 64 * 1024;
+
+/// (module, name) pairs needed to load plain state dicts. `*` matches any name.
+/// Based on the restricted unpickler of AUTOMATIC1111 webui, which most SD checkpoints are made to load with,
+/// and on the `weights_only` unpickler of torch.
+const SAFE_GLOBALS: &[(&str, &str)] = &[
+    ("collections", "OrderedDict"),
+    ("collections", "defaultdict"),
+    // Protocol 2 pickles written by Python 2 name it `__builtin__.set`, Python 3 names it `builtins.set`.
+    // It only builds a set from the given items.
+    ("builtins", "set"),
+    ("__builtin__", "set"),
+    // Only copies the given bytes. Allowed by torch `weights_only` loading, which writes it for byte buffers.
+    ("builtins", "bytearray"),
+    // Protocol 2 pickles encode bytes (e.g. numpy array data) as `_codecs.encode(str, "latin1")`.
+    // It only converts a string to bytes and cannot reach other code.
+    ("_codecs", "encode"),
+    ("torch", "Size"),
+    ("torch", "device"),
+    ("torch", "dtype"),
+    ("torch._utils", "_rebuild_tensor"),
+    ("torch._utils", "_rebuild_tensor_v2"),
+    ("torch._utils", "_rebuild_tensor_v3"),
+    ("torch._utils", "_rebuild_parameter"),
+    ("torch._utils", "_rebuild_parameter_with_state"),
+    ("torch._utils", "_rebuild_device_tensor_from_numpy"),
+    ("torch._utils", "_rebuild_meta_tensor_no_storage"),
+    ("torch._utils", "_rebuild_qtensor"),
+    ("torch._utils", "_rebuild_sparse_tensor"),
+    ("torch._tensor", "_rebuild_from_type_v2"),
+    ("torch.nn.parameter", "Parameter"),
+    ("torch.nn.modules.container", "ParameterDict"),
+    ("numpy", "dtype"),
+    ("numpy", "ndarray"),
+    ("numpy.core.multiarray", "scalar"),
+    ("numpy.core.multiarray", "_reconstruct"),
+    ("numpy._core.multiarray", "scalar"),
+    ("numpy._core.multiarray", "_reconstruct"),
+    ("numpy.dtypes", "*"),
+];
+
+/// Dtype names of torch module, e.g. torch.float16
+const TORCH_DTYPES: &[&str] = &[
+    "float16",
+    "float32",
+    "float64",
+    "bfloat16",
+    "float8_e4m3fn",
+    "float8_e5m2",
+    "complex64",
+    "complex128",
+    "uint8",
+    "int8",
+    "int16",
+    "int32",
+    "int64",
+    "bool",
+];
+
+#[derive(Serialize, Default, Debug)]
+pub struct PickleScan {
+    /// All imported globals as `module.name`, up to the point where parsing failed if it did
+    pub globals: BTreeSet<String>,
+    /// Globals outside the allowlist
+    pub unsafe_globals: BTreeSet<String>,
+    /// Why the file could not be fully parsed
+    pub error: Option<String>,
+}
+
+impl PickleScan {
+    /// Safe only if the whole file was parsed and no global is outside the allowlist
+    pub fn is_safe(&self) -> bool {
+        self.unsafe_globals.is_empty() && self.error.is_none()
+    }
+
+    /// Keep the first error, later ones are usually caused by it
+    fn fail(&mut self, error: anyhow::Error) {
+        if self.error.is_none() {
+            self.error = Some(error.to_string());
+        }
+    }
+
+    fn add(&mut self, module: &str, name: &str) {
+        let global = format!("{}.{}", module, name);
+        if !is_safe_global(module, name) {
+            self.unsafe_globals.insert(global.clone());
+        }
+        self.globals.insert(global);
+    }
+}
+
+/// Scan all pickles of a torch zip archive or legacy torch file
+pub fn scan(path: &Path) -> PickleScan {
+    match File::open(path) {
+        Ok(file) => scan_reader(BufReader::new(file)),
+        Err(e) => PickleScan {
+            error: Some(e.to_string()),
+            ..Default::default()
+        },
+    }
+}
+
+/// A zip archive may have data prepended before its first entry, which loaders that only look for the central
+/// directory accept. So a file with a zip archive anywhere has its archive scanned, and unless the file starts
+/// with the zip magic its start is scanned as legacy pickles too.
+fn scan_reader<R: BufRead + Seek>(mut reader: R) -> PickleScan {
+    let mut result = PickleScan::default();
+
+    let mut magic = [0u8; 4];
+    let starts_with_zip = reader.read_exact(&mut magic).is_ok() && &magic == ZIP_MAGIC;
+
+    match zip::ZipArchive::new(&mut reader) {
+        Ok(mut archive) => scan_zip(&mut archive, &mut result),
+        // A file starting like a zip must be a valid one, torch would fail to load it otherwise
+        Err(e) if starts_with_zip => result.fail(e.into()),
+        // Not a zip archive
+        Err(_) => {}
+    }
+
+    if !starts_with_zip {
+        match reader.seek(SeekFrom::Start(0)) {
+            Ok(_) => scan_legacy(&mut reader, &mut result),
+            Err(e) => result.fail(e.into()),
+        }
+    }
+
+    result
+}
+
+/// Scan pickles in a torch zip archive
+fn scan_zip<R: Read + Seek>(archive: &mut zip::ZipArchive<R>, result: &mut PickleScan) {
+    for i in 0..archive.len() {
+        let entry = match archive.by_index(i) {
+            Ok(entry) => entry,
+            Err(e) => {
+                result.fail(e.into());
+                continue;
+            }
+        };
+        let name = match entry.name() {
+            Ok(name) => name.to_string(),
+            Err(e) => {
+                result.fail(e.into());
+                continue;
+            }
+        };
+        if !name.ends_with(".pkl") {
+            continue;
+        }
+        if entry.size() > MAX_LEN {
+            result.fail(anyhow::anyhow!("Pickle too big: {} ({} bytes)", name, entry.size()));
+            continue;
+        }
+        if let Err(e) = scan_pickle(&mut BufReader::new(entry), result) {
+            result.fail(anyhow::anyhow!("Failed to parse {}: {}", name, e));
+        }
+    }
+}
+
+/// Legacy torch format is a sequence of pickles followed by raw storage data. A plain pickle file has only one.
+/// Anything other than end of file after a pickle, before the storage data, must be another pickle.
+fn scan_legacy(reader: &mut impl BufRead, result: &mut PickleScan) {
+    for _ in 0..MAX_LEGACY_PICKLES {
+        if let Err(e) = scan_pickle(reader, result) {
+            result.fail(e);
+            return;
+        }
+        match reader.fill_buf() {
+            Ok([]) => return,
+            Ok(_) => {}
+            Err(e) => {
+                result.fail(e.into());
+                return;
+            }
+        }
+    }
+}
+
+fn is_safe_global(module: &str, name: &str) -> bool {
+    if module == "torch" && (name.ends_with("Storage") || TORCH_DTYPES.contains(&name)) {
+        return true;
+    }
+    SAFE_GLOBALS
+        .iter()
+        .any(|(m, n)| *m == module && (*n == "*" || *n == name))
+}
+
+/// Walk opcodes of one pickle until STOP, emulating the stack only as far as needed to know
+/// which strings STACK_GLOBAL takes as module and name.
+fn scan_pickle(reader: &mut impl BufRead, result: &mut PickleScan) -> anyhow::Result<()> {
+    // Strings are kept, other values are None
+    let mut stack: Vec<Option<String>> = Vec::new();
+    let mut marks: Vec<usize> = Vec::new();
+    let mut memo: HashMap<u64, Option<String>> = HashMap::new();
+
+    loop {
+        let opcode = read_bytes::<1>(reader)?[0];
+        match opcode {
+            // STOP
+            b'.' => return Ok(()),
+            // MARK
+            b'(' => marks.push(stack.len()),
+            // POP
+            b'0' => {
+                if stack.len() > marks.last().copied().unwrap_or(0) {
+                    stack.pop();
+                } else {
+                    marks.pop();
+                }
+            }
+            // POP_MARK, APPENDS, SETITEMS, ADDITEMS
+            b'1' | b'e' | b'u' | 0x90 => pop_mark(&mut stack, &mut marks)?,
+            // DUP
+            b'2' => {
+                let top = stack.last().cloned().ok_or_else(underflow)?;
+                stack.push(top);
+            }
+            // FLOAT, INT, LONG, PERSID
+            b'F' | b'I' | b'L' | b'P' => {
+                read_line(reader)?;
+                stack.push(None);
+            }
+            // BININT, BININT1, BININT2, BINFLOAT
+            b'J' => push_skip::<4>(reader, &mut stack)?,
+            b'K' => push_skip::<1>(reader, &mut stack)?,
+            b'M' => push_skip::<2>(reader, &mut stack)?,
+            b'G' => push_skip::<8>(reader, &mut stack)?,
+            // NONE, NEWTRUE, NEWFALSE, EMPTY_DICT, EMPTY_LIST, EMPTY_TUPLE, EMPTY_SET, NEXT_BUFFER
+            b'N' | 0x88 | 0x89 | b'}' | b']' | b')' | 0x8f | 0x97 => stack.push(None),
+            // STRING, UNICODE
+            b'S' | b'V' => {
+                let line = read_line(reader)?;
+                stack.push(Some(line.trim_matches(|c| c == '\'' || c == '"').to_string()));
+            }
+            // SHORT_BINSTRING, SHORT_BINUNICODE
+            b'U' | 0x8c => {
+                let len = read_bytes::<1>(reader)?[0] as u64;
+                stack.push(read_string(reader, len)?);
+            }
+            // BINSTRING, BINUNICODE
+            b'T' | b'X' => {
+                let len = u32::from_le_bytes(read_bytes(reader)?) as u64;
+                stack.push(read_string(reader, len)?);
+            }
+            // BINUNICODE8
+            0x8d => {
+                let len = u64::from_le_bytes(read_bytes(reader)?);
+                stack.push(read_string(reader, len)?);
+            }
+            // SHORT_BINBYTES, LONG1
+            b'C' | 0x8a => {
+                let len = read_bytes::<1>(reader)?[0] as u64;
+                skip(reader, len)?;
+                stack.push(None);
+            }
+            // BINBYTES, LONG4
+            b'B' | 0x8b => {
+                let len = u32::from_le_bytes(read_bytes(reader)?) as u64;
+                skip(reader, len)?;
+                stack.push(None);
+            }
+            // BINBYTES8, BYTEARRAY8
+            0x8e | 0x96 => {
+                let len = u64::from_le_bytes(read_bytes(reader)?);
+                skip(reader, len)?;
+                stack.push(None);
+            }
+            // GLOBAL
+            b'c' => {
+                let module = read_line(reader)?;
+                let name = read_line(reader)?;
+                result.add(&module, &name);
+                stack.push(None);
+            }
+            // INST
+            b'i' => {
+                let module = read_line(reader)?;
+                let name = read_line(reader)?;
+                result.add(&module, &name);
+                pop_mark(&mut stack, &mut marks)?;
+                stack.push(None);
+            }
+            // STACK_GLOBAL
+            0x93 => {
+                let name = pop(&mut stack)?;
+                let module = pop(&mut stack)?;
+                match (module, name) {
+                    (Some(module), Some(name)) => result.add(&module, &name),
+                    // Module or name built at load time cannot be checked
+                    _ => result.add("<dynamic>", "<dynamic>"),
+                }
+                stack.push(None);
+            }
+            // EXT1, EXT2, EXT4: globals from copyreg extension registry
+            0x82 => push_extension::<1>(reader, &mut stack, result)?,
+            0x83 => push_extension::<2>(reader, &mut stack, result)?,
+            0x84 => push_extension::<4>(reader, &mut stack, result)?,
+            // BINPERSID, TUPLE1
+            b'Q' | 0x85 => pop_push(&mut stack, 1)?,
+            // REDUCE, NEWOBJ, TUPLE2
+            b'R' | 0x81 | 0x86 => pop_push(&mut stack, 2)?,
+            // NEWOBJ_EX, TUPLE3
+            0x92 | 0x87 => pop_push(&mut stack, 3)?,
+            // BUILD, APPEND
+            b'b' | b'a' => {
+                pop(&mut stack)?;
+            }
+            // SETITEM
+            b's' => {
+                pop(&mut stack)?;
+                pop(&mut stack)?;
+            }
+            // OBJ, TUPLE, LIST, DICT, FROZENSET
+            b'o' | b't' | b'l' | b'd' | 0x91 => {
+                pop_mark(&mut stack, &mut marks)?;
+                stack.push(None);
+            }
+            // GET, BINGET, LONG_BINGET
+            b'g' => {
+                let idx = read_line(reader)?.parse()?;
+                stack.push(memo.get(&idx).cloned().flatten());
+            }
+            b'h' => {
+                let idx = read_bytes::<1>(reader)?[0] as u64;
+                stack.push(memo.get(&idx).cloned().flatten());
+            }
+            b'j' => {
+                let idx = u32::from_le_bytes(read_bytes(reader)?) as u64;
+                stack.push(memo.get(&idx).cloned().flatten());
+            }
+            // PUT, BINPUT, LONG_BINPUT, MEMOIZE
+            b'p' => {
+                let idx = read_line(reader)?.parse()?;
+                memo.insert(idx, stack.last().cloned().ok_or_else(underflow)?);
+            }
+            b'q' => {
+                let idx = read_bytes::<1>(reader)?[0] as u64;
+                memo.insert(idx, stack.last().cloned().ok_or_else(underflow)?);
+            }
+            b'r' => {
+                let idx = u32::from_le_bytes(read_bytes(reader)?) as u64;
+                memo.insert(idx, stack.last().cloned().ok_or_else(underflow)?);
+            }
+            0x94 => {
+                memo.insert(memo.len() as u64, stack.last().cloned().ok_or_else(underflow)?);
+            }
+            // PROTO
+            0x80 => skip(reader, 1)?,
+            // FRAME
+            0x95 => skip(reader, 8)?,
+            // READONLY_BUFFER
+            0x98 => {}
+            op => return Err(anyhow::anyhow!("Unknown pickle opcode 0x{:02x}", op)),
+        }
+    }
+}
+
+fn underflow() -> anyhow::Error {
+    anyhow::anyhow!("Pickle stack underflow")
+}
+
+fn pop(stack: &mut Vec<Option<String>>) -> anyhow::Result<Option<String>> {
+    stack.pop().ok_or_else(underflow)
+}
+
+fn pop_push(stack: &mut Vec<Option<String>>, count: usize) -> anyhow::Result<()> {
+    for _ in 0..count {
+        pop(stack)?;
+    }
+    stack.push(None);
+    Ok(())
+}
+
+fn pop_mark(stack: &mut Vec<Option<String>>, marks: &mut Vec<usize>) -> anyhow::Result<()> {
+    let mark = marks.pop().ok_or_else(|| anyhow::anyhow!("Pickle mark not found"))?;
+    stack.truncate(mark);
+    Ok(())
+}
+
+fn push_skip<const N: usize>(reader: &mut impl Read, stack: &mut Vec<Option<String>>) -> anyhow::Result<()> {
+    read_bytes::<N>(reader)?;
+    stack.push(None);
+    Ok(())
+}
+
+fn push_extension<const N: usize>(
+    reader: &mut impl Read,
+    stack: &mut Vec<Option<String>>,
+    result: &mut PickleScan,
+) -> anyhow::Result<()> {
+    let mut code = [0u8; 8];
+    code[..N].copy_from_slice(&read_bytes::<N>(reader)?);
+    result.add("<extension>", &u64::from_le_bytes(code).to_string());
+    stack.push(None);
+    Ok(())
+}
+
+fn read_bytes<const N: usize>(reader: &mut impl Read) -> std::io::Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_line(reader: &mut impl BufRead) -> anyhow::Result<String> {
+    let mut buf = Vec::new();
+    reader.by_ref().take(MAX_STRING_LEN + 1).read_until(b'\n', &mut buf)?;
+    if buf.pop() != Some(b'\n') {
+        return Err(anyhow::anyhow!("Unterminated or too long pickle line"));
+    }
+    Ok(String::from_utf8_lossy(&buf).trim_end_matches('\r').to_string())
+}
+
+/// Read a string which may be used as module or class name. A longer one is skipped and returned as None.
+/// Memory grows with the bytes actually read, so a bogus length in a truncated file does not allocate.
+fn read_string(reader: &mut impl Read, len: u64) -> anyhow::Result<Option<String>> {
+    if len > MAX_STRING_LEN {
+        skip(reader, len)?;
+        return Ok(None);
+    }
+    let mut buf = Vec::new();
+    reader.take(len).read_to_end(&mut buf)?;
+    if (buf.len() as u64) < len {
+        return Err(anyhow::anyhow!("Unexpected end of pickle"));
+    }
+    Ok(Some(String::from_utf8_lossy(&buf).to_string()))
+}
+
+fn skip(reader: &mut impl Read, len: u64) -> anyhow::Result<()> {
+    if len > MAX_LEN {
+        return Err(anyhow::anyhow!("Pickle value too long: {}", len));
+    }
+    let skipped = std::io::copy(&mut reader.by_ref().take(len), &mut std::io::sink())?;
+    if skipped < len {
+        return Err(anyhow::anyhow!("Unexpected end of pickle"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    fn scan_bytes(bytes: &[u8]) -> PickleScan {
+        scan_reader(Cursor::new(bytes.to_vec()))
+    }
+
+    fn globals(scan: &PickleScan) -> Vec<&str> {
+        scan.unsafe_globals.iter().map(|g| g.as_str()).collect()
+    }
+
+    /// `{"weight": torch._utils._rebuild_tensor_v2(...)}` as written by torch.save, protocol 2
+    fn state_dict() -> Vec<u8> {
+        [
+            &b"\x80\x02}q\x00("[..],
+            b"X\x06\x00\x00\x00weightq\x01",
+            b"ctorch._utils\n_rebuild_tensor_v2\nq\x02",
+            b"((X\x07\x00\x00\x00storageq\x03ctorch\nFloatStorage\nq\x04X\x01\x00\x00\x000q\x05X\x03\x00\x00\x00cpuq\x06K\x04tq\x07Q",
+            b"K\x00K\x04\x85q\x08K\x01\x85q\x09\x89",
+            b"ccollections\nOrderedDict\nq\x0a)Rq\x0btq\x0cRq\x0d",
+            b"u.",
+        ]
+        .concat()
+    }
+
+    /// `os.system("echo pwned")` with GLOBAL and REDUCE, protocol 0
+    const OS_SYSTEM: &[u8] = b"cos\nsystem\n(S'echo pwned'\ntR.";
+
+    fn zip_archive(pickle: &[u8]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        writer.start_file("archive/data.pkl", options).unwrap();
+        writer.write_all(pickle).unwrap();
+        writer.start_file("archive/data/0", options).unwrap();
+        writer.write_all(&[0u8; 16]).unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn safe_state_dict() {
+        let scan = scan_bytes(&state_dict());
+        assert!(scan.is_safe(), "{:?}", scan);
+        assert!(scan.globals.contains("torch._utils._rebuild_tensor_v2"));
+        assert!(scan.globals.contains("torch.FloatStorage"));
+        assert!(scan.globals.contains("collections.OrderedDict"));
+    }
+
+    #[test]
+    fn safe_state_dict_in_zip() {
+        let scan = scan_bytes(&zip_archive(&state_dict()));
+        assert!(scan.is_safe(), "{:?}", scan);
+        assert!(scan.globals.contains("torch._utils._rebuild_tensor_v2"));
+    }
+
+    #[test]
+    fn global_reduce() {
+        let scan = scan_bytes(OS_SYSTEM);
+        assert!(!scan.is_safe());
+        assert!(scan.error.is_none());
+        assert_eq!(globals(&scan), vec!["os.system"]);
+    }
+
+    #[test]
+    fn stack_global() {
+        // Protocol 4: SHORT_BINUNICODE module and name, MEMOIZE, STACK_GLOBAL, then call it with a tuple
+        let pickle = b"\x80\x04\x8c\x05posix\x94\x8c\x06system\x94\x93\x94\x8c\x02id\x94\x85\x94R\x94.";
+        let scan = scan_bytes(pickle);
+        assert!(!scan.is_safe());
+        assert!(scan.error.is_none());
+        assert_eq!(globals(&scan), vec!["posix.system"]);
+    }
+
+    #[test]
+    fn stack_global_from_memo() {
+        // Module and name pushed again with BINGET from memo
+        let pickle = b"\x80\x02X\x08\x00\x00\x00builtinsq\x000X\x04\x00\x00\x00evalq\x010h\x00h\x01\x93.";
+        let scan = scan_bytes(pickle);
+        assert_eq!(globals(&scan), vec!["builtins.eval"]);
+    }
+
+    #[test]
+    fn payload_followed_by_bad_opcode() {
+        let mut pickle = b"cos\nsystem\n(S'echo pwned'\ntR".to_vec();
+        pickle.push(0xff);
+        let scan = scan_bytes(&pickle);
+        assert!(!scan.is_safe());
+        assert!(scan.error.is_some());
+        // Globals read before the error are kept
+        assert_eq!(globals(&scan), vec!["os.system"]);
+    }
+
+    #[test]
+    fn unparsable_file_is_not_safe() {
+        let scan = scan_bytes(b"\x80\x02}\xff");
+        assert!(scan.unsafe_globals.is_empty());
+        assert!(scan.error.is_some());
+        assert!(!scan.is_safe());
+
+        // Truncated pickle
+        let scan = scan_bytes(&state_dict()[..40]);
+        assert!(!scan.is_safe());
+
+        // Data after a pickle which is not another pickle
+        let mut bytes = state_dict();
+        bytes.extend_from_slice(b"\xffgarbage");
+        assert!(!scan_bytes(&bytes).is_safe());
+    }
+
+    #[test]
+    fn error_in_later_legacy_pickle() {
+        // Legacy torch file: magic number, protocol and sys info pickles, then the model
+        let mut bytes = b"\x80\x02K\x01.\x80\x02K\x02.".to_vec();
+        bytes.extend_from_slice(OS_SYSTEM);
+        let scan = scan_bytes(&bytes);
+        assert_eq!(globals(&scan), vec!["os.system"]);
+
+        let mut bytes = b"\x80\x02K\x01.\x80\x02K\x02.".to_vec();
+        bytes.push(0xff);
+        assert!(!scan_bytes(&bytes).is_safe());
+    }
+
+    #[test]
+    fn unsafe_pickle_in_zip() {
+        let scan = scan_bytes(&zip_archive(OS_SYSTEM));
+        assert_eq!(globals(&scan), vec!["os.system"]);
+    }
+
+    #[test]
+    fn zip_with_prepended_data() {
+        // Prepended data is not a pickle and the archive holds a payload: both make the file unsafe
+        let mut bytes = b"not a pickle\n".to_vec();
+        bytes.extend_from_slice(&zip_archive(OS_SYSTEM));
+        let scan = scan_bytes(&bytes);
+        assert!(!scan.is_safe());
+        assert_eq!(globals(&scan), vec!["os.system"]);
+
+        // Payload pickle prepended to a safe archive
+        let mut bytes = OS_SYSTEM.to_vec();
+        bytes.extend_from_slice(&zip_archive(&state_dict()));
+        let scan = scan_bytes(&bytes);
+        assert!(scan.globals.contains("torch._utils._rebuild_tensor_v2"));
+        assert!(scan.unsafe_globals.contains("os.system"));
+    }
+
+    #[test]
+    fn broken_zip_is_not_safe() {
+        let mut bytes = zip_archive(&state_dict());
+        bytes.truncate(bytes.len() - 30);
+        assert!(!scan_bytes(&bytes).is_safe());
+    }
+
+    #[test]
+    fn globals_outside_allowlist() {
+        let bytes = [
+            &b"\x80\x02cpytorch_lightning.callbacks.model_checkpoint\nModelCheckpoint\nq\x00"[..],
+            b"cbuiltins\nfrozenset\nq\x01\x86.",
+        ]
+        .concat();
+        let scan = scan_bytes(&bytes);
+        assert_eq!(
+            globals(&scan),
+            vec![
+                "builtins.frozenset",
+                "pytorch_lightning.callbacks.model_checkpoint.ModelCheckpoint"
+            ]
+        );
+    }
+
+    #[test]
+    fn long_string_is_not_a_name() {
+        let module = "a".repeat(MAX_STRING_LEN as usize + 1);
+        let mut bytes = b"\x80\x04X".to_vec();
+        bytes.extend_from_slice(&(module.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(module.as_bytes());
+        bytes.extend_from_slice(b"\x8c\x06system\x93.");
+        let scan = scan_bytes(&bytes);
+        assert_eq!(globals(&scan), vec!["<dynamic>.<dynamic>"]);
+        assert!(scan.error.is_none());
+    }
+
+    #[test]
+    fn truncated_string() {
+        // Length of 4 GiB but only a few bytes left
+        let scan = scan_bytes(b"\x80\x04X\xff\xff\xff\xffabc");
+        assert!(!scan.is_safe());
+        let scan = scan_bytes(b"\x80\x04X\x10\x00\x00\x00abc");
+        assert_eq!(scan.error.as_deref(), Some("Unexpected end of pickle"));
+    }
+}
@@ -0,0 +1,148 @@
+//! Copyright (c) 2025 Trung Do <dothanhtrung@pm.me>.
+//!
+//! Ready to paste prompt snippets for LoRAs and embeddings, in A1111 and ComfyUI syntax.
+
+use crate::db::item;
+use crate::db::trigger_word::{self, SOURCE_KOHYA};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use sqlx::SqlitePool;
+use std::path::Path;
+
+/// Weight used when model author does not recommend one
+const DEFAULT_WEIGHT: f64 = 1.0;
+
+/// Id of CheckpointLoaderSimple node in ComfyUI default workflow, the first LoraLoader is linked to it
+const COMFYUI_CHECKPOINT_NODE: &str = "4";
+
+/// Ids of generated LoraLoader nodes start here to not collide with nodes of default workflow
+const COMFYUI_FIRST_NODE: usize = 100;
+
+/// Model type tags, the same for Civitai info and tensor classification
+const LORA_TAGS: &[&str] = &["lora", "locon", "lycoris", "dora"];
+const EMBEDDING_TAGS: &[&str] = &["textualinversion"];
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SnippetKind {
+    Lora,
+    Embedding,
+}
+
+#[derive(Serialize)]
+pub struct Snippet {
+    pub id: i64,
+    pub kind: SnippetKind,
+    /// File name without extension, as A1111 and ComfyUI embeddings refer to it
+    pub name: String,
+    /// Relative path in collection, as ComfyUI LoraLoader refers to it
+    pub file: String,
+    pub weight: f64,
+    pub trigger_words: Vec<String>,
+    pub a1111: String,
+}
+
+#[derive(Serialize, Default)]
+pub struct Prompt {
+    pub snippets: Vec<Snippet>,
+    /// All snippets in one A1111 prompt
+    pub a1111: String,
+    /// Embeddings and trigger words for ComfyUI text encoder, LoRAs are in `comfyui_nodes`
+    pub comfyui_prompt: String,
+    /// Chained LoraLoader nodes in ComfyUI API format
+    pub comfyui_nodes: Map<String, Value>,
+}
+
+/// Build snippet of a LoRA or embedding. Other model types have no prompt syntax.
+pub async fn get_snippet(pool: &SqlitePool, id: i64) -> anyhow::Result<Snippet> {
+    let item = item::get_by_id(pool, id)
+        .await
+        .map_err(|e| anyhow::anyhow!("Item {}: {}", id, e))?;
+    let tags = item::get_tags(pool, id).await?;
+    let kind = if tags.iter().any(|t| LORA_TAGS.contains(&t.as_str())) {
+        SnippetKind::Lora
+    } else if tags.iter().any(|t| EMBEDDING_TAGS.contains(&t.as_str())) {
+        SnippetKind::Embedding
+    } else {
+        return Err(anyhow::anyhow!("Item {} is neither a LoRA nor an embedding", id));
+    };
+
+    let name = Path::new(&item.path)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default()
+        .to_string();
+    let weight = item::get_recommended_weight(pool, id).await?.unwrap_or(DEFAULT_WEIGHT);
+    // Kohya words are only the most frequent training tags, words of the provider are preferred
+    let mut words = trigger_word::get(pool, id).await?;
+    if words.iter().any(|w| w.source != SOURCE_KOHYA) {
+        words.retain(|w| w.source != SOURCE_KOHYA);
+    }
+    let trigger_words = words.into_iter().map(|w| w.word).collect::<Vec<_>>();
+
+    let mut parts = vec![match kind {
+        SnippetKind::Lora => format!("<lora:{}:{}>", name, weight),
+        SnippetKind::Embedding => weighted(&name, weight),
+    }];
+    parts.extend(trigger_words.iter().cloned());
+
+    Ok(Snippet {
+        id,
+        kind,
+        name,
+        file: item.path,
+        weight,
+        trigger_words,
+        a1111: parts.join(", "),
+    })
+}
+
+/// Combine snippets into one prompt for each UI
+pub fn build(snippets: Vec<Snippet>) -> Prompt {
+    let mut comfyui_prompt = Vec::new();
+    let mut comfyui_nodes = Map::new();
+    let mut prev_node = COMFYUI_CHECKPOINT_NODE.to_string();
+
+    for snippet in &snippets {
+        match snippet.kind {
+            SnippetKind::Lora => {
+                let node = (COMFYUI_FIRST_NODE + comfyui_nodes.len()).to_string();
+                comfyui_nodes.insert(
+                    node.clone(),
+                    json!({
+                        "class_type": "LoraLoader",
+                        "inputs": {
+                            "lora_name": snippet.file,
+                            "strength_model": snippet.weight,
+                            "strength_clip": snippet.weight,
+                            "model": [prev_node, 0],
+                            "clip": [prev_node, 1],
+                        },
+                        "_meta": { "title": snippet.name },
+                    }),
+                );
+                prev_node = node;
+            }
+            SnippetKind::Embedding => {
+                comfyui_prompt.push(weighted(&format!("embedding:{}", snippet.name), snippet.weight));
+            }
+        }
+        comfyui_prompt.extend(snippet.trigger_words.iter().cloned());
+    }
+
+    Prompt {
+        a1111: snippets.iter().map(|s| s.a1111.as_str()).collect::<Vec<_>>().join(", "),
+        comfyui_prompt: comfyui_prompt.join(", "),
+        comfyui_nodes,
+        snippets,
+    }
+}
+
+/// `(text:weight)`, or only text for the default weight
+fn weighted(text: &str, weight: f64) -> String {
+    if weight == DEFAULT_WEIGHT {
+        text.to_string()
+    } else {
+        format!("({}:{})", text, weight)
+    }
+}
@@ -0,0 +1,337 @@
+//! Copyright (c) 2025 Trung Do <dothanhtrung@pm.me>.
+//!
+//! Identify model files: a provider turns the hashes of a file into a normalized model record,
+//! from what it cached during its own sync. Providers are tried in configured order.
+
+use crate::civitai::{CivitaiProvider, FileHashes};
+use crate::config::Config;
+use crate::db::model_record;
+use crate::huggingface::HuggingFaceProvider;
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio::fs;
+
+/// Source of model records. Civitai and Hugging Face providers live next to their sync.
+pub trait MetadataProvider: Send + Sync {
+    fn kind(&self) -> ProviderKind;
+
+    /// Record of file from what provider already knows, None if it does not know the file.
+    /// Remote providers learn about files in their own sync, not here.
+    fn identify<'a>(&'a self, file: &'a ModelFile<'a>) -> BoxFuture<'a, anyhow::Result<Option<ModelRecord>>>;
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderKind {
+    /// Records written by user in `local_overrides` file
+    Local,
+    Civitai,
+    Huggingface,
+}
+
+impl ProviderKind {
+    pub const ALL: [ProviderKind; 3] = [ProviderKind::Local, ProviderKind::Civitai, ProviderKind::Huggingface];
+
+    /// Also the source of trigger words from this provider
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProviderKind::Local => "local",
+            ProviderKind::Civitai => "civitai",
+            ProviderKind::Huggingface => "huggingface",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.as_str() == name)
+    }
+}
+
+/// Model as described by a provider. Missing fields are empty.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct ModelRecord {
+    pub name: String,
+    pub version: String,
+    /// e.g. `Checkpoint`, `LORA`, `VAE` from Civitai.
+    /// Pipeline or library of repo from Hugging Face, e.g. `text-to-image`.
+    #[serde(rename = "type")]
+    pub model_type: String,
+    /// e.g. `SDXL 1.0`, `Flux.1 D`
+    pub base_model: String,
+    pub tags: Vec<String>,
+    pub trigger_words: Vec<String>,
+    /// URLs of example images
+    pub images: Vec<String>,
+    /// Empty for Civitai, whose model version info has no license
+    pub license: String,
+}
+
+/// Record of an item with the provider which identified it
+#[derive(Serialize)]
+pub struct Identified {
+    pub provider: ProviderKind,
+    pub record: ModelRecord,
+}
+
+/// Model file to identify
+pub struct ModelFile<'a> {
+    /// None if file is not indexed yet
+    pub item: Option<i64>,
+    pub path: &'a Path,
+    pub hashes: &'a FileHashes,
+}
+
+/// Providers in configured order, shared by scans, syncs, downloads and the file watcher
+pub struct Providers {
+    providers: Vec<Arc<dyn MetadataProvider>>,
+    local: Option<Arc<LocalProvider>>,
+}
+
+impl Providers {
+    pub fn new(config: &Config, pool: &SqlitePool) -> Self {
+        let mut local = None;
+        let providers = config
+            .providers
+            .order
+            .iter()
+            .map(|kind| -> Arc<dyn MetadataProvider> {
+                match kind {
+                    ProviderKind::Local => local
+                        .get_or_insert_with(|| Arc::new(LocalProvider::new(&config.providers.local_overrides)))
+                        .clone(),
+                    ProviderKind::Civitai => Arc::new(CivitaiProvider::new(pool.clone())),
+                    ProviderKind::Huggingface => Arc::new(HuggingFaceProvider::new(&config.huggingface, pool.clone())),
+                }
+            })
+            .collect();
+
+        Self { providers, local }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn MetadataProvider> {
+        self.providers.iter().map(|p| p.as_ref())
+    }
+
+    /// Modification time of local overrides file in nanoseconds, 0 if it is not used or does not exist
+    pub async fn local_overrides_mtime(&self) -> i64 {
+        match &self.local {
+            Some(local) => local.mtime().await.map(nanos).unwrap_or_default(),
+            None => 0,
+        }
+    }
+}
+
+/// Whether sync of `kind` should look up item: no provider tried before `kind` identified it when it was indexed.
+/// Sync of a provider which is not in `providers.order` is not run at all.
+pub async fn needs_sync(config: &Config, pool: &SqlitePool, kind: ProviderKind, item: i64) -> anyhow::Result<bool> {
+    let Some(position) = config.providers.order.iter().position(|p| *p == kind) else {
+        return Ok(false);
+    };
+    let Some(provider) = model_record::get_provider(pool, item).await? else {
+        return Ok(true);
+    };
+
+    Ok(!config.providers.order[..position]
+        .iter()
+        .any(|p| p.as_str() == provider))
+}
+
+/// Remember record of item, or forget it if no provider knows the item
+pub async fn save(pool: &SqlitePool, item: i64, identified: Option<&Identified>) -> anyhow::Result<()> {
+    match identified {
+        Some(identified) => {
+            let record = serde_json::to_string(&identified.record)?;
+            model_record::insert_or_update(pool, item, identified.provider.as_str(), &record).await?;
+        }
+        None => {
+            model_record::delete(pool, item).await?;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn get(pool: &SqlitePool, item: i64) -> anyhow::Result<Option<Identified>> {
+    let Some(stored) = model_record::get(pool, item).await? else {
+        return Ok(None);
+    };
+    let provider =
+        ProviderKind::parse(&stored.provider).ok_or_else(|| anyhow::anyhow!("Unknown provider {}", stored.provider))?;
+
+    Ok(Some(Identified {
+        provider,
+        record: serde_json::from_str(&stored.record)?,
+    }))
+}
+
+/// Records of `local_overrides` file, by lowercase BLAKE3, SHA256 or AutoV2 hash.
+/// The file is parsed again only when its mtime changes.
+pub struct LocalProvider {
+    path: String,
+    cache: Mutex<Option<LocalOverrides>>,
+}
+
+struct LocalOverrides {
+    mtime: SystemTime,
+    records: Arc<HashMap<String, ModelRecord>>,
+}
+
+impl LocalProvider {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            cache: Mutex::new(None),
+        }
+    }
+
+    async fn mtime(&self) -> anyhow::Result<SystemTime> {
+        fs::metadata(&self.path)
+            .await
+            .and_then(|m| m.modified())
+            .map_err(|e| anyhow::anyhow!("{}: {}", self.path, e))
+    }
+
+    async fn record(&self, hashes: &FileHashes) -> anyhow::Result<Option<ModelRecord>> {
+        if self.path.is_empty() {
+            return Ok(None);
+        }
+        let records = self.records().await?;
+
+        let local = [&hashes.blake3, &hashes.sha256, &hashes.autov2];
+        Ok(local
+            .into_iter()
+            .filter(|hash| !hash.is_empty())
+            .find_map(|hash| records.get(&hash.to_lowercase()))
+            .cloned())
+    }
+
+    async fn records(&self) -> anyhow::Result<Arc<HashMap<String, ModelRecord>>> {
+        let mtime = self.mtime().await?;
+        if let Some(cached) = self.cache.lock().unwrap().as_ref() {
+            if cached.mtime == mtime {
+                return Ok(cached.records.clone());
+            }
+        }
+
+        let path = &self.path;
+        let content = fs::read_to_string(path)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}: {}", path, e))?;
+        let records: HashMap<String, ModelRecord> =
+            serde_json::from_str(&content).map_err(|e| anyhow::anyhow!("{}: {}", path, e))?;
+        let records = Arc::new(
+            records
+                .into_iter()
+                .map(|(hash, record)| (hash.to_lowercase(), record))
+                .collect::<HashMap<_, _>>(),
+        );
+        *self.cache.lock().unwrap() = Some(LocalOverrides {
+            mtime,
+            records: records.clone(),
+        });
+
+        Ok(records)
+    }
+}
+
+impl MetadataProvider for LocalProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Local
+    }
+
+    fn identify<'a>(&'a self, file: &'a ModelFile<'a>) -> BoxFuture<'a, anyhow::Result<Option<ModelRecord>>> {
+        Box::pin(self.record(file.hashes))
+    }
+}
+
+fn nanos(time: SystemTime) -> i64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::civitai::calculate_hashes;
+    use crate::db::{item, scan_error};
+    use crate::testing::{self, memory_pool, TempDir, LABEL};
+    use std::time::Duration;
+
+    fn write_overrides(path: &Path, content: &str, mtime: SystemTime) {
+        std::fs::write(path, content).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+    }
+
+    fn overrides(hash: &str, name: &str) -> String {
+        format!(r#"{{"{}": {{"name": "{}", "type": "LORA"}}}}"#, hash, name)
+    }
+
+    #[tokio::test]
+    async fn local_overrides_reloaded_on_change() {
+        let dir = TempDir::new();
+        let path = dir.path().join("overrides.json");
+        let hashes = FileHashes {
+            autov2: "abcdef".to_string(),
+            ..Default::default()
+        };
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        write_overrides(&path, &overrides("ABCDEF", "first"), mtime);
+        let local = LocalProvider::new(&path.to_string_lossy());
+
+        let record = local.record(&hashes).await.unwrap().unwrap();
+        assert_eq!((record.name.as_str(), record.model_type.as_str()), ("first", "LORA"));
+        assert!(local.record(&FileHashes::default()).await.unwrap().is_none());
+
+        write_overrides(&path, &overrides("ABCDEF", "second"), mtime + Duration::from_secs(1));
+        let record = local.record(&hashes).await.unwrap().unwrap();
+        assert_eq!(record.name, "second");
+    }
+
+    #[tokio::test]
+    async fn broken_local_overrides() {
+        let dir = TempDir::new();
+        let mut config = testing::collection(&dir, &["a"]);
+        let overrides_dir = TempDir::new();
+        let path = overrides_dir.path().join("overrides.json");
+        config.providers.local_overrides = path.to_string_lossy().to_string();
+        let pool = memory_pool().await;
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        write_overrides(&path, "{not json", mtime);
+
+        // File is still indexed, the error is reported
+        testing::scan(&config, &pool).await;
+        let (id, _) = item::get_fingerprint(&pool, "a.safetensors", LABEL)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(get(&pool, id).await.unwrap().is_none());
+        let (errors, _) = scan_error::get(&pool, Some("identify"), 10, 0).await.unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.starts_with("local: "));
+
+        // Unchanged model file is identified again once overrides file is fixed
+        let hashes = calculate_hashes(&dir.path().join("a.safetensors")).unwrap();
+        write_overrides(
+            &path,
+            &overrides(&hashes.sha256, "fixed"),
+            mtime + Duration::from_secs(1),
+        );
+        testing::scan(&config, &pool).await;
+        let identified = get(&pool, id).await.unwrap().unwrap();
+        assert_eq!(identified.provider, ProviderKind::Local);
+        assert_eq!(identified.record.name, "fixed");
+        let (errors, _) = scan_error::get(&pool, Some("identify"), 10, 0).await.unwrap();
+        assert!(errors.is_empty());
+    }
+}
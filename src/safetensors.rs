@@ -0,0 +1,173 @@
+//! Copyright (c) 2025 Trung Do <dothanhtrung@pm.me>.
+//!
+//! Read the JSON header of safetensors file without loading tensors.
+//! Layout: 8 bytes little-endian header size, then the JSON header.
+
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Refuse header bigger than this. Real headers are a few MB at most.
+const MAX_HEADER_SIZE: u64 = 100 * 1024 * 1024;
+
+#[derive(Deserialize)]
+pub struct TensorInfo {
+    pub dtype: String,
+    pub shape: Vec<u64>,
+}
+
+/// Tensor names, shapes and embedded metadata of a model file
+pub struct TensorHeader {
+    pub tensors: BTreeMap<String, TensorInfo>,
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl TensorHeader {
+    /// Fail if total number of parameters overflows
+    pub fn new(tensors: BTreeMap<String, TensorInfo>, metadata: BTreeMap<String, String>) -> anyhow::Result<Self> {
+        if checked_param_count(&tensors).is_none() {
+            return Err(anyhow::anyhow!("Number of parameters overflows"));
+        }
+        Ok(Self { tensors, metadata })
+    }
+
+    pub fn tensor_count(&self) -> usize {
+        self.tensors.len()
+    }
+
+    /// Number of tensors per dtype
+    pub fn dtypes(&self) -> BTreeMap<String, u64> {
+        let mut dtypes = BTreeMap::new();
+        for tensor in self.tensors.values() {
+            *dtypes.entry(tensor.dtype.clone()).or_insert(0) += 1;
+        }
+        dtypes
+    }
+
+    pub fn param_count(&self) -> u64 {
+        checked_param_count(&self.tensors).unwrap_or_default()
+    }
+}
+
+/// Total number of parameters, None if it does not fit in i64 of DB
+fn checked_param_count(tensors: &BTreeMap<String, TensorInfo>) -> Option<u64> {
+    let mut count = 0u64;
+    for tensor in tensors.values() {
+        let params = tensor.shape.iter().try_fold(1u64, |acc, dim| acc.checked_mul(*dim))?;
+        count = count.checked_add(params)?;
+    }
+    (count <= i64::MAX as u64).then_some(count)
+}
+
+pub fn read_header(path: &Path) -> anyhow::Result<TensorHeader> {
+    let mut file = File::open(path)?;
+    let file_size = file.metadata()?.len();
+    parse_header(&mut file, file_size)
+}
+
+fn parse_header(reader: &mut impl Read, file_size: u64) -> anyhow::Result<TensorHeader> {
+    let mut size_buf = [0u8; 8];
+    reader.read_exact(&mut size_buf)?;
+    let header_size = u64::from_le_bytes(size_buf);
+    if header_size > MAX_HEADER_SIZE || header_size > file_size {
+        return Err(anyhow::anyhow!("Invalid safetensors header size {}", header_size));
+    }
+
+    let mut header_buf = vec![0u8; header_size as usize];
+    reader.read_exact(&mut header_buf)?;
+    let mut header: HashMap<String, Value> = serde_json::from_slice(&header_buf)?;
+
+    let metadata = match header.remove("__metadata__") {
+        Some(Value::Object(map)) => map
+            .into_iter()
+            .map(|(k, v)| match v {
+                Value::String(s) => (k, s),
+                v => (k, v.to_string()),
+            })
+            .collect(),
+        _ => BTreeMap::new(),
+    };
+
+    let mut tensors = BTreeMap::new();
+    for (name, value) in header {
+        tensors.insert(name, serde_json::from_value::<TensorInfo>(value)?);
+    }
+
+    TensorHeader::new(tensors, metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Safetensors file with the given JSON header and no tensor data
+    fn file(header: &str) -> Vec<u8> {
+        let mut buf = (header.len() as u64).to_le_bytes().to_vec();
+        buf.extend_from_slice(header.as_bytes());
+        buf
+    }
+
+    fn parse(buf: &[u8]) -> anyhow::Result<TensorHeader> {
+        parse_header(&mut &buf[..], buf.len() as u64)
+    }
+
+    #[test]
+    fn parse_tensors_and_metadata() {
+        let buf = file(
+            r#"{
+                "__metadata__": {"format": "pt", "ss_network_dim": "32"},
+                "a.weight": {"dtype": "F16", "shape": [320, 768], "data_offsets": [0, 491520]},
+                "a.bias": {"dtype": "F32", "shape": [320], "data_offsets": [491520, 492800]}
+            }"#,
+        );
+        let header = parse(&buf).unwrap();
+        assert_eq!(header.tensor_count(), 2);
+        assert_eq!(header.param_count(), 320 * 768 + 320);
+        assert_eq!(header.tensors["a.weight"].shape, vec![320, 768]);
+        assert_eq!(
+            header.dtypes(),
+            BTreeMap::from([("F16".to_string(), 1), ("F32".to_string(), 1)])
+        );
+        assert_eq!(header.metadata["ss_network_dim"], "32");
+    }
+
+    #[test]
+    fn scalar_tensor_has_one_param() {
+        let buf = file(r#"{"step": {"dtype": "I64", "shape": [], "data_offsets": [0, 8]}}"#);
+        assert_eq!(parse(&buf).unwrap().param_count(), 1);
+    }
+
+    #[test]
+    fn reject_overflowing_shape() {
+        let buf = file(r#"{"a": {"dtype": "F16", "shape": [4294967296, 4294967296], "data_offsets": [0, 0]}}"#);
+        assert!(parse(&buf).is_err());
+    }
+
+    #[test]
+    fn reject_overflowing_sum() {
+        let buf = file(
+            r#"{
+                "a": {"dtype": "F16", "shape": [9223372036854775807], "data_offsets": [0, 0]},
+                "b": {"dtype": "F16", "shape": [2], "data_offsets": [0, 0]}
+            }"#,
+        );
+        assert!(parse(&buf).is_err());
+    }
+
+    #[test]
+    fn reject_header_size_bigger_than_file() {
+        let mut buf = file(r#"{}"#);
+        buf[..8].copy_from_slice(&1000u64.to_le_bytes());
+        assert!(parse(&buf).is_err());
+    }
+
+    #[test]
+    fn reject_truncated_and_invalid_header() {
+        assert!(parse(&[1, 0, 0]).is_err());
+        assert!(parse(&file("not json")).is_err());
+        assert!(parse(&file(r#"{"a": {"dtype": "F16"}}"#)).is_err());
+    }
+}
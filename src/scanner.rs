@@ -0,0 +1,542 @@
+//! Copyright (c) 2025 Trung Do <dothanhtrung@pm.me>.
+
+use crate::civitai::{calculate_hashes, read_info, recommended_weight, FileHashes};
+use crate::classify::classify;
+use crate::config::Config;
+use crate::db::app_info;
+use crate::db::hash;
+use crate::db::item::{self, insert_or_update, Fingerprint};
+use crate::db::model_header::{self, ModelHeader};
+use crate::db::pickle_scan;
+use crate::db::scan_error::{self, ScanStage};
+use crate::db::tag;
+use crate::db::trigger_word;
+use crate::duplicate::abs_path;
+use crate::gguf;
+use crate::job::{unix_time, Job};
+use crate::kohya;
+use crate::pickle::{self, PickleScan};
+use crate::provider::{self, Identified, ModelFile, ModelRecord, ProviderKind, Providers};
+use crate::safetensors::{self, TensorHeader};
+use crate::trash;
+use jwalk::{Parallelism, WalkDir};
+use serde_json::Value;
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tokio::fs;
+use tokio::task::spawn_blocking;
+use tracing::{error, info, warn};
+
+const SAFETENSORS_EXT: &str = "safetensors";
+const GGUF_EXT: &str = "gguf";
+const PICKLE_EXTS: [&str; 3] = ["ckpt", "pt", "pth"];
+
+pub const UNSAFE_PICKLE_TAG: &str = "unsafe_pickle";
+
+/// Modification time of local overrides file at the last complete scan
+const LOCAL_OVERRIDES_MTIME_LABEL: &str = "local_overrides_mtime";
+
+/// Walk all model paths and update the item table.
+/// Files whose fingerprint is not changed since last scan are only marked as checked, unless `force` is set
+/// or the local overrides file is changed.
+/// Items which are not seen during a complete scan are marked obsolete, except under paths which could not be walked.
+pub async fn reload_from_disk(
+    config: &Config,
+    pool: &SqlitePool,
+    providers: &Providers,
+    job: &Job,
+    force: bool,
+) -> anyhow::Result<()> {
+    let valid_ext = config.extensions.iter().collect::<HashSet<_>>();
+    let started_at = unix_time();
+    let overrides_mtime = providers.local_overrides_mtime().await;
+    let overrides_changed = app_info::get(pool, LOCAL_OVERRIDES_MTIME_LABEL)
+        .await?
+        .unwrap_or_default()
+        != overrides_mtime;
+    if overrides_changed {
+        info!("Local overrides changed, identify all files again");
+    }
+    let force = force || overrides_changed;
+
+    scan_error::clear_stage(pool, ScanStage::Walk).await?;
+    scan_error::clear_stage(pool, ScanStage::DBInsert).await?;
+
+    let (mut updated, mut skipped) = (0, 0);
+    // (label, relative path) of files and directories which could not be read
+    let mut unreadable = Vec::new();
+    'walk: for (label, base_path) in config.model_paths.iter() {
+        let parallelism = Parallelism::RayonNewPool(config.walkdir_parallel);
+        for entry in WalkDir::new(base_path)
+            .skip_hidden(true)
+            .parallelism(parallelism.clone())
+            .follow_links(true)
+        {
+            if job.is_cancelled() {
+                break 'walk;
+            }
+
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    let path = e
+                        .path()
+                        .map(|p| p.to_path_buf())
+                        .unwrap_or_else(|| PathBuf::from(base_path));
+                    let relative_path = get_relative_path(base_path, &path).unwrap_or_default();
+                    report(pool, Some(job), label, &relative_path, ScanStage::Walk, &e.to_string()).await;
+                    unreadable.push((label.as_str(), relative_path));
+                    continue;
+                }
+            };
+            let path = entry.path();
+
+            if !(entry.file_type().is_file() || entry.file_type().is_symlink()) {
+                continue;
+            }
+
+            let file_ext = path.extension().unwrap_or_default().to_str().unwrap_or_default();
+            if !valid_ext.contains(&file_ext.to_string()) {
+                continue;
+            }
+
+            let Ok(relative_path) = get_relative_path(base_path, &path) else {
+                continue;
+            };
+            job.inc_seen();
+
+            let fingerprint = match get_fingerprint(&path).await {
+                Ok(f) => f,
+                Err(e) => {
+                    report(pool, Some(job), label, &relative_path, ScanStage::Walk, &e.to_string()).await;
+                    unreadable.push((label.as_str(), relative_path));
+                    continue;
+                }
+            };
+
+            match item::get_fingerprint(pool, &relative_path, label).await {
+                Ok(Some((id, old))) if old == fingerprint && !force => {
+                    if let Err(e) = item::mark_checked(pool, id).await {
+                        error!("Failed to mark item as checked: {}", e);
+                    }
+                    skipped += 1;
+                    continue;
+                }
+                Err(e) => error!("Failed to get item fingerprint: {}", e),
+                _ => {}
+            }
+
+            index_file(config, pool, providers, label, &relative_path, &fingerprint, Some(job)).await;
+            updated += 1;
+        }
+    }
+
+    if job.is_cancelled() {
+        info!("Reload from disk cancelled: {} updated, {} unchanged", updated, skipped);
+        return Ok(());
+    }
+
+    if unreadable.is_empty() {
+        item::mark_obsolete_unseen(pool, started_at).await?;
+    } else {
+        for unseen in item::get_unseen(pool, started_at).await? {
+            let path = Path::new(&unseen.path);
+            let kept = unreadable
+                .iter()
+                .any(|(label, dir)| *label == unseen.base_label && path.starts_with(dir));
+            if !kept {
+                item::mark_obsolete(pool, unseen.id).await?;
+            }
+        }
+    }
+    scan_error::clear_obsolete(pool).await?;
+    app_info::set(pool, LOCAL_OVERRIDES_MTIME_LABEL, overrides_mtime).await?;
+    info!("Reload from disk done: {} updated, {} unchanged", updated, skipped);
+    Ok(())
+}
+
+/// Hash model file, parse its json sidecar if any, identify it with providers in configured order,
+/// then insert/update item and its tags. Errors are recorded per file and do not stop the scan.
+pub async fn index_file(
+    config: &Config,
+    pool: &SqlitePool,
+    providers: &Providers,
+    label: &str,
+    relative_path: &str,
+    fingerprint: &Fingerprint,
+    job: Option<&Job>,
+) {
+    let path = &abs_path(config, label, relative_path);
+    if let Err(e) = scan_error::clear_path(pool, relative_path, label, &ScanStage::SCAN).await {
+        error!("Failed to clear scan errors: {}", e);
+    }
+
+    let name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_str()
+        .unwrap_or_default()
+        .to_string();
+
+    let mut fingerprint = *fingerprint;
+    let hashes = match get_hashes(pool, label, relative_path, path, &fingerprint, job).await {
+        Ok(h) => h,
+        Err(e) => {
+            report(pool, job, label, relative_path, ScanStage::Hash, &e.to_string()).await;
+            // Leave fingerprint empty so the file is hashed again on next scan
+            fingerprint = Fingerprint::default();
+            FileHashes::default()
+        }
+    };
+
+    // Info in DB belongs to the item of this file if it is already indexed
+    let known_id = match item::get_fingerprint(pool, relative_path, label).await {
+        Ok(known) => known.map(|(id, _)| id),
+        Err(e) => {
+            report(pool, job, label, relative_path, ScanStage::DBInsert, &e.to_string()).await;
+            None
+        }
+    };
+    let info = match read_info(pool, known_id, path).await {
+        Ok(info) => info,
+        Err(e) => {
+            report(pool, job, label, relative_path, ScanStage::ReadSidecar, &e.to_string()).await;
+            String::new()
+        }
+    };
+    let v: Value = if info.is_empty() {
+        Value::Null
+    } else {
+        match serde_json::from_str(&info) {
+            Ok(v) => v,
+            Err(e) => {
+                report(pool, job, label, relative_path, ScanStage::Parse, &e.to_string()).await;
+                Value::Null
+            }
+        }
+    };
+
+    let file = ModelFile {
+        item: known_id,
+        path,
+        hashes: &hashes,
+    };
+    let mut identified = None;
+    for provider in providers.iter() {
+        match provider.identify(&file).await {
+            Ok(Some(record)) => {
+                identified = Some(Identified {
+                    provider: provider.kind(),
+                    record,
+                });
+                break;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                let message = format!("{}: {}", provider.kind().as_str(), e);
+                report(pool, job, label, relative_path, ScanStage::Identify, &message).await;
+            }
+        }
+    }
+    let model_name = identified.as_ref().map(|i| i.record.name.as_str()).unwrap_or_default();
+
+    match insert_or_update(
+        pool,
+        Some(name.as_str()),
+        relative_path,
+        label,
+        &hashes.blake3,
+        model_name,
+        &fingerprint,
+    )
+    .await
+    {
+        Ok(id) => {
+            let header = match index_header(pool, id, path).await {
+                Ok(header) => header,
+                Err((stage, e)) => {
+                    report(pool, job, label, relative_path, stage, &e.to_string()).await;
+                    None
+                }
+            };
+
+            if let Some(header) = &header {
+                if let Err(e) = index_kohya_metadata(pool, id, header).await {
+                    report(pool, job, label, relative_path, ScanStage::DBInsert, &e.to_string()).await;
+                }
+            }
+
+            if let Err(e) = index_civitai_info(pool, id, &v).await {
+                report(pool, job, label, relative_path, ScanStage::DBInsert, &e.to_string()).await;
+            }
+
+            if let Err(e) = index_record(pool, id, identified.as_ref(), header.as_ref()).await {
+                report(pool, job, label, relative_path, ScanStage::DBInsert, &e.to_string()).await;
+            }
+
+            if let Err((stage, e)) = index_pickle(config, pool, job, label, relative_path, id, path).await {
+                report(pool, job, label, relative_path, stage, &e.to_string()).await;
+            }
+        }
+        Err(e) => report(pool, job, label, relative_path, ScanStage::DBInsert, &e.to_string()).await,
+    }
+}
+
+/// Read tensor summary and embedded metadata of safetensors or GGUF file
+async fn index_header(
+    pool: &SqlitePool,
+    item: i64,
+    path: &Path,
+) -> Result<Option<TensorHeader>, (ScanStage, anyhow::Error)> {
+    let format = path
+        .extension()
+        .unwrap_or_default()
+        .to_str()
+        .unwrap_or_default()
+        .to_string();
+    let reader = match format.as_str() {
+        SAFETENSORS_EXT => safetensors::read_header,
+        GGUF_EXT => gguf::read_header,
+        _ => return Ok(None),
+    };
+
+    let file_path = path.to_path_buf();
+    let header = spawn_blocking(move || reader(&file_path))
+        .await
+        .map_err(|e| (ScanStage::Parse, e.into()))?
+        .map_err(|e| (ScanStage::Parse, e))?;
+
+    let model_header = ModelHeader {
+        format,
+        tensor_count: header.tensor_count() as i64,
+        param_count: header.param_count() as i64,
+        dtypes: header.dtypes(),
+        metadata: header.metadata.clone(),
+    };
+    model_header::insert_or_update(pool, item, &model_header)
+        .await
+        .map_err(|e| (ScanStage::DBInsert, e.into()))?;
+
+    Ok(Some(header))
+}
+
+/// List globals imported by pickle file. Unsafe files, including files which cannot be fully parsed,
+/// are tagged and moved to quarantine if configured.
+async fn index_pickle(
+    config: &Config,
+    pool: &SqlitePool,
+    job: Option<&Job>,
+    label: &str,
+    relative_path: &str,
+    item: i64,
+    path: &Path,
+) -> Result<(), (ScanStage, anyhow::Error)> {
+    let ext = path.extension().unwrap_or_default().to_str().unwrap_or_default();
+    if !PICKLE_EXTS.contains(&ext) {
+        return Ok(());
+    }
+
+    let file_path = path.to_path_buf();
+    let scan = spawn_blocking(move || pickle::scan(&file_path))
+        .await
+        .unwrap_or_else(|e| PickleScan {
+            error: Some(format!("Pickle scanner failed: {}", e)),
+            ..Default::default()
+        });
+    if let Some(e) = &scan.error {
+        report(pool, job, label, relative_path, ScanStage::Parse, e).await;
+    }
+    pickle_scan::insert_or_update(pool, item, &scan)
+        .await
+        .map_err(|e| (ScanStage::DBInsert, e.into()))?;
+
+    if scan.is_safe() {
+        return tag::remove_tag_item(pool, item, UNSAFE_PICKLE_TAG)
+            .await
+            .map_err(|e| (ScanStage::DBInsert, e));
+    }
+
+    warn!(
+        "Unsafe pickle {}: {:?} {}",
+        path.display(),
+        scan.unsafe_globals,
+        scan.error.as_deref().unwrap_or_default()
+    );
+    tag::add_tag_item(pool, item, &vec![UNSAFE_PICKLE_TAG.to_string()])
+        .await
+        .map_err(|e| (ScanStage::DBInsert, e.into()))?;
+
+    if config.pickle.quarantine {
+        trash::move_item(config, pool, item, &config.pickle.quarantine_dir)
+            .await
+            .map_err(|e| (ScanStage::Quarantine, e))?;
+        info!("Quarantined: {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// Store trigger words, network dim and alpha of LoRA trained by kohya-ss
+async fn index_kohya_metadata(pool: &SqlitePool, item: i64, header: &TensorHeader) -> Result<(), sqlx::Error> {
+    let Some(info) = kohya::parse(&header.metadata) else {
+        return Ok(());
+    };
+
+    item::update_network(pool, item, info.network_dim, info.network_alpha).await?;
+    trigger_word::replace(pool, item, trigger_word::SOURCE_KOHYA, &info.trigger_words).await?;
+    if let Some(output_name) = &info.output_name {
+        item::set_default_model_name(pool, item, output_name).await?;
+    }
+
+    Ok(())
+}
+
+/// Store recommended weight and Civitai IDs from Civitai info. Null info clears them.
+async fn index_civitai_info(pool: &SqlitePool, item: i64, info: &Value) -> Result<(), sqlx::Error> {
+    item::update_recommended_weight(pool, item, recommended_weight(info)).await?;
+    item::update_civitai_ids(pool, item, info["modelId"].as_i64(), info["id"].as_i64()).await?;
+
+    Ok(())
+}
+
+/// Store record of the provider which identified item, its trigger words and tags.
+/// Trigger words of other providers are cleared.
+async fn index_record(
+    pool: &SqlitePool,
+    item: i64,
+    identified: Option<&Identified>,
+    header: Option<&TensorHeader>,
+) -> anyhow::Result<()> {
+    provider::save(pool, item, identified).await?;
+    for kind in ProviderKind::ALL {
+        let words = identified
+            .filter(|i| i.provider == kind)
+            .map(|i| {
+                i.record
+                    .trigger_words
+                    .iter()
+                    .map(|w| (w.clone(), 0))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        trigger_word::replace(pool, item, kind.as_str(), &words).await?;
+    }
+    add_tag_from_record(pool, item, identified.map(|i| &i.record), header).await?;
+
+    Ok(())
+}
+
+/// Tag model type, base model and tags of record.
+/// Type and base model guessed from tensors are used when record has none, or when no provider knows the file.
+async fn add_tag_from_record(
+    pool: &SqlitePool,
+    item: i64,
+    record: Option<&ModelRecord>,
+    header: Option<&TensorHeader>,
+) -> Result<(), sqlx::Error> {
+    let classification = header.map(classify).unwrap_or_default();
+    let model_type = record
+        .map(|r| r.model_type.as_str())
+        .filter(|t| !t.is_empty())
+        .or(classification.kind.map(|k| k.as_str()));
+    let base_model = record
+        .map(|r| r.base_model.as_str())
+        .filter(|b| !b.is_empty())
+        .or(classification.base_model.map(|b| b.as_str()));
+
+    let tags = model_type
+        .into_iter()
+        .chain(base_model)
+        .chain(record.into_iter().flat_map(|r| r.tags.iter().map(String::as_str)))
+        .map(tag::normalize)
+        .filter(|t| !t.is_empty())
+        .collect::<Vec<_>>();
+    tag::add_tag_item(pool, item, &tags).await
+}
+
+/// Log error of a file, record it in DB and count it in job
+pub async fn report(
+    pool: &SqlitePool,
+    job: Option<&Job>,
+    label: &str,
+    relative_path: &str,
+    stage: ScanStage,
+    message: &str,
+) {
+    error!("{} {}/{}: {}", stage.as_str(), label, relative_path, message);
+    scan_error::insert(pool, relative_path, label, stage, message).await;
+    if let Some(job) = job {
+        job.fail(format!("{}/{}: {}", label, relative_path, message));
+    }
+}
+
+/// Get hashes from cache or calculate them if file is changed since last hashing
+async fn get_hashes(
+    pool: &SqlitePool,
+    label: &str,
+    relative_path: &str,
+    path: &Path,
+    fingerprint: &Fingerprint,
+    job: Option<&Job>,
+) -> anyhow::Result<FileHashes> {
+    if let Some(hashes) = hash::get(pool, relative_path, label, fingerprint.size, fingerprint.mtime).await? {
+        return Ok(hashes);
+    }
+
+    let file_path = path.to_path_buf();
+    let hashes = spawn_blocking(move || calculate_hashes(&file_path)).await??;
+    if let Some(job) = job {
+        job.inc_hashed();
+    }
+    hash::insert_or_update(pool, relative_path, label, fingerprint.size, fingerprint.mtime, &hashes).await?;
+
+    Ok(hashes)
+}
+
+/// Build fingerprint of model file and its json sidecar
+pub async fn get_fingerprint(path: &Path) -> std::io::Result<Fingerprint> {
+    let metadata = fs::metadata(path).await?;
+
+    let mut json_file = PathBuf::from(path);
+    json_file.set_extension("json");
+    let json_mtime = match fs::metadata(&json_file).await {
+        Ok(m) => mtime(&m),
+        Err(_) => 0,
+    };
+
+    Ok(Fingerprint {
+        size: metadata.len() as i64,
+        mtime: mtime(&metadata),
+        inode: inode(&metadata),
+        json_mtime,
+    })
+}
+
+fn mtime(metadata: &Metadata) -> i64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(unix)]
+fn inode(metadata: &Metadata) -> i64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.ino() as i64
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &Metadata) -> i64 {
+    0
+}
+
+pub fn get_relative_path(base_path: &str, path: &Path) -> Result<String, anyhow::Error> {
+    let base = PathBuf::from(base_path);
+    let path = path.strip_prefix(&base)?;
+    Ok(path.to_str().unwrap_or_default().to_string())
+}
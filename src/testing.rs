@@ -0,0 +1,105 @@
+//! Copyright (c) 2025 Trung Do <dothanhtrung@pm.me>.
+//!
+//! Helpers for tests: in-memory database, temporary directories and local stand-in servers for remote APIs.
+
+use crate::config::Config;
+use crate::job::{JobKind, JobManager};
+use crate::provider::Providers;
+use crate::scanner::reload_from_disk;
+use actix_web::{web, App, HttpServer};
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Empty database with the current schema. One connection, since each connection gets its own in-memory database.
+pub async fn memory_pool() -> SqlitePool {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::raw_sql(include_str!("../db/db_init.sql"))
+        .execute(&pool)
+        .await
+        .unwrap();
+    pool
+}
+
+/// Directory under system temp dir, removed with its content on drop
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "sdmm-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+pub const LABEL: &str = "collection1";
+
+/// Config with `dir` as the only collection, holding a small safetensors file for each name
+pub fn collection(dir: &TempDir, names: &[&str]) -> Config {
+    for name in names {
+        write_model(&dir.path().join(format!("{}.safetensors", name)));
+    }
+
+    Config {
+        model_paths: HashMap::from([(LABEL.to_string(), dir.path().to_string_lossy().to_string())]),
+        ..Default::default()
+    }
+}
+
+/// Write a small safetensors file, with content depending on its name
+pub fn write_model(path: &Path) {
+    let mut content = 2u64.to_le_bytes().to_vec();
+    content.extend_from_slice(b"{}");
+    content.extend_from_slice(path.file_stem().unwrap().as_encoded_bytes());
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+}
+
+/// Index all files of collections, with their hashes
+pub async fn scan(config: &Config, pool: &SqlitePool) {
+    let job = JobManager::default().start(JobKind::Scan).unwrap();
+    reload_from_disk(config, pool, &Providers::new(config, pool), &job, false)
+        .await
+        .unwrap();
+    job.finish(&Ok(()));
+}
+
+/// Start a server with the given routes on a free local port and return its base URL.
+/// It runs until the test runtime is shut down.
+pub fn serve<F>(routes: F) -> String
+where
+    F: Fn(&mut web::ServiceConfig) + Send + Clone + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = HttpServer::new(move || App::new().configure(routes.clone()))
+        .workers(1)
+        .disable_signals()
+        .listen(listener)
+        .unwrap()
+        .run();
+    actix_web::rt::spawn(server);
+    format!("http://{}", addr)
+}
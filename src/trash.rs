@@ -0,0 +1,115 @@
+//! Copyright (c) 2025 Trung Do <dothanhtrung@pm.me>.
+//!
+//! Move files of items out of the library, to trash or quarantine.
+
+use crate::civitai::gallery::GALLERY_EXT;
+use crate::civitai::PREVIEW_EXT;
+use crate::config::Config;
+use crate::db::item;
+use sqlx::SqlitePool;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+pub const TRASH_DIR: &str = ".trash";
+
+/// Move model, json and preview files of item to trash directory of its collection, then mark item obsolete
+pub async fn trash_item(config: &Config, pool: &SqlitePool, id: i64) -> anyhow::Result<()> {
+    move_item(config, pool, id, TRASH_DIR).await
+}
+
+/// Move model, json, preview and gallery files of item into `dir` of its collection, then mark item obsolete.
+/// Relative path of item is kept under `dir`. A suffix is added if a file with the same name is already there.
+pub async fn move_item(config: &Config, pool: &SqlitePool, id: i64, dir: &str) -> anyhow::Result<()> {
+    let item = item::get_by_id(pool, id).await?;
+    let Some(base_path) = config.model_paths.get(&item.base_label) else {
+        return Err(anyhow::anyhow!("Unknown collection {}", item.base_label));
+    };
+    let base_path = PathBuf::from(base_path);
+    let model_file = base_path.join(&item.path);
+    let dest = free_dest(&base_path.join(dir).join(&item.path));
+    // TODO: Removed downloaded video
+
+    move_file(&model_file, &dest).await?;
+    for ext in ["json", PREVIEW_EXT, GALLERY_EXT] {
+        let file = model_file.with_extension(ext);
+        if file.exists() {
+            move_file(&file, &dest.with_extension(ext)).await?;
+        }
+    }
+    item::mark_obsolete(pool, id).await?;
+
+    Ok(())
+}
+
+/// Return `dest`, or `dest` with a number appended to its stem if it or its sidecar files already exist
+fn free_dest(dest: &Path) -> PathBuf {
+    let taken = |path: &Path| {
+        path.exists()
+            || ["json", PREVIEW_EXT, GALLERY_EXT]
+                .iter()
+                .any(|ext| path.with_extension(ext).exists())
+    };
+    let stem = dest.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = dest
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut ret = dest.to_path_buf();
+    let mut n = 1;
+    while taken(&ret) {
+        ret = dest.with_file_name(format!("{}_{}{}", stem, n, ext));
+        n += 1;
+    }
+    ret
+}
+
+async fn move_file(file: &Path, dest: &Path) -> anyhow::Result<()> {
+    if let Some(dir) = dest.parent() {
+        fs::create_dir_all(dir).await?;
+    }
+    fs::rename(file, dest).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, memory_pool, write_model, TempDir, LABEL};
+
+    #[tokio::test]
+    async fn trash_keeps_earlier_trashed_files() {
+        let dir = TempDir::new();
+        let config = testing::collection(&dir, &["a"]);
+        std::fs::write(dir.path().join("a.json"), "{}").unwrap();
+        let pool = memory_pool().await;
+        testing::scan(&config, &pool).await;
+        let (first, _) = item::get_fingerprint(&pool, "a.safetensors", LABEL)
+            .await
+            .unwrap()
+            .unwrap();
+        trash_item(&config, &pool, first).await.unwrap();
+
+        // Same file name again, e.g. downloaded after being trashed
+        write_model(&dir.path().join("a.safetensors"));
+        testing::scan(&config, &pool).await;
+        let (second, _) = item::get_fingerprint(&pool, "a.safetensors", LABEL)
+            .await
+            .unwrap()
+            .unwrap();
+        trash_item(&config, &pool, second).await.unwrap();
+
+        let trash = dir.path().join(TRASH_DIR);
+        assert!(trash.join("a.safetensors").exists());
+        assert!(trash.join("a.json").exists());
+        assert!(trash.join("a_1.safetensors").exists());
+        assert!(!dir.path().join("a.safetensors").exists());
+        let checked: bool = sqlx::query_scalar(r#"SELECT is_checked FROM item WHERE id = ?"#)
+            .bind(second)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert!(!checked);
+    }
+}
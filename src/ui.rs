@@ -10,6 +10,8 @@ pub fn scope_config(cfg: &mut web::ServiceConfig) {
     cfg.app_data(Data::new(tera))
         .service(index)
         .service(get_item)
+        .service(maintain)
+        .service(civitai)
         .service(Files::new(
             "/assets",
             concat!(env!("CARGO_MANIFEST_DIR"), "/res/assets"),
@@ -37,3 +39,23 @@ async fn get_item(tmpl: Data<Tera>) -> impl Responder {
         .unwrap_or_default();
     HttpResponse::Ok().content_type("text/html").body(template)
 }
+
+#[get("/maintain")]
+async fn maintain(tmpl: Data<Tera>) -> impl Responder {
+    let ctx = tera::Context::new();
+    let template = tmpl
+        .render("maintain.html", &ctx)
+        .map_err(|e| error::ErrorInternalServerError(format!("Template error: {:?}", e)))
+        .unwrap_or_default();
+    HttpResponse::Ok().content_type("text/html").body(template)
+}
+
+#[get("/civitai")]
+async fn civitai(tmpl: Data<Tera>) -> impl Responder {
+    let ctx = tera::Context::new();
+    let template = tmpl
+        .render("civitai.html", &ctx)
+        .map_err(|e| error::ErrorInternalServerError(format!("Template error: {:?}", e)))
+        .unwrap_or_default();
+    HttpResponse::Ok().content_type("text/html").body(template)
+}
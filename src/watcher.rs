@@ -0,0 +1,370 @@
+//! Copyright (c) 2025 Trung Do <dothanhtrung@pm.me>.
+//!
+//! Watch model paths and keep the item table in sync with the file system.
+
+use crate::civitai::PREVIEW_EXT;
+use crate::config::Config;
+use crate::db::{hash, item};
+use crate::job::JobManager;
+use crate::provider::Providers;
+use crate::scanner::{get_fingerprint, get_relative_path, index_file};
+use jwalk::WalkDir;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use sqlx::SqlitePool;
+use std::cmp::max;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf, MAIN_SEPARATOR};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::time::interval;
+use tracing::{error, info};
+
+/// Start watching all model paths.
+/// The returned watcher must be kept alive for as long as events are wanted.
+pub fn start(
+    config: Arc<Config>,
+    pool: SqlitePool,
+    providers: Arc<Providers>,
+    jobs: Arc<JobManager>,
+) -> anyhow::Result<RecommendedWatcher> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| match res {
+        // Indexing reads the file, so access events would queue it again forever
+        Ok(event) if event.kind.is_access() => {}
+        Ok(event) => {
+            for path in event.paths {
+                let _ = tx.send(path);
+            }
+        }
+        Err(e) => error!("Watch error: {}", e),
+    })?;
+
+    for (label, base_path) in config.model_paths.iter() {
+        match watcher.watch(Path::new(base_path), RecursiveMode::Recursive) {
+            Ok(_) => info!("Watching {}: {}", label, base_path),
+            Err(e) => error!("Failed to watch {}: {}", base_path, e),
+        }
+    }
+
+    tokio::spawn(debounce(config, pool, providers, jobs, rx));
+
+    Ok(watcher)
+}
+
+/// Collect changed paths and process them once they have been quiet for `debounce_ms`.
+/// Copying a big file fires lots of events, so the file is only indexed when the copy is done.
+/// Changes are kept while a scan or sync job is running, since it writes the same rows.
+async fn debounce(
+    config: Arc<Config>,
+    pool: SqlitePool,
+    providers: Arc<Providers>,
+    jobs: Arc<JobManager>,
+    mut rx: mpsc::UnboundedReceiver<PathBuf>,
+) {
+    let delay = Duration::from_millis(config.watcher.debounce_ms);
+    // interval() panics on a zero period
+    let mut ticker = interval(max(delay / 2, Duration::from_millis(1)));
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+
+    loop {
+        tokio::select! {
+            path = rx.recv() => {
+                let Some(path) = path else {
+                    break;
+                };
+                if let Some(path) = changed_path(&config, &path) {
+                    pending.insert(path, Instant::now());
+                }
+            }
+            _ = ticker.tick() => {
+                let now = Instant::now();
+                let ready: Vec<PathBuf> = pending
+                    .iter()
+                    .filter(|(_, last)| now.duration_since(**last) >= delay)
+                    .map(|(path, _)| path.clone())
+                    .collect();
+                if ready.is_empty() || jobs.is_writing_items() {
+                    continue;
+                }
+                for path in ready.iter() {
+                    pending.remove(path);
+                }
+                process(&config, &pool, &providers, ready).await;
+            }
+        }
+    }
+}
+
+/// Insert, update or mark obsolete the items of changed files and of all model files under changed directories.
+/// A removed file and a new file with same inode and size in one batch are treated as a move,
+/// so renaming a directory keeps the items of its files.
+async fn process(config: &Config, pool: &SqlitePool, providers: &Providers, paths: Vec<PathBuf>) {
+    let mut removed = Vec::new();
+    let mut removed_dirs = Vec::new();
+    let mut present = Vec::new();
+    for path in paths {
+        let Some((label, relative_path)) = find_label(config, &path) else {
+            continue;
+        };
+        if path.is_dir() {
+            present.extend(model_files_under(config, &path));
+        } else if path.exists() {
+            present.push((label, relative_path, path));
+        } else if is_model(config, &path) {
+            removed.push((label, relative_path));
+        } else if !relative_path.is_empty() {
+            // A directory which is removed or moved away is reported as a single event
+            removed_dirs.push((label, relative_path));
+        }
+    }
+
+    let mut moved_from = Vec::new();
+    for (label, relative_path) in removed {
+        match item::get_fingerprint(pool, &relative_path, &label).await {
+            Ok(Some((id, fingerprint))) => {
+                if let Err(e) = item::mark_obsolete_by_path(pool, &relative_path, &label).await {
+                    error!("Failed to mark item obsolete: {}", e);
+                }
+                info!("Removed: {}/{}", label, relative_path);
+                moved_from.push((id, fingerprint, label, relative_path));
+            }
+            Ok(None) => {}
+            Err(e) => error!("Failed to get item: {}", e),
+        }
+    }
+    for (label, dir) in removed_dirs {
+        let prefix = format!("{}{}", dir, MAIN_SEPARATOR);
+        match item::get_checked_under(pool, &prefix, &label).await {
+            Ok(items) => {
+                for (id, relative_path, fingerprint) in items {
+                    if let Err(e) = item::mark_obsolete(pool, id).await {
+                        error!("Failed to mark item obsolete: {}", e);
+                    }
+                    info!("Removed: {}/{}", label, relative_path);
+                    moved_from.push((id, fingerprint, label.clone(), relative_path));
+                }
+            }
+            Err(e) => error!("Failed to get items under {}/{}: {}", label, dir, e),
+        }
+    }
+
+    for (label, relative_path, path) in present {
+        let fingerprint = match get_fingerprint(&path).await {
+            Ok(f) => f,
+            Err(e) => {
+                error!("Failed to read metadata of {}: {}", path.display(), e);
+                continue;
+            }
+        };
+
+        match item::get_fingerprint(pool, &relative_path, &label).await {
+            Ok(None) => {
+                if let Some(pos) = moved_from
+                    .iter()
+                    .position(|(_, f, _, _)| f.inode != 0 && f.inode == fingerprint.inode && f.size == fingerprint.size)
+                {
+                    let (id, _, old_label, old_path) = moved_from.remove(pos);
+                    moved(pool, id, (&old_label, &old_path), (&label, &relative_path)).await;
+                }
+            }
+            // Files of a directory event are mostly unchanged, e.g. when only the directory is touched
+            Ok(Some((id, old))) if old == fingerprint => {
+                if let Err(e) = item::mark_checked(pool, id).await {
+                    error!("Failed to mark item as checked: {}", e);
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        index_file(config, pool, providers, &label, &relative_path, &fingerprint, None).await;
+    }
+}
+
+/// Point item and its cached hashes to the new (label, relative path) of its file
+async fn moved(pool: &SqlitePool, id: i64, from: (&str, &str), to: (&str, &str)) {
+    if let Err(e) = item::update_path(pool, id, to.1, to.0).await {
+        error!("Failed to update moved item: {}", e);
+    }
+    if let Err(e) = hash::rename(pool, from.1, from.0, to.1, to.0).await {
+        error!("Failed to update hash cache of moved item: {}", e);
+    }
+    info!("Moved: {} -> {}/{}", id, to.0, to.1);
+}
+
+/// (label, relative path, path) of all model files under a directory
+fn model_files_under(config: &Config, dir: &Path) -> Vec<(String, String, PathBuf)> {
+    WalkDir::new(dir)
+        .skip_hidden(true)
+        .follow_links(true)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file() || entry.file_type().is_symlink())
+        .map(|entry| entry.path())
+        .filter(|path| is_model(config, path))
+        .filter_map(|path| {
+            let (label, relative_path) = find_label(config, &path)?;
+            Some((label, relative_path, path))
+        })
+        .collect()
+}
+
+fn is_model(config: &Config, path: &Path) -> bool {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    config.extensions.iter().any(|e| e == ext)
+}
+
+/// Map a changed path to the path to process: a model file, or a directory which may hold model files.
+/// A path which is gone may have been a directory.
+fn changed_path(config: &Config, path: &Path) -> Option<PathBuf> {
+    model_file(config, path).or_else(|| (path.is_dir() || !path.exists()).then(|| path.to_path_buf()))
+}
+
+/// Map a changed path to its model file. Sidecars (json, preview) are mapped to the model next to them.
+fn model_file(config: &Config, path: &Path) -> Option<PathBuf> {
+    if is_model(config, path) {
+        return Some(path.to_path_buf());
+    }
+    let ext = path.extension()?.to_str()?;
+
+    if ext == "json" || ext == PREVIEW_EXT {
+        for model_ext in config.extensions.iter() {
+            let mut model = path.to_path_buf();
+            model.set_extension(model_ext);
+            if model.exists() {
+                return Some(model);
+            }
+        }
+    }
+
+    None
+}
+
+/// Return (label, relative path) of a file inside model paths. Hidden directories like trash are ignored.
+fn find_label(config: &Config, path: &Path) -> Option<(String, String)> {
+    config
+        .model_paths
+        .iter()
+        .filter(|(_, base_path)| path.starts_with(base_path))
+        .max_by_key(|(_, base_path)| base_path.len())
+        .and_then(|(label, base_path)| {
+            let relative = path.strip_prefix(base_path).ok()?;
+            let hidden = relative.components().any(|c| match c {
+                Component::Normal(name) => name.to_str().unwrap_or_default().starts_with('.'),
+                _ => false,
+            });
+            if hidden {
+                return None;
+            }
+            let relative_path = get_relative_path(base_path, path).ok()?;
+            Some((label.clone(), relative_path))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::job::JobKind;
+    use crate::testing::{self, memory_pool, write_model, TempDir, LABEL};
+
+    /// Id and is_checked of item
+    async fn item(pool: &SqlitePool, path: &str) -> Option<(i64, bool)> {
+        sqlx::query_as(r#"SELECT id, is_checked FROM item WHERE path = ? AND base_label = ?"#)
+            .bind(path)
+            .bind(LABEL)
+            .fetch_optional(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn file_events() {
+        let dir = TempDir::new();
+        let config = testing::collection(&dir, &["a", "b"]);
+        let pool = memory_pool().await;
+        testing::scan(&config, &pool).await;
+        let (b, _) = item(&pool, "b.safetensors").await.unwrap();
+
+        write_model(&dir.path().join("c.safetensors"));
+        std::fs::remove_file(dir.path().join("a.safetensors")).unwrap();
+        std::fs::rename(dir.path().join("b.safetensors"), dir.path().join("d.safetensors")).unwrap();
+        let events = ["a", "b", "c", "d"].map(|name| dir.path().join(format!("{}.safetensors", name)));
+        process(&config, &pool, &Providers::new(&config, &pool), events.to_vec()).await;
+
+        assert_eq!(
+            item(&pool, "a.safetensors").await.map(|(_, checked)| checked),
+            Some(false)
+        );
+        assert!(item(&pool, "b.safetensors").await.is_none());
+        assert!(item(&pool, "c.safetensors").await.unwrap().1);
+        assert_eq!(item(&pool, "d.safetensors").await, Some((b, true)));
+    }
+
+    #[tokio::test]
+    async fn rename_directory() {
+        let dir = TempDir::new();
+        let config = testing::collection(&dir, &[]);
+        write_model(&dir.path().join("old/a.safetensors"));
+        write_model(&dir.path().join("old/sub/b.safetensors"));
+        let pool = memory_pool().await;
+        testing::scan(&config, &pool).await;
+        let (a, _) = item(&pool, "old/a.safetensors").await.unwrap();
+        let (b, _) = item(&pool, "old/sub/b.safetensors").await.unwrap();
+
+        std::fs::rename(dir.path().join("old"), dir.path().join("new")).unwrap();
+        let paths = vec![dir.path().join("old"), dir.path().join("new")];
+        process(&config, &pool, &Providers::new(&config, &pool), paths).await;
+
+        assert_eq!(item(&pool, "new/a.safetensors").await, Some((a, true)));
+        assert_eq!(item(&pool, "new/sub/b.safetensors").await, Some((b, true)));
+        assert!(item(&pool, "old/a.safetensors").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn move_directory_in_and_out() {
+        let outside = TempDir::new();
+        write_model(&outside.path().join("in/a.safetensors"));
+        let dir = TempDir::new();
+        let config = testing::collection(&dir, &[]);
+        write_model(&dir.path().join("out/b.safetensors"));
+        let pool = memory_pool().await;
+        testing::scan(&config, &pool).await;
+
+        std::fs::rename(outside.path().join("in"), dir.path().join("in")).unwrap();
+        std::fs::rename(dir.path().join("out"), outside.path().join("out")).unwrap();
+        let paths = vec![dir.path().join("in"), dir.path().join("out")];
+        process(&config, &pool, &Providers::new(&config, &pool), paths).await;
+
+        assert!(item(&pool, "in/a.safetensors").await.unwrap().1);
+        assert!(!item(&pool, "out/b.safetensors").await.unwrap().1);
+    }
+
+    #[tokio::test]
+    async fn changes_wait_for_scan() {
+        let dir = TempDir::new();
+        let config = Config {
+            watcher: crate::config::WatcherConfig {
+                enabled: true,
+                debounce_ms: 10,
+            },
+            ..testing::collection(&dir, &[])
+        };
+        let pool = memory_pool().await;
+        let jobs = Arc::new(JobManager::default());
+        let (tx, rx) = mpsc::unbounded_channel();
+        let providers = Arc::new(Providers::new(&config, &pool));
+        tokio::spawn(debounce(Arc::new(config), pool.clone(), providers, jobs.clone(), rx));
+
+        let scan = jobs.start(JobKind::Scan).unwrap();
+        let path = dir.path().join("a.safetensors");
+        write_model(&path);
+        tx.send(path).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(item(&pool, "a.safetensors").await.is_none());
+
+        scan.finish(&Ok(()));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(item(&pool, "a.safetensors").await.unwrap().1);
+    }
+}